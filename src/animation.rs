@@ -91,6 +91,29 @@ impl AnimationSM {
         &self.animations[self.current_anim]
     }
 
+    // The animation playing as of the last update, without moving on from it
+    pub fn playing(&self) -> &Animation {
+        &self.animations[self.current_anim]
+    }
+
+    // How many animations it switches between
+    pub fn len(&self) -> usize {
+        self.animations.len()
+    }
+
+    // Which animation is playing, as an index into the animations it was made with
+    pub fn current_index(&self) -> usize {
+        self.current_anim
+    }
+
+    // Switches straight to animation index, starting it over at now
+    pub fn jump_to(&mut self, index: usize, now: usize) {
+        if index < self.animations.len() {
+            self.current_anim = index;
+            self.animations[index].start_time = now;
+        }
+    }
+
     pub fn input(&mut self, input: &str, now: usize) {
         for (src, dest, read) in self.transitions.iter() {
             if *src == self.current_anim && *read == input {
@@ -111,7 +134,7 @@ impl AnimationSM {
             let mut transition_found = false;
 
             for (src, dest, read) in self.transitions.iter() {
-                if *src == self.current_anim && *read == "" {
+                if *src == self.current_anim && read.is_empty() {
                     self.current_anim = *dest;
                     transition_found = true;
                     break;
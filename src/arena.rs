@@ -0,0 +1,240 @@
+use std::ops::{Index, IndexMut};
+
+/*
+A Vec whose elements keep their ids when others are removed. Contacts refer to
+mobiles and terrains by EntityId rather than by Vec index, so removing one
+entity can't make a contact point at a different one.
+
+Removed slots are reused, but each reuse bumps the slot's generation, so an id
+handed out before the removal no longer resolves.
+*/
+
+// Ordered by slot, the same order Arena iterates in
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+pub struct EntityId {
+    index: usize,
+    gen: u32,
+}
+
+struct Slot<T> {
+    gen: u32,
+    value: Option<T>,
+}
+
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    // Indices of empty slots, reused before growing
+    free: Vec<usize>,
+    len: usize,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+            len: 0,
+        }
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, value: T) -> EntityId {
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.value = Some(value);
+                EntityId {
+                    index,
+                    gen: slot.gen,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    gen: 0,
+                    value: Some(value),
+                });
+                EntityId {
+                    index: self.slots.len() - 1,
+                    gen: 0,
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn remove(&mut self, id: EntityId) -> Option<T> {
+        self.get(id)?;
+        Some(self.remove_at(id.index))
+    }
+
+    // Empties an occupied slot and retires its generation
+    fn remove_at(&mut self, index: usize) -> T {
+        let slot = &mut self.slots[index];
+        slot.gen += 1;
+        self.free.push(index);
+        self.len -= 1;
+        slot.value.take().unwrap()
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&T> {
+        match self.slots.get(id.index) {
+            Some(slot) if slot.gen == id.gen => slot.value.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        match self.slots.get_mut(id.index) {
+            Some(slot) if slot.gen == id.gen => slot.value.as_mut(),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The live elements with their ids, in slot order
+    pub fn entries(&self) -> impl Iterator<Item = (EntityId, &T)> + Clone {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let id = EntityId {
+                index,
+                gen: slot.gen,
+            };
+            slot.value.as_ref().map(|value| (id, value))
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> + Clone {
+        self.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().filter_map(|slot| slot.value.as_mut())
+    }
+
+    // How many slots there are, and the empty ones, reused last first
+    pub fn layout(&self) -> (usize, &[usize]) {
+        (self.slots.len(), &self.free)
+    }
+
+    /**
+     * Spreads the elements back out, in order, over slots slots with the ones
+     * in free left empty, so the arena is laid out the way layout described
+     * it. Returns None and leaves the arena alone if the elements don't fit.
+     */
+    pub fn relayout(&mut self, slots: usize, free: Vec<usize>) -> Option<()> {
+        let mut empty = vec![false; slots];
+        for &index in free.iter() {
+            if std::mem::replace(empty.get_mut(index)?, true) {
+                return None;
+            }
+        }
+        if slots - free.len() != self.len {
+            return None;
+        }
+        let mut values = std::mem::take(&mut self.slots)
+            .into_iter()
+            .filter_map(|slot| slot.value);
+        self.slots = empty
+            .into_iter()
+            .map(|empty| Slot {
+                gen: 0,
+                value: if empty { None } else { values.next() },
+            })
+            .collect();
+        self.free = free;
+        Some(())
+    }
+
+    // Removes every element f returns false for
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        for index in 0..self.slots.len() {
+            if matches!(&self.slots[index].value, Some(value) if !f(value)) {
+                self.remove_at(index);
+            }
+        }
+    }
+}
+
+impl<T> Index<EntityId> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: EntityId) -> &T {
+        self.get(id).expect("stale entity id")
+    }
+}
+
+impl<T> IndexMut<EntityId> for Arena<T> {
+    fn index_mut(&mut self, id: EntityId) -> &mut T {
+        self.get_mut(id).expect("stale entity id")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_id_misses_the_slots_new_occupant() {
+        let mut arena = Arena::new();
+        let a = arena.insert("a");
+        let b = arena.insert("b");
+        assert_eq!(arena.remove(a), Some("a"));
+        assert!(arena.get(a).is_none());
+        assert_eq!(arena.remove(a), None);
+        assert_eq!(arena.len(), 1);
+
+        // c takes over a's slot, but a's id doesn't find it there
+        let c = arena.insert("c");
+        assert_eq!(c.index, a.index);
+        assert!(!arena.contains(a));
+        assert_eq!(arena.get(c), Some(&"c"));
+        assert_eq!(arena[b], "b");
+
+        arena.remove(b);
+        arena.remove(c);
+        assert!(arena.is_empty());
+    }
+
+    #[test]
+    fn relayout_puts_the_holes_back() {
+        let mut arena = Arena::new();
+        let ids: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|v| arena.insert(*v))
+            .collect();
+        arena.remove(ids[2]);
+        arena.remove(ids[0]);
+        let (slots, free) = arena.layout();
+        let free = free.to_vec();
+
+        let mut copy = Arena::new();
+        copy.insert("b");
+        copy.insert("d");
+        assert_eq!(copy.relayout(slots, vec![0]), None);
+        assert_eq!(copy.relayout(slots, free.clone()), Some(()));
+        assert_eq!(copy.layout(), (slots, &free[..]));
+        assert_eq!(
+            copy.iter().collect::<Vec<_>>(),
+            arena.iter().collect::<Vec<_>>()
+        );
+        // The next insert fills the same slot in both
+        assert_eq!(copy.insert("e").index, arena.insert("e").index);
+    }
+}
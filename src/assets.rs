@@ -1,67 +1,106 @@
 use rand::Rng;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use crate::animation::*;
+use crate::atlas::Atlas;
+use crate::axis::ScrollAxis;
 use crate::collision::*;
 use crate::entity::*;
 use crate::screen::*;
 use crate::sprite::*;
 use crate::texture::*;
 use crate::types::*;
+use crate::weapon::Weapon;
 
-pub fn player_anim(sprite_sheet: &Rc<Texture>, frame_count: usize) -> Sprite {
+/*
+Factories for everything the game puts on screen. Pictures are looked up by
+name in the sprite atlas (content/sprites.csv) or the tile atlas
+(content/tiles.csv).
+*/
+
+/**
+ * Textures loaded so far, by path, so asking for the same file twice shares
+ * one copy instead of reading it again.
+ */
+#[derive(Default)]
+pub struct Assets {
+    textures: HashMap<PathBuf, Rc<Texture>>,
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_texture(&mut self, path: &Path) -> Result<Rc<Texture>, image::ImageError> {
+        if let Some(texture) = self.textures.get(path) {
+            return Ok(Rc::clone(texture));
+        }
+        let texture = Rc::new(Texture::load(path)?);
+        self.textures
+            .insert(path.to_path_buf(), Rc::clone(&texture));
+        Ok(texture)
+    }
+}
+
+// Every name the game looks up in the sprite atlas and the tile atlas
+pub const SPRITE_NAMES: [&str; 18] = [
+    "player",
+    "player_dying_0",
+    "player_dying_1",
+    "player_dying_2",
+    "player_dying_3",
+    "player_dying_4",
+    "muzzle_flash_0",
+    "muzzle_flash_1",
+    "enemy_green",
+    "enemy_red",
+    "enemy_orange",
+    "enemy_blue",
+    "boss",
+    "heal",
+    "shield",
+    "weapon_spread",
+    "weapon_rapid",
+    "weapon_homing",
+];
+pub const TILE_NAMES: [&str; 7] = [
+    "boulder",
+    "rock",
+    "rock_cracked_1",
+    "rock_cracked_2",
+    "rock_cracked_3",
+    "rock_patch",
+    "water",
+];
+
+// A sprite showing the named region and nothing else
+fn still(atlas: &Atlas, name: &str, frame_count: usize, pos: Vec2i) -> Sprite {
+    let region = atlas
+        .region(name)
+        .unwrap_or_else(|| panic!("Atlas has no region called {:?}", name));
+    Sprite::from_region(&region, frame_count, pos)
+}
+
+pub fn player_anim(sprite_sheet: &Atlas, frame_count: usize) -> Sprite {
+    let dying = (0..5)
+        .map(|i| sprite_sheet.rect(&format!("player_dying_{}", i)))
+        .collect();
     Sprite::new(
-        &sprite_sheet,
+        sprite_sheet.texture(),
         AnimationSM::new(
             vec![
                 Animation::new(
-                    vec![Rect {
-                        x: 502,
-                        y: 991,
-                        w: 36,
-                        h: 25,
-                    }],
+                    vec![sprite_sheet.rect("player")],
                     vec![60],
                     frame_count,
                     true,
                 ),
-                Animation::new(
-                    vec![
-                        Rect {
-                            x: 865,
-                            y: 974,
-                            w: 36,
-                            h: 25,
-                        },
-                        Rect {
-                            x: 865,
-                            y: 999,
-                            w: 36,
-                            h: 25,
-                        },
-                        Rect {
-                            x: 901,
-                            y: 999,
-                            w: 36,
-                            h: 25,
-                        },
-                        Rect {
-                            x: 937,
-                            y: 999,
-                            w: 36,
-                            h: 25,
-                        },
-                        Rect {
-                            x: 973,
-                            y: 999,
-                            w: 36,
-                            h: 25,
-                        },
-                    ],
-                    vec![20, 20, 20, 20, 2000],
-                    frame_count,
-                    true,
-                ),
+                Animation::new(dying, vec![20, 20, 20, 20, 2000], frame_count, true),
             ],
             vec![(0, 1, "die".to_string())],
             0,
@@ -70,123 +109,203 @@ pub fn player_anim(sprite_sheet: &Rc<Texture>, frame_count: usize) -> Sprite {
     )
 }
 
-pub fn enemy_entity(sprite_sheet: &Rc<Texture>, frame_count: usize, pos: Vec2i) -> Entity<Mobile> {
-    let sprite_rects = vec![
-        Rect {
-            x: 535,
-            y: 150,
-            w: 32,
-            h: 25,
-        },
-        Rect {
-            x: 775,
-            y: 301,
-            w: 32,
-            h: 25,
-        },
-        Rect {
-            x: 777,
-            y: 327,
-            w: 32,
-            h: 25,
-        },
-        Rect {
-            x: 482,
-            y: 358,
-            w: 32,
-            h: 25,
-        },
+// A burst of light that plays through once, for when the player fires
+pub fn muzzle_flash(sprite_sheet: &Atlas, frame_count: usize, pos: Vec2i) -> Sprite {
+    let frames = vec![
+        sprite_sheet.rect("muzzle_flash_0"),
+        sprite_sheet.rect("muzzle_flash_1"),
     ];
+    Sprite::new(
+        sprite_sheet.texture(),
+        AnimationSM::new(
+            vec![Animation::new(frames, vec![3, 3], frame_count, false)],
+            vec![],
+            0,
+        ),
+        pos,
+    )
+    .with_z(Z_EFFECTS)
+}
+
+// The looks an ordinary enemy can have, as sprite atlas names
+pub const ENEMY_LOOKS: [&str; 4] = ["enemy_green", "enemy_red", "enemy_orange", "enemy_blue"];
 
-    let mut rng = rand::thread_rng();
-    let sprite_i = rng.gen_range(0..sprite_rects.len());
+// An enemy with a random look from ENEMY_LOOKS
+pub fn enemy_entity<R: Rng>(
+    sprite_sheet: &Atlas,
+    frame_count: usize,
+    pos: Vec2i,
+    rng: &mut R,
+) -> Entity<Mobile> {
+    let look = rng.gen_range(0..ENEMY_LOOKS.len());
+    enemy_entity_with_look(sprite_sheet, frame_count, pos, look)
+}
 
+pub fn enemy_entity_with_look(
+    sprite_sheet: &Atlas,
+    frame_count: usize,
+    pos: Vec2i,
+    look: usize,
+) -> Entity<Mobile> {
     Entity::new(
-        Sprite::new(
-            &sprite_sheet,
-            AnimationSM::new(
-                vec![Animation::new(
-                    vec![sprite_rects[sprite_i]],
-                    vec![60],
-                    frame_count,
-                    true,
-                )],
-                vec![],
-                0,
-            ),
-            pos,
+        still(sprite_sheet, ENEMY_LOOKS[look], frame_count, pos),
+        pos,
+        Mobile {
+            // Start the fire cooldown from when the enemy shows up
+            last_fired: frame_count,
+            ..Mobile::enemy(
+                Rect {
+                    x: pos.0,
+                    y: pos.1,
+                    w: 32,
+                    h: 25,
+                },
+                0.0,
+                3.0,
+                20,
+            )
+        },
+    )
+}
+
+pub fn boss_entity(sprite_sheet: &Atlas, frame_count: usize, pos: Vec2i) -> Entity<Mobile> {
+    Entity::new(
+        still(sprite_sheet, "boss", frame_count, pos),
+        pos,
+        Mobile::boss(
+            Rect {
+                x: pos.0,
+                y: pos.1,
+                w: 93,
+                h: 84,
+            },
+            400,
         ),
+    )
+}
+
+// How much hp a heal pickup gives back, up to the player's max_hp
+pub const HEAL_AMOUNT: usize = 25;
+
+pub fn heal_entity(sprite_sheet: &Atlas, frame_count: usize, pos: Vec2i) -> Entity<PowerUp> {
+    Entity::new(
+        still(sprite_sheet, "heal", frame_count, pos).with_z(Z_PICKUPS),
         pos,
-        Mobile::enemy(
+        PowerUp::new(
             Rect {
                 x: pos.0,
                 y: pos.1,
-                w: 32,
-                h: 25,
+                w: 22,
+                h: 21,
+            },
+            PowerUpKind::Heal(HEAL_AMOUNT),
+        ),
+    )
+}
+
+pub fn shield_entity(sprite_sheet: &Atlas, frame_count: usize, pos: Vec2i) -> Entity<PowerUp> {
+    Entity::new(
+        still(sprite_sheet, "shield", frame_count, pos).with_z(Z_PICKUPS),
+        pos,
+        PowerUp::new(
+            Rect {
+                x: pos.0,
+                y: pos.1,
+                w: 34,
+                h: 33,
             },
-            0.0,
-            3.0,
-            20,
+            PowerUpKind::Shield,
         ),
     )
 }
 
-pub fn walls_vec(screen_w: u16, screen_h: u16) -> Vec<Wall> {
-    vec![
-        Wall::new(Rect {
-            x: -64,
-            y: -64,
-            w: 64,
-            h: screen_h + 128,
-        }),
-        Wall::new(Rect {
-            x: screen_w as i32,
-            y: -64,
-            w: 64,
-            h: screen_h + 128,
-        }),
-        /*
-        Wall::new(Rect {
-            x: 0,
-            y: -64,
-            w: screen_w,
-            h: 64,
-        }),
-        */
-        Wall::new(Rect {
-            x: 0,
-            y: screen_h as i32,
-            w: screen_w,
-            h: 64,
-        }),
-    ]
-}
-
-pub fn boulder_entity(
-    sprite_sheet: &Rc<Texture>,
+pub fn weapon_entity(
+    sprite_sheet: &Atlas,
     frame_count: usize,
     pos: Vec2i,
-) -> Entity<Terrain> {
+    weapon: Weapon,
+) -> Entity<PowerUp> {
+    let name = match weapon {
+        Weapon::Rapid => "weapon_rapid",
+        Weapon::Homing => "weapon_homing",
+        _ => "weapon_spread",
+    };
     Entity::new(
-        Sprite::new(
-            &sprite_sheet,
-            AnimationSM::new(
-                vec![Animation::new(
-                    vec![Rect {
-                        x: 48,
-                        y: 320,
-                        w: 32,
-                        h: 32,
-                    }],
-                    vec![60],
-                    frame_count,
-                    true,
-                )],
-                vec![],
-                0,
-            ),
-            pos,
+        still(sprite_sheet, name, frame_count, pos).with_z(Z_PICKUPS),
+        pos,
+        PowerUp::new(
+            Rect {
+                x: pos.0,
+                y: pos.1,
+                w: 34,
+                h: 33,
+            },
+            PowerUpKind::Weapon(weapon),
         ),
+    )
+}
+
+/*
+Walls along both sides of the screen and across the trailing edge, just out of
+view, so nothing can leave except off the leading edge. Scrolling sideways the
+sides are the top and bottom and the trailing edge is on the left.
+*/
+pub fn walls_vec(screen_w: u16, screen_h: u16, axis: ScrollAxis) -> Vec<Wall> {
+    match axis {
+        ScrollAxis::Vertical => vec![
+            Wall::new(Rect {
+                x: -64,
+                y: -64,
+                w: 64,
+                h: screen_h + 128,
+            }),
+            Wall::new(Rect {
+                x: screen_w as i32,
+                y: -64,
+                w: 64,
+                h: screen_h + 128,
+            }),
+            /*
+            Wall::new(Rect {
+                x: 0,
+                y: -64,
+                w: screen_w,
+                h: 64,
+            }),
+            */
+            Wall::new(Rect {
+                x: 0,
+                y: screen_h as i32,
+                w: screen_w,
+                h: 64,
+            }),
+        ],
+        ScrollAxis::Horizontal => vec![
+            Wall::new(Rect {
+                x: -64,
+                y: -64,
+                w: screen_w + 128,
+                h: 64,
+            }),
+            Wall::new(Rect {
+                x: -64,
+                y: screen_h as i32,
+                w: screen_w + 128,
+                h: 64,
+            }),
+            Wall::new(Rect {
+                x: -64,
+                y: 0,
+                w: 64,
+                h: screen_h,
+            }),
+        ],
+    }
+}
+
+pub fn boulder_entity(tile_sheet: &Atlas, frame_count: usize, pos: Vec2i) -> Entity<Terrain> {
+    Entity::new(
+        still(tile_sheet, "boulder", frame_count, pos).with_z(Z_TERRAIN),
         pos,
         Terrain::new(
             Rect {
@@ -202,57 +321,19 @@ pub fn boulder_entity(
     )
 }
 
-pub fn rock_entity(sprite_sheet: &Rc<Texture>, frame_count: usize, pos: Vec2i) -> Entity<Terrain> {
+// Rocks crack a little more with each of their animations
+const ROCK_LOOKS: [&str; 4] = ["rock", "rock_cracked_1", "rock_cracked_2", "rock_cracked_3"];
+
+pub fn rock_entity(tile_sheet: &Atlas, frame_count: usize, pos: Vec2i) -> Entity<Terrain> {
+    let looks = ROCK_LOOKS
+        .iter()
+        .map(|name| Animation::new(vec![tile_sheet.rect(name)], vec![60], frame_count, true))
+        .collect();
     Entity::new(
         Sprite::new(
-            &sprite_sheet,
+            tile_sheet.texture(),
             AnimationSM::new(
-                vec![
-                    Animation::new(
-                        vec![Rect {
-                            x: 368,
-                            y: 128,
-                            w: 16,
-                            h: 16,
-                        }],
-                        vec![60],
-                        frame_count,
-                        true,
-                    ),
-                    Animation::new(
-                        vec![Rect {
-                            x: 368,
-                            y: 144,
-                            w: 16,
-                            h: 16,
-                        }],
-                        vec![60],
-                        frame_count,
-                        true,
-                    ),
-                    Animation::new(
-                        vec![Rect {
-                            x: 368,
-                            y: 160,
-                            w: 16,
-                            h: 16,
-                        }],
-                        vec![60],
-                        frame_count,
-                        true,
-                    ),
-                    Animation::new(
-                        vec![Rect {
-                            x: 368,
-                            y: 176,
-                            w: 16,
-                            h: 16,
-                        }],
-                        vec![60],
-                        frame_count,
-                        true,
-                    ),
-                ],
+                looks,
                 vec![
                     (0, 1, String::from("hit")),
                     (1, 2, String::from("hit")),
@@ -261,7 +342,8 @@ pub fn rock_entity(sprite_sheet: &Rc<Texture>, frame_count: usize, pos: Vec2i) -
                 0,
             ),
             pos,
-        ),
+        )
+        .with_z(Z_TERRAIN),
         pos,
         Terrain::new(
             Rect {
@@ -300,27 +382,625 @@ pub fn get_font_letter(c: char) -> Option<Rect> {
             h: 18,
         })
     } else {
-        None
+        // Punctuation follows the digits on the same row
+        let i = match c {
+            '.' => 10,
+            ':' => 11,
+            ',' => 12,
+            ';' => 13,
+            '*' => 15,
+            '!' => 16,
+            '?' => 17,
+            '#' => 21,
+            '$' => 22,
+            '%' => 24,
+            '&' => 26,
+            '-' => 27,
+            '+' => 28,
+            '@' => 29,
+            _ => return None,
+        };
+        Some(Rect {
+            x: i * 18 + 9,
+            y: 41,
+            w: 18,
+            h: 18,
+        })
+    }
+}
+
+/**
+ * A bitmap font. The monospace one is laid out like monospace_font.png, with
+ * every glyph advance pixels along from the last. A proportional one is read
+ * from a descriptor giving each glyph's place on the sheet and its own
+ * advance, plus kerning between pairs, one per line:
+ *
+ *     # char, x, y, w, h, advance
+ *     i 9 5 6 18 7
+ *     m 27 5 18 18 19
+ *     space 0 0 0 0 8
+ *     # kern, left, right, adjustment
+ *     kern A V -3
+ *
+ * advance: horizontal distance in pixels between glyphs, and for a
+ * proportional font how far characters it hasn't got move along
+ * line_height: how tall a line of text is
+ * line_spacing: extra gap left between lines
+ */
+pub struct Font {
+    pub sheet: Rc<Texture>,
+    pub advance: u32,
+    pub line_height: u32,
+    pub line_spacing: u32,
+    // Empty for the monospace sheet, which get_font_letter describes
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), i32>,
+}
+
+#[derive(Clone, Copy)]
+struct Glyph {
+    rect: Rect,
+    advance: u32,
+}
+
+/// Ways loading a font descriptor can fail
+#[derive(Debug)]
+pub enum FontError {
+    Io(std::io::Error),
+    /// A line that's neither a glyph nor a kerning pair, at this (1-based) line number
+    BadLine(usize, String),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::Io(e) => write!(f, "couldn't read font: {}", e),
+            FontError::BadLine(line, text) => {
+                write!(
+                    f,
+                    "line {}: expected a glyph or kerning pair, got {:?}",
+                    line, text
+                )
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for FontError {
+    fn from(e: std::io::Error) -> Self {
+        FontError::Io(e)
+    }
+}
+
+impl Font {
+    pub fn monospace(sheet: &Rc<Texture>) -> Self {
+        Self {
+            sheet: Rc::clone(sheet),
+            advance: 18,
+            line_height: 18,
+            line_spacing: 4,
+            glyphs: HashMap::new(),
+            kerning: HashMap::new(),
+        }
+    }
+
+    pub fn proportional(path: &Path, sheet: &Rc<Texture>) -> Result<Self, FontError> {
+        Self::proportional_from_str(&fs::read_to_string(path)?, sheet)
+    }
+
+    pub fn proportional_from_str(text: &str, sheet: &Rc<Texture>) -> Result<Self, FontError> {
+        let mut font = Self::monospace(sheet);
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = || FontError::BadLine(i + 1, line.to_string());
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["kern", left, right, by] => {
+                    let pair = (
+                        single_char(left).ok_or_else(bad)?,
+                        single_char(right).ok_or_else(bad)?,
+                    );
+                    font.kerning.insert(pair, by.parse().map_err(|_| bad())?);
+                }
+                [c, numbers @ ..] if numbers.len() == 5 => {
+                    let c = if *c == "space" {
+                        Some(' ')
+                    } else {
+                        single_char(c)
+                    };
+                    let n: Vec<u32> = numbers
+                        .iter()
+                        .map(|n| n.parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| bad())?;
+                    let rect = Rect {
+                        x: n[0] as i32,
+                        y: n[1] as i32,
+                        w: n[2] as u16,
+                        h: n[3] as u16,
+                    };
+                    if !sheet.valid_frame(rect) {
+                        return Err(bad());
+                    }
+                    let glyph = Glyph {
+                        rect,
+                        advance: n[4],
+                    };
+                    font.glyphs.insert(c.ok_or_else(bad)?, glyph);
+                }
+                _ => return Err(bad()),
+            }
+        }
+        // Anything the descriptor leaves out is as wide as a space
+        if let Some(space) = font.glyphs.get(&' ') {
+            font.advance = space.advance;
+        }
+        if let Some(tallest) = font.glyphs.values().map(|g| g.rect.h as u32).max() {
+            font.line_height = tallest;
+        }
+        Ok(font)
+    }
+
+    // Where c is on the sheet, if anywhere, and how far it moves the pen along
+    fn glyph(&self, c: char) -> (Option<Rect>, u32) {
+        if self.glyphs.is_empty() {
+            return (get_font_letter(c), self.advance);
+        }
+        match self.glyphs.get(&c) {
+            Some(g) => (Some(g.rect).filter(|r| r.w > 0), g.advance),
+            None => (None, self.advance),
+        }
+    }
+
+    // How far down each line of text is from the one before
+    fn line_pitch(&self) -> i32 {
+        (self.line_height + self.line_spacing) as i32
+    }
+
+    // Calls place with each character's x offset from the start of string and
+    // its picture, and returns the width of the whole thing
+    fn layout(&self, string: &str, mut place: impl FnMut(i32, Option<Rect>)) -> u32 {
+        let mut pen = 0;
+        let mut prev = None;
+        for c in string.chars() {
+            if let Some(p) = prev {
+                pen += self.kerning.get(&(p, c)).copied().unwrap_or(0);
+            }
+            let (rect, advance) = self.glyph(c);
+            place(pen, rect);
+            pen += advance as i32;
+            prev = Some(c);
+        }
+        pen.max(0) as u32
+    }
+}
+
+fn single_char(word: &str) -> Option<char> {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+// Width in pixels that draw_string would cover for this string: its widest line
+pub fn measure_string(string: &str, font: &Font) -> u32 {
+    string
+        .split('\n')
+        .map(|line| font.layout(line, |_, _| {}))
+        .max()
+        .unwrap_or(0)
+}
+
+/*
+Draws string a line at a time, breaking at each \n, with the first line's top
+at y and each line after a line height (and spacing) further down. line_x
+gives where a line starts given how wide it is.
+*/
+fn draw_lines(
+    string: &str,
+    screen: &mut Screen,
+    font: &Font,
+    y: i32,
+    scroll: Vec2i,
+    color: Rgba,
+    line_x: impl Fn(u32) -> i32,
+) {
+    for (i, line) in string.split('\n').enumerate() {
+        let y = scroll.1 + y + i as i32 * font.line_pitch();
+        let x = scroll.0 + line_x(measure_string(line, font));
+        font.layout(line, |dx, rect| {
+            if let Some(rect) = rect {
+                screen.bitblt_colored(&font.sheet, rect, Vec2i(x + dx, y), color);
+            }
+        });
     }
 }
 
 pub fn draw_string(
     string: &str,
     screen: &mut Screen,
-    font_sheet: &Rc<Texture>,
+    font: &Font,
     pos: Vec2i,
     scroll: Vec2i,
+    color: Rgba,
 ) {
-    for (i, c) in string.chars().enumerate() {
-        match get_font_letter(c) {
-            None => {}
-            Some(rect) => {
-                screen.bitblt(
-                    font_sheet,
-                    rect,
-                    Vec2i(pos.0 + 18 * i as i32, scroll.1 + pos.1),
-                );
-            }
+    draw_lines(string, screen, font, pos.1, scroll, color, |_| pos.0);
+}
+
+// Each line is centered on center_x on its own
+pub fn draw_string_centered(
+    string: &str,
+    screen: &mut Screen,
+    font: &Font,
+    center_x: i32,
+    y: i32,
+    scroll: Vec2i,
+    color: Rgba,
+) {
+    draw_lines(string, screen, font, y, scroll, color, |w| {
+        center_x - w as i32 / 2
+    });
+}
+
+pub fn draw_string_right(
+    string: &str,
+    screen: &mut Screen,
+    font: &Font,
+    right_x: i32,
+    y: i32,
+    scroll: Vec2i,
+    color: Rgba,
+) {
+    draw_lines(string, screen, font, y, scroll, color, |w| {
+        right_x - w as i32
+    });
+}
+
+/**
+ * Appends n to out, with a , between each group of three digits if thousands,
+ * padded on the left with pad to at least width characters. Numbers wider than
+ * width are written in full rather than cut.
+ */
+pub fn push_number(out: &mut String, n: usize, width: usize, pad: char, thousands: bool) {
+    // Digits come out least significant first, so they're collected backwards.
+    // 20 digits and 6 commas are as long as a usize gets.
+    let mut buf = [0u8; 26];
+    let mut len = 0;
+    let mut rest = n;
+    loop {
+        if thousands && len % 4 == 3 {
+            buf[len] = b',';
+            len += 1;
         }
+        buf[len] = b'0' + (rest % 10) as u8;
+        len += 1;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    for _ in len..width {
+        out.push(pad);
+    }
+    out.extend(buf[..len].iter().rev().map(|&b| b as char));
+}
+
+// A score zero-padded to width digits, so the HUD doesn't shift as it grows
+pub fn format_score(score: usize, width: usize) -> String {
+    let mut out = String::with_capacity(width);
+    push_number(&mut out, score, width, '0', false);
+    out
+}
+
+// Space between a health bar's label and the bar itself
+const HEALTH_BAR_GAP: i32 = 14;
+
+/**
+ * Draws a health bar filling bar (world coordinates) in proportion to
+ * current / max, green for what's left and red for what's gone, with label
+ * written just to its left. current is capped at max, and a max of 0 draws an
+ * empty bar.
+ */
+pub fn draw_health_bar(
+    screen: &mut Screen,
+    font: &Font,
+    bar: Rect,
+    current: usize,
+    max: usize,
+    label: &str,
+) {
+    draw_string_right(
+        label,
+        screen,
+        font,
+        bar.x - HEALTH_BAR_GAP,
+        bar.y,
+        Vec2i(0, 0),
+        Rgba::BLACK,
+    );
+
+    let filled = (bar.w as usize * current.min(max))
+        .checked_div(max)
+        .unwrap_or(0) as u16;
+    screen.rect(Rect { w: filled, ..bar }, Rgba(0, 128, 0, 255));
+    screen.rect(
+        Rect {
+            x: bar.x + filled as i32,
+            w: bar.w - filled,
+            ..bar
+        },
+        Rgba(128, 0, 0, 255),
+    );
+
+    let (x0, y0) = (bar.x, bar.y);
+    let (x1, y1) = (bar.x + bar.w as i32, bar.y + bar.h as i32);
+    screen.line(Vec2i(x0, y0), Vec2i(x1, y0), Rgba::BLACK);
+    screen.line(Vec2i(x1, y0), Vec2i(x1, y1), Rgba::BLACK);
+    screen.line(Vec2i(x0, y0), Vec2i(x0, y1), Rgba::BLACK);
+    screen.line(Vec2i(x0, y1), Vec2i(x1, y1), Rgba::BLACK);
+    screen.line(
+        Vec2i(x0 + filled as i32, y0),
+        Vec2i(x0 + filled as i32, y1),
+        Rgba::BLACK,
+    );
+}
+
+// Height of the boss marker on top of a progress bar
+const BOSS_MARKER_H: u16 = 4;
+
+/**
+ * Draws a progress bar up the screen in bar (world coordinates), filled from
+ * the bottom in proportion to done / total, with a red marker above it for
+ * the boss waiting at the end. A total of 0 draws an empty bar.
+ */
+pub fn draw_progress_bar(screen: &mut Screen, bar: Rect, done: usize, total: usize) {
+    let filled = (bar.h as usize * done.min(total))
+        .checked_div(total)
+        .unwrap_or(0) as u16;
+    screen.rect(bar, Rgba::BLACK.with_alpha(80));
+    screen.rect(
+        Rect {
+            y: bar.y + (bar.h - filled) as i32,
+            h: filled,
+            ..bar
+        },
+        Rgba(0, 128, 0, 255),
+    );
+    screen.rect_outline(bar, Rgba::BLACK);
+    screen.rect(
+        Rect {
+            x: bar.x - 2,
+            y: bar.y - BOSS_MARKER_H as i32,
+            w: bar.w + 4,
+            h: BOSS_MARKER_H,
+        },
+        Rgba(192, 0, 0, 255),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn monospace() -> Font {
+        let sheet = Rc::new(Texture::with_file(Path::new("content/monospace_font.png")));
+        Font::monospace(&sheet)
+    }
+
+    // What draw leaves on a blank 200x40 screen
+    fn render(draw: impl FnOnce(&mut Screen)) -> Vec<u8> {
+        let mut fb = vec![0; 200 * 40 * 4];
+        let mut screen = Screen::wrap(&mut fb, 200, 40, 4, Vec2i(0, 0));
+        draw(&mut screen);
+        fb
+    }
+
+    // The pixel at (x, y) of something render drew
+    fn pixel(fb: &[u8], x: usize, y: usize) -> Rgba {
+        let i = (y * 200 + x) * 4;
+        Rgba(fb[i], fb[i + 1], fb[i + 2], fb[i + 3])
+    }
+
+    #[test]
+    fn the_bundled_atlases_have_every_name_the_game_uses() {
+        let atlases = [
+            (
+                "content/sprites.csv",
+                "content/spaceshooter/Spritesheet/sheet.png",
+                &SPRITE_NAMES[..],
+            ),
+            (
+                "content/tiles.csv",
+                "content/tilesheet.png",
+                &TILE_NAMES[..],
+            ),
+        ];
+        for (descriptor, sheet, names) in atlases {
+            let sheet = Rc::new(Texture::with_file(Path::new(sheet)));
+            let atlas = Atlas::load(Path::new(descriptor), &sheet).unwrap();
+            assert!(atlas.check_names(names).is_ok(), "{}", descriptor);
+        }
+    }
+
+    #[test]
+    fn centered_string_starts_half_its_width_left_of_center() {
+        let font = monospace();
+        let w = measure_string("Score", &font) as i32;
+        assert_eq!(w, 5 * 18);
+        let centered =
+            render(|s| draw_string_centered("Score", s, &font, 100, 10, Vec2i(0, 0), Rgba::BLACK));
+        let placed = render(|s| {
+            draw_string(
+                "Score",
+                s,
+                &font,
+                Vec2i(100 - w / 2, 10),
+                Vec2i(0, 0),
+                Rgba::BLACK,
+            )
+        });
+        assert!(centered.iter().any(|&b| b != 0));
+        assert_eq!(centered, placed);
+    }
+
+    #[test]
+    fn right_aligned_string_ends_at_right_x() {
+        let font = monospace();
+        let w = measure_string("Lives 3", &font) as i32;
+        let right =
+            render(|s| draw_string_right("Lives 3", s, &font, 190, 10, Vec2i(0, 0), Rgba::BLACK));
+        let placed = render(|s| {
+            draw_string(
+                "Lives 3",
+                s,
+                &font,
+                Vec2i(190 - w, 10),
+                Vec2i(0, 0),
+                Rgba::BLACK,
+            )
+        });
+        assert_eq!(right, placed);
+    }
+
+    #[test]
+    fn health_bar_fills_in_proportion() {
+        let font = monospace();
+        let bar = Rect {
+            x: 40,
+            y: 10,
+            w: 100,
+            h: 8,
+        };
+        let green = Rgba(0, 128, 0, 255);
+        let red = Rgba(128, 0, 0, 255);
+        // Filled up to x = 40 + 100 * 30 / 120, where a divider's drawn
+        let fb = render(|s| draw_health_bar(s, &font, bar, 30, 120, "HP"));
+        assert_eq!(pixel(&fb, 41, 14), green);
+        assert_eq!(pixel(&fb, 64, 14), green);
+        assert_eq!(pixel(&fb, 65, 14), Rgba::BLACK);
+        assert_eq!(pixel(&fb, 66, 14), red);
+        assert_eq!(pixel(&fb, 139, 14), red);
+
+        // Overfull is full, and a max of 0 is empty
+        let fb = render(|s| draw_health_bar(s, &font, bar, 200, 120, "HP"));
+        assert_eq!(pixel(&fb, 139, 14), green);
+        let fb = render(|s| draw_health_bar(s, &font, bar, 5, 0, "HP"));
+        assert_eq!(pixel(&fb, 41, 14), red);
+    }
+
+    #[test]
+    fn progress_bar_fills_up_from_the_bottom() {
+        let bar = Rect {
+            x: 50,
+            y: 6,
+            w: 6,
+            h: 32,
+        };
+        let green = Rgba(0, 128, 0, 255);
+        // A quarter done fills the bottom 8 of its 32 rows
+        let fb = render(|s| draw_progress_bar(s, bar, 1, 4));
+        assert_ne!(pixel(&fb, 52, 29), green);
+        assert_eq!(pixel(&fb, 52, 30), green);
+        assert_eq!(pixel(&fb, 52, 36), green);
+        // With the boss marker sat on top
+        assert_eq!(pixel(&fb, 52, 3), Rgba(192, 0, 0, 255));
+
+        // Past the end is full, and nothing to do is empty
+        let fb = render(|s| draw_progress_bar(s, bar, 9, 4));
+        assert_eq!(pixel(&fb, 52, 7), green);
+        let fb = render(|s| draw_progress_bar(s, bar, 0, 0));
+        assert_ne!(pixel(&fb, 52, 36), green);
+    }
+
+    #[test]
+    fn a_texture_asked_for_twice_is_loaded_once() {
+        let mut assets = Assets::new();
+        let path = Path::new("content/tilesheet.png");
+        let first = assets.get_texture(path).unwrap();
+        let second = assets.get_texture(path).unwrap();
+        assert!(Rc::ptr_eq(&first, &second));
+        // A missing file is an error for the caller, not a panic
+        assert!(assets
+            .get_texture(Path::new("content/missing.png"))
+            .is_err());
+    }
+
+    #[test]
+    fn the_bundled_font_trims_narrow_letters() {
+        let sheet = Rc::new(Texture::with_file(Path::new("content/monospace_font.png")));
+        let font = Font::proportional(Path::new("content/proportional_font.txt"), &sheet).unwrap();
+        assert!(measure_string("i", &font) < measure_string("m", &font));
+        // Digits all line up, so scores don't jiggle as they count
+        let digits: Vec<u32> = ('0'..='9')
+            .map(|d| measure_string(&d.to_string(), &font))
+            .collect();
+        assert!(digits.iter().all(|&w| w == digits[0]));
+    }
+
+    #[test]
+    fn proportional_i_is_narrower_than_m() {
+        let sheet = Rc::new(Texture::new(image::RgbaImage::new(64, 32)));
+        let descriptor = "# char, x, y, w, h, advance\n\
+                          i 0 0 4 16 5\n\
+                          m 4 0 14 16 15\n\
+                          A 18 0 12 16 13\n\
+                          V 30 0 12 16 13\n\
+                          space 0 0 0 0 8\n\
+                          kern A V -3";
+        let font = Font::proportional_from_str(descriptor, &sheet).unwrap();
+        assert!(measure_string("i", &font) < measure_string("m", &font));
+        assert_eq!(measure_string("mi m", &font), 15 + 5 + 8 + 15);
+        // Kerning only applies to the pair it names, in that order
+        assert_eq!(measure_string("AV", &font), 13 + 13 - 3);
+        assert_eq!(measure_string("VA", &font), 13 + 13);
+        // The monospace font makes no such difference
+        assert_eq!(
+            measure_string("i", &monospace()),
+            measure_string("m", &monospace())
+        );
+    }
+
+    #[test]
+    fn each_line_starts_a_line_height_further_down() {
+        let font = monospace();
+        let pitch = (font.line_height + font.line_spacing) as i32;
+        let both =
+            render(|s| draw_string("A\nB", s, &font, Vec2i(10, 0), Vec2i(0, 0), Rgba::BLACK));
+        let apart = render(|s| {
+            draw_string("A", s, &font, Vec2i(10, 0), Vec2i(0, 0), Rgba::BLACK);
+            draw_string("B", s, &font, Vec2i(10, pitch), Vec2i(0, 0), Rgba::BLACK);
+        });
+        assert!(both.iter().any(|&b| b != 0));
+        assert_eq!(both, apart);
+        // A block is as wide as its widest line
+        assert_eq!(
+            measure_string("A\nBCD", &font),
+            measure_string("BCD", &font)
+        );
+    }
+
+    #[test]
+    fn small_scores_are_zero_padded() {
+        assert_eq!(format_score(42, 6), "000042");
+        assert_eq!(format_score(0, 3), "000");
+        let mut out = String::new();
+        push_number(&mut out, 7, 4, ' ', false);
+        assert_eq!(out, "   7");
+    }
+
+    #[test]
+    fn scores_wider_than_the_field_are_written_in_full() {
+        assert_eq!(format_score(1234567, 4), "1234567");
+        let mut out = String::new();
+        push_number(&mut out, 1234567, 4, ' ', true);
+        assert_eq!(out, "1,234,567");
+        out.clear();
+        push_number(&mut out, 999, 6, '0', true);
+        assert_eq!(out, "000999");
     }
 }
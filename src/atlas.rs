@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::texture::Texture;
+use crate::types::Rect;
+
+/*
+Names for the pictures on a sprite sheet, so assets.rs can ask for "player"
+instead of spelling out where on the sheet it is. An atlas is read from a
+descriptor with one region per line:
+
+    # name, x, y, w, h
+    player, 502, 991, 36, 25
+    boss, 423, 728, 93, 84
+
+Lines starting with # are comments.
+*/
+
+// Part of a texture: one picture on a sheet
+#[derive(Clone)]
+pub struct TextureRegion {
+    pub texture: Rc<Texture>,
+    pub rect: Rect,
+}
+
+/// Ways loading an atlas descriptor can fail
+#[derive(Debug)]
+pub enum AtlasError {
+    Io(std::io::Error),
+    /// A line that isn't "name, x, y, w, h", at this (1-based) line number
+    BadLine(usize, String),
+    /// A region that doesn't fit on the texture
+    OutOfBounds(usize, String),
+    /// Names the game draws with that the descriptor leaves out
+    Missing(Vec<String>),
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtlasError::Io(e) => write!(f, "couldn't read atlas: {}", e),
+            AtlasError::BadLine(line, text) => {
+                write!(
+                    f,
+                    "line {}: expected name, x, y, w, h, got {:?}",
+                    line, text
+                )
+            }
+            AtlasError::OutOfBounds(line, name) => {
+                write!(
+                    f,
+                    "line {}: {:?} is off the edge of the texture",
+                    line, name
+                )
+            }
+            AtlasError::Missing(names) => write!(f, "no regions called {}", names.join(", ")),
+        }
+    }
+}
+
+impl From<std::io::Error> for AtlasError {
+    fn from(e: std::io::Error) -> Self {
+        AtlasError::Io(e)
+    }
+}
+
+pub struct Atlas {
+    texture: Rc<Texture>,
+    regions: HashMap<String, Rect>,
+}
+
+impl Atlas {
+    pub fn load(path: &Path, texture: &Rc<Texture>) -> Result<Self, AtlasError> {
+        Self::from_str(&fs::read_to_string(path)?, texture)
+    }
+
+    pub fn from_str(text: &str, texture: &Rc<Texture>) -> Result<Self, AtlasError> {
+        let mut regions = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, rect) =
+                parse_region(line).ok_or_else(|| AtlasError::BadLine(i + 1, line.to_string()))?;
+            if !texture.valid_frame(rect) {
+                return Err(AtlasError::OutOfBounds(i + 1, name.to_string()));
+            }
+            regions.insert(name.to_string(), rect);
+        }
+        Ok(Self {
+            texture: Rc::clone(texture),
+            regions,
+        })
+    }
+
+    pub fn texture(&self) -> &Rc<Texture> {
+        &self.texture
+    }
+
+    pub fn region(&self, name: &str) -> Option<TextureRegion> {
+        self.regions.get(name).map(|&rect| TextureRegion {
+            texture: Rc::clone(&self.texture),
+            rect,
+        })
+    }
+
+    /// Fails listing whichever of names the atlas has no region for
+    pub fn check_names(&self, names: &[&str]) -> Result<(), AtlasError> {
+        let missing: Vec<String> = names
+            .iter()
+            .filter(|name| !self.regions.contains_key(**name))
+            .map(|name| name.to_string())
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(AtlasError::Missing(missing))
+        }
+    }
+
+    /// Where name is on the texture. The game's own names are checked for
+    /// when it loads its atlases, so a missing one is a bug and panics.
+    pub fn rect(&self, name: &str) -> Rect {
+        match self.regions.get(name) {
+            Some(rect) => *rect,
+            None => panic!("Atlas has no region called {:?}", name),
+        }
+    }
+}
+
+fn parse_region(line: &str) -> Option<(&str, Rect)> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    match fields.as_slice() {
+        [name, x, y, w, h] if !name.is_empty() => Some((
+            name,
+            Rect {
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+                w: w.parse().ok()?,
+                h: h.parse().ok()?,
+            },
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet() -> Rc<Texture> {
+        Rc::new(Texture::new(image::RgbaImage::new(64, 32)))
+    }
+
+    #[test]
+    fn named_regions_resolve_to_their_rects() {
+        let descriptor = "# name, x, y, w, h\n\
+                          player, 0, 0, 36, 25\n\
+                          \n\
+                          rock,  40, 16, 16, 16\n";
+        let atlas = Atlas::from_str(descriptor, &sheet()).unwrap();
+        assert_eq!(
+            atlas.rect("rock"),
+            Rect {
+                x: 40,
+                y: 16,
+                w: 16,
+                h: 16
+            }
+        );
+        let player = atlas.region("player").unwrap();
+        assert_eq!(player.rect.w, 36);
+        assert!(Rc::ptr_eq(&player.texture, atlas.texture()));
+        assert!(atlas.region("boss").is_none());
+    }
+
+    #[test]
+    fn bad_descriptors_say_which_line() {
+        let result = Atlas::from_str("player, 0, 0, 36, 25\nrock, 40, 16", &sheet());
+        assert!(matches!(result, Err(AtlasError::BadLine(2, _))));
+        let result = Atlas::from_str("rock, 56, 0, 16, 16", &sheet());
+        assert!(matches!(result, Err(AtlasError::OutOfBounds(1, name)) if name == "rock"));
+    }
+
+    #[test]
+    fn missing_names_are_all_listed() {
+        let atlas = Atlas::from_str("player, 0, 0, 36, 25", &sheet()).unwrap();
+        assert!(atlas.check_names(&["player"]).is_ok());
+        let result = atlas.check_names(&["boss", "player", "heal"]);
+        assert!(matches!(result, Err(AtlasError::Missing(names)) if names == ["boss", "heal"]));
+    }
+}
@@ -0,0 +1,314 @@
+use crate::collision::GameEvent;
+use crate::game::GameStage;
+
+/*
+Sound effects and music. Which sound goes with what happened in the game, and
+which track with which stage, is plain data and always compiled; actually
+playing them goes through rodio and is only built with the "audio" feature.
+Without it (or with no sound device, or a file that won't load) Audio does
+nothing and the game is silent.
+*/
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Sound {
+    Fire,
+    Explosion,
+    Hurt,
+    Pickup,
+    Bonus,
+}
+
+// Only the rodio side loads samples
+#[cfg_attr(not(feature = "audio"), allow(dead_code))]
+impl Sound {
+    pub const ALL: [Sound; 5] = [
+        Sound::Fire,
+        Sound::Explosion,
+        Sound::Hurt,
+        Sound::Pickup,
+        Sound::Bonus,
+    ];
+
+    // Where the sample for this sound lives
+    pub fn path(self) -> &'static str {
+        match self {
+            Sound::Fire => "content/spaceshooter/Bonus/sfx_laser1.ogg",
+            Sound::Explosion => "content/spaceshooter/Bonus/sfx_zap.ogg",
+            Sound::Hurt => "content/spaceshooter/Bonus/sfx_shieldDown.ogg",
+            Sound::Pickup => "content/spaceshooter/Bonus/sfx_shieldUp.ogg",
+            Sound::Bonus => "content/spaceshooter/Bonus/sfx_twoTone.ogg",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Track {
+    Calm,
+    Tense,
+}
+
+#[cfg_attr(not(feature = "audio"), allow(dead_code))]
+impl Track {
+    // Where the music for this track lives; it loops from the start once over
+    pub fn path(self) -> &'static str {
+        match self {
+            Track::Calm => "content/music/calm.ogg",
+            Track::Tense => "content/music/tense.ogg",
+        }
+    }
+}
+
+// The music for a stage, or None for silence
+pub fn track_for(stage: GameStage) -> Option<Track> {
+    match stage {
+        GameStage::Menu
+        | GameStage::Rocks(_, _)
+        | GameStage::Boulders(_, _)
+        | GameStage::Victory(_) => Some(Track::Calm),
+        GameStage::BossWarning(_) | GameStage::Boss => Some(Track::Tense),
+        GameStage::GameOver(_) => None,
+    }
+}
+
+// The sound a contact event makes, if any; firing isn't a contact, so Game asks for that itself
+pub(crate) fn sound_for(event: &GameEvent) -> Option<Sound> {
+    match event {
+        GameEvent::EnemyDestroyed { .. } | GameEvent::TerrainDestroyed { .. } => {
+            Some(Sound::Explosion)
+        }
+        GameEvent::PlayerDamaged { .. } => Some(Sound::Hurt),
+        GameEvent::PowerUpCollected(_) => Some(Sound::Pickup),
+        GameEvent::TriggerEntered(_) => Some(Sound::Bonus),
+        GameEvent::TileShot(_) => None,
+    }
+}
+
+// Sounds playing at once past this many are dropped, so rapid fire can't pile up and clip
+#[cfg(feature = "audio")]
+const MAX_VOICES: usize = 8;
+
+// Seconds one track takes to fade into the next
+#[cfg(feature = "audio")]
+const CROSSFADE_SECS: f32 = 1.5;
+
+#[cfg(feature = "audio")]
+type Sample = rodio::source::Buffered<rodio::Decoder<std::io::BufReader<std::fs::File>>>;
+
+#[cfg(feature = "audio")]
+pub struct Audio {
+    // Keeps the device open; None if there isn't one to play on
+    output: Option<(rodio::OutputStream, rodio::OutputStreamHandle)>,
+    samples: std::collections::HashMap<Sound, Sample>,
+    voices: Vec<rodio::Sink>,
+    // Every track still audible: the one fading in (or playing) and any fading out
+    music: Vec<MusicVoice>,
+    // Tracks that wouldn't load, so they aren't tried again every frame
+    unplayable: Vec<Track>,
+    volume: f32,
+    muted: bool,
+}
+
+#[cfg(feature = "audio")]
+struct MusicVoice {
+    track: Track,
+    sink: rodio::Sink,
+    // How far faded in, 0.0 to 1.0, and where it's heading
+    level: f32,
+    target: f32,
+}
+
+#[cfg(feature = "audio")]
+impl Audio {
+    // volume is the master volume, 1.0 for samples as recorded
+    pub fn new(volume: f32) -> Self {
+        use rodio::Source;
+
+        let output = match rodio::OutputStream::try_default() {
+            Ok(output) => Some(output),
+            Err(e) => {
+                eprintln!("Sound unavailable: {}", e);
+                None
+            }
+        };
+        let mut samples = std::collections::HashMap::new();
+        for &sound in Sound::ALL.iter() {
+            let decoded = std::fs::File::open(sound.path())
+                .map_err(|e| e.to_string())
+                .and_then(|f| {
+                    rodio::Decoder::new(std::io::BufReader::new(f)).map_err(|e| e.to_string())
+                });
+            match decoded {
+                Ok(decoder) => {
+                    samples.insert(sound, decoder.buffered());
+                }
+                Err(e) => eprintln!("Couldn't load {}: {}", sound.path(), e),
+            }
+        }
+        Self {
+            output,
+            samples,
+            voices: vec![],
+            music: vec![],
+            unplayable: vec![],
+            volume,
+            muted: false,
+        }
+    }
+
+    pub fn play(&mut self, sound: Sound) {
+        if self.muted {
+            return;
+        }
+        let (_, handle) = match &self.output {
+            Some(output) => output,
+            None => return,
+        };
+        let sample = match self.samples.get(&sound) {
+            Some(sample) => sample,
+            None => return,
+        };
+        self.voices.retain(|v| !v.empty());
+        if self.voices.len() >= MAX_VOICES {
+            return;
+        }
+        if let Ok(voice) = rodio::Sink::try_new(handle) {
+            voice.set_volume(self.volume);
+            voice.append(sample.clone());
+            self.voices.push(voice);
+        }
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+    }
+
+    /**
+     * Call once a rendered frame, secs after the last: fades towards track
+     * (starting it if it isn't playing yet) and everything else out, and holds
+     * the music still while the game's paused.
+     */
+    pub fn update_music(&mut self, track: Option<Track>, paused: bool, secs: f32) {
+        for voice in self.music.iter_mut() {
+            voice.target = if Some(voice.track) == track { 1.0 } else { 0.0 };
+        }
+        if let Some(track) = track {
+            let playing = self.music.iter().any(|v| v.track == track);
+            if !playing && !self.unplayable.contains(&track) {
+                self.start_track(track);
+            }
+        }
+        let step = secs / CROSSFADE_SECS;
+        let volume = if self.muted { 0.0 } else { self.volume };
+        for voice in self.music.iter_mut() {
+            if voice.level < voice.target {
+                voice.level = (voice.level + step).min(voice.target);
+            } else {
+                voice.level = (voice.level - step).max(voice.target);
+            }
+            voice.sink.set_volume(voice.level * volume);
+            if paused {
+                voice.sink.pause();
+            } else {
+                voice.sink.play();
+            }
+        }
+        self.music.retain(|v| v.level > 0.0 || v.target > 0.0);
+    }
+
+    fn start_track(&mut self, track: Track) {
+        use rodio::Source;
+
+        let (_, handle) = match &self.output {
+            Some(output) => output,
+            None => return,
+        };
+        let decoded = std::fs::File::open(track.path())
+            .map_err(|e| e.to_string())
+            .and_then(|f| {
+                rodio::Decoder::new(std::io::BufReader::new(f)).map_err(|e| e.to_string())
+            });
+        let source = match decoded {
+            Ok(decoder) => decoder.repeat_infinite(),
+            Err(e) => {
+                eprintln!("Couldn't load {}: {}", track.path(), e);
+                self.unplayable.push(track);
+                return;
+            }
+        };
+        if let Ok(sink) = rodio::Sink::try_new(handle) {
+            sink.set_volume(0.0);
+            sink.append(source);
+            self.music.push(MusicVoice {
+                track,
+                sink,
+                level: 0.0,
+                target: 1.0,
+            });
+        }
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+pub struct Audio;
+
+#[cfg(not(feature = "audio"))]
+impl Audio {
+    pub fn new(_volume: f32) -> Self {
+        Audio
+    }
+
+    pub fn play(&mut self, _sound: Sound) {}
+
+    pub fn toggle_mute(&mut self) {}
+
+    pub fn update_music(&mut self, _track: Option<Track>, _paused: bool, _secs: f32) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::PowerUpKind;
+    use crate::types::Vec2i;
+
+    #[test]
+    fn each_event_asks_for_its_sound() {
+        let at = Vec2i(10, 20);
+        let cases = [
+            (
+                GameEvent::EnemyDestroyed {
+                    pos: at,
+                    center: at,
+                    points: 1,
+                },
+                Some(Sound::Explosion),
+            ),
+            (
+                GameEvent::TerrainDestroyed {
+                    pos: at,
+                    center: at,
+                },
+                Some(Sound::Explosion),
+            ),
+            (GameEvent::PlayerDamaged { amount: 30 }, Some(Sound::Hurt)),
+            (
+                GameEvent::PowerUpCollected(PowerUpKind::Shield),
+                Some(Sound::Pickup),
+            ),
+            (GameEvent::TriggerEntered(0), Some(Sound::Bonus)),
+            (GameEvent::TileShot(0), None),
+        ];
+        for (event, sound) in cases.iter() {
+            assert_eq!(sound_for(event), *sound, "{:?}", event);
+        }
+    }
+
+    #[test]
+    fn the_boss_gets_the_tense_track() {
+        assert_eq!(track_for(GameStage::Rocks(true, 1)), Some(Track::Calm));
+        assert_eq!(track_for(GameStage::Boulders(false, 2)), Some(Track::Calm));
+        assert_eq!(track_for(GameStage::BossWarning(100)), Some(Track::Tense));
+        assert_eq!(track_for(GameStage::Boss), Some(Track::Tense));
+        assert_eq!(track_for(GameStage::Victory(100)), Some(Track::Calm));
+        assert_eq!(track_for(GameStage::GameOver(100)), None);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::game::{HEIGHT, WIDTH};
+use crate::types::{Rect, Vec2f, Vec2i};
+
+/*
+Which way the camera scrolls. The game was laid out as a vertical scroller:
+the camera climbs, ahead is up the screen and things arrive over the top edge.
+A horizontal run turns all of that a quarter turn clockwise, so the camera
+moves right and things arrive from the right hand edge.
+
+Rather than spell out both, code that cares which way is ahead keeps its
+vertical layout and asks the axis to turn it: offsets and velocities with
+turn, places just past the leading edge with ahead_of, and so on. Turning is
+exact, so a vertical run plays exactly as it always has.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ScrollAxis {
+    #[default]
+    Vertical,
+    Horizontal,
+}
+
+impl ScrollAxis {
+    // v, laid out with ahead up the screen, turned to point the same way relative to this axis
+    pub fn turn(self, v: Vec2i) -> Vec2i {
+        match self {
+            ScrollAxis::Vertical => v,
+            ScrollAxis::Horizontal => Vec2i(-v.1, v.0),
+        }
+    }
+
+    pub fn turn_f(self, v: Vec2f) -> Vec2f {
+        match self {
+            ScrollAxis::Vertical => v,
+            ScrollAxis::Horizontal => Vec2f(-v.1, v.0),
+        }
+    }
+
+    // The other way to turn_f: v as it would be laid out with ahead up the screen
+    pub fn unturn_f(self, v: Vec2f) -> Vec2f {
+        match self {
+            ScrollAxis::Vertical => v,
+            ScrollAxis::Horizontal => Vec2f(v.1, -v.0),
+        }
+    }
+
+    // Clockwise quarter turns that make a sprite drawn facing up face ahead
+    pub fn rotation(self) -> u8 {
+        match self {
+            ScrollAxis::Vertical => 0,
+            ScrollAxis::Horizontal => 1,
+        }
+    }
+
+    // How wide the view is across the way the camera moves
+    pub fn breadth(self) -> i32 {
+        match self {
+            ScrollAxis::Vertical => WIDTH as i32,
+            ScrollAxis::Horizontal => HEIGHT as i32,
+        }
+    }
+
+    /**
+     * Where the top left of something depth long (along the axis) goes so its
+     * far edge is ahead pixels past the leading edge of the view at scroll, and
+     * its near side across pixels in from the near side of the view.
+     */
+    pub fn ahead_of(self, scroll: Vec2i, ahead: i32, across: i32, depth: i32) -> Vec2i {
+        match self {
+            ScrollAxis::Vertical => Vec2i(scroll.0 + across, scroll.1 - ahead),
+            ScrollAxis::Horizontal => {
+                Vec2i(scroll.0 + WIDTH as i32 + ahead - depth, scroll.1 + across)
+            }
+        }
+    }
+
+    // How far r's far edge has come into the view at scroll past its leading edge
+    pub fn depth(self, scroll: Vec2i, r: Rect) -> i32 {
+        match self {
+            ScrollAxis::Vertical => r.y - scroll.1,
+            ScrollAxis::Horizontal => scroll.0 + WIDTH as i32 - r.right(),
+        }
+    }
+
+    // Has r fallen all the way off the trailing edge of the view at scroll?
+    pub fn behind(self, scroll: Vec2i, r: Rect) -> bool {
+        match self {
+            ScrollAxis::Vertical => r.y >= scroll.1 + HEIGHT as i32,
+            ScrollAxis::Horizontal => r.right() <= scroll.0,
+        }
+    }
+
+    // Where r's near and far sides are across the view at scroll, from its near side
+    pub fn span_across(self, scroll: Vec2i, r: Rect) -> (i32, i32) {
+        match self {
+            ScrollAxis::Vertical => (r.x - scroll.0, r.right() - scroll.0),
+            ScrollAxis::Horizontal => (r.y - scroll.1, r.bottom() - scroll.1),
+        }
+    }
+
+    // The middle of the edge of r facing ahead, and of the one facing back
+    pub fn nose(self, r: Rect) -> Vec2i {
+        match self {
+            ScrollAxis::Vertical => Vec2i(r.center().0, r.y),
+            ScrollAxis::Horizontal => Vec2i(r.right(), r.center().1),
+        }
+    }
+
+    pub fn tail(self, r: Rect) -> Vec2i {
+        match self {
+            ScrollAxis::Vertical => Vec2i(r.center().0, r.bottom()),
+            ScrollAxis::Horizontal => Vec2i(r.x, r.center().1),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use rand::Rng;
+
+use crate::axis::ScrollAxis;
+use crate::collision::{Mobile, Projectile};
+use crate::pattern::BulletPattern;
+use crate::types::{Vec2f, Vec2i};
+
+/*
+The boss is an ordinary Mobile (flagged is_boss) living in state.mobiles, so
+projectiles and rams hit it through the usual collision paths. This struct
+keeps the bookkeeping that only the boss needs: which phase of the fight we
+are in.
+*/
+pub struct Boss {
+    pub max_hp: usize,
+    pub phase: usize,
+}
+
+// How far below the top of the screen the boss settles
+pub const BOSS_HOVER_Y: i32 = 40;
+
+impl Boss {
+    pub fn new(max_hp: usize) -> Self {
+        Self { max_hp, phase: 0 }
+    }
+
+    /**
+     * Phases are driven by remaining hp: 0 above 2/3, 1 above 1/3, 2 below.
+     * Returns true if the phase changed.
+     */
+    pub fn update_phase(&mut self, hp: usize) -> bool {
+        let phase = if hp * 3 > self.max_hp * 2 {
+            0
+        } else if hp * 3 > self.max_hp {
+            1
+        } else {
+            2
+        };
+        let changed = phase != self.phase;
+        self.phase = phase;
+        changed
+    }
+
+    // Horizontal sweep speed for the current phase
+    fn sweep_speed(&self) -> f32 {
+        1.0 + self.phase as f32
+    }
+
+    // Frames between volleys for the current phase
+    fn fire_interval(&self) -> usize {
+        [40, 30, 20][self.phase]
+    }
+
+    // The patterns the current phase cycles through, a volley each
+    fn patterns(&self) -> &'static [BulletPattern] {
+        match self.phase {
+            0 => &[
+                BulletPattern::Fan(1),
+                BulletPattern::Fan(1),
+                BulletPattern::Fan(3),
+            ],
+            1 => &[
+                BulletPattern::Fan(3),
+                BulletPattern::Fan(5),
+                BulletPattern::Spiral(6),
+            ],
+            _ => &[
+                BulletPattern::Aimed,
+                BulletPattern::Spiral(8),
+                BulletPattern::Scatter(5),
+            ],
+        }
+    }
+
+    /**
+     * Moves the boss down to its hover height, then sweeps it back and forth
+     * across the screen. Scrolling sideways, that's left to its hover distance
+     * from the right hand edge and then up and down.
+     */
+    pub fn steer(&self, boss: &mut Mobile, scroll: Vec2i, axis: ScrollAxis) {
+        let vy = if axis.depth(scroll, boss.rect) < BOSS_HOVER_Y {
+            2.0
+        } else {
+            0.0
+        };
+
+        let speed = self.sweep_speed();
+        let (left, right) = axis.span_across(scroll, boss.rect);
+        let heading_left = if left <= 0 {
+            false
+        } else if right >= axis.breadth() {
+            true
+        } else {
+            axis.unturn_f(boss.velocity()).0 < 0.0
+        };
+        let vx = if heading_left { -speed } else { speed };
+        boss.set_velocity(axis.turn_f(Vec2f(vx, vy)));
+    }
+
+    /**
+     * Returns the (enemy team) projectiles fired this frame, if any: a volley
+     * from just behind the boss every fire_interval, in whichever of the
+     * phase's patterns is up next.
+     */
+    pub fn fire(
+        &self,
+        boss: &mut Mobile,
+        target: Vec2i,
+        axis: ScrollAxis,
+        now: usize,
+        rng: &mut impl Rng,
+    ) -> Vec<Projectile> {
+        let interval = self.fire_interval();
+        boss.set_fire_cooldown(interval);
+        if !boss.ready_to_fire(now) {
+            return vec![];
+        }
+        boss.last_fired = now;
+
+        let origin = axis.tail(boss.rect) + axis.turn(Vec2i(0, 2));
+        let patterns = self.patterns();
+        let pattern = patterns[now / interval % patterns.len()];
+        pattern.emit(origin, target, axis, now, rng)
+    }
+}
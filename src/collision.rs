@@ -1,5 +1,12 @@
+use crate::arena::{Arena, EntityId};
+use crate::axis::ScrollAxis;
+use crate::enemy::EnemyKind;
 use crate::entity::Entity;
-use crate::types::Rect;
+use crate::popup::Popup;
+use crate::trail::Trail;
+use crate::types::{Rect, Vec2f, Vec2i};
+use crate::weapon::Weapon;
+use std::f64::consts::PI;
 
 // seconds per frame
 const DEPTH: usize = 4;
@@ -12,10 +19,13 @@ type Color = [u8; DEPTH];
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 enum ColliderID {
-    Terrain(usize),
-    Mobile(usize),
+    Terrain(EntityId),
+    Mobile(EntityId),
     Projectile(usize),
     Wall(usize),
+    Tile(usize),
+    PowerUp(usize),
+    Trigger(usize),
 }
 
 /*
@@ -31,6 +41,67 @@ pub trait Collider {
     fn move_pos(&mut self, dx: i32, dy: i32);
 
     fn set_pos(&mut self, x: i32, y: i32);
+
+    // Sets the width and height, leaving the top left where it is
+    fn set_size(&mut self, w: u16, h: u16);
+
+    // The box this collider occupies, used by the broadphase
+    fn rect(&self) -> Rect;
+
+    // Where the collision rect sits relative to the entity's sprite
+    fn hitbox_offset(&self) -> Vec2i {
+        Vec2i(0, 0)
+    }
+}
+
+// Solid tiles are bare rects
+impl Collider for Rect {
+    fn move_pos(&mut self, dx: i32, dy: i32) {
+        self.x += dx;
+        self.y += dy;
+    }
+
+    fn set_pos(&mut self, x: i32, y: i32) {
+        self.x = x;
+        self.y = y;
+    }
+
+    fn set_size(&mut self, w: u16, h: u16) {
+        self.w = w;
+        self.h = h;
+    }
+
+    fn rect(&self) -> Rect {
+        *self
+    }
+}
+
+/*
+Things handle_contact saw happen, in the order it saw them, so the game can
+hang effects (popups, debris, drops) off them. Positions are in world pixels;
+pos is where the thing's sprite was, center the middle of its hitbox. An
+enemy's points are its score_value, before any combo multiplier.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(crate) enum GameEvent {
+    EnemyDestroyed {
+        pos: Vec2i,
+        center: Vec2i,
+        points: usize,
+    },
+    TerrainDestroyed {
+        pos: Vec2i,
+        center: Vec2i,
+    },
+    // The player lost this much hp, all told
+    PlayerDamaged {
+        amount: usize,
+    },
+    PowerUpCollected(PowerUpKind),
+    // A solid tile was shot, as an index into the tiles given to gather_contacts
+    TileShot(usize),
+    // The player flew into this trigger, as an index into triggers
+    TriggerEntered(usize),
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -56,6 +127,12 @@ pub struct Terrain {
     pub created_at: usize,
     pub destructible: bool,
     pub hp: usize,
+    // What hp it started with, for how cracked it looks
+    pub max_hp: usize,
+    // Pixels per frame it drifts through the world, on top of the camera scroll
+    pub velocity: Vec2f,
+    // The part of a pixel it's drifted but not yet moved
+    pub remainder: Vec2f,
 }
 impl Collider for Terrain {
     fn move_pos(&mut self, dx: i32, dy: i32) {
@@ -67,17 +144,47 @@ impl Collider for Terrain {
         self.rect.x = x;
         self.rect.y = y;
     }
+
+    fn set_size(&mut self, w: u16, h: u16) {
+        self.rect.w = w;
+        self.rect.h = h;
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
 }
 
 impl Terrain {
     pub fn new(rect: Rect, created_at: usize, destructible: bool, hp: usize) -> Self {
         Self {
-            rect: rect,
-            created_at: created_at,
-            destructible: destructible,
-            hp: hp,
+            rect,
+            created_at,
+            destructible,
+            hp,
+            max_hp: hp,
+            velocity: Vec2f(0.0, 0.0),
+            remainder: Vec2f(0.0, 0.0),
         }
     }
+
+    // How many whole pixels to drift in an update covering this many frames
+    pub fn drift(&mut self, frames: f32) -> Vec2i {
+        sub_pixel_step(&mut self.remainder, self.velocity * frames)
+    }
+
+    /**
+     * Which of stages looks to show, from 0 while undamaged up to stages - 1
+     * once it's down to its last quarter or so (with 4 stages). The lost share
+     * of max_hp decides it.
+     */
+    pub fn crack_stage(&self, stages: usize) -> usize {
+        let lost = self.max_hp.saturating_sub(self.hp);
+        (lost * stages)
+            .checked_div(self.max_hp)
+            .unwrap_or(0)
+            .min(stages.saturating_sub(1))
+    }
 }
 
 /*
@@ -90,6 +197,26 @@ pub struct Mobile {
     pub vy: f32,
     pub hp: usize,
     pub is_player: bool,
+    pub is_boss: bool,
+    pub last_fired: usize,
+    // Frames that must pass between shots
+    pub fire_cooldown: usize,
+    // The player can't be hurt again before this frame
+    pub invuln_until: usize,
+    // Hits do nothing before this frame, thanks to a shield power-up
+    pub shield_until: usize,
+    // Percent of each hit's damage it shrugs off, 0 to 100
+    pub armor: u8,
+    // rect is inset this far from the top left of the sprite
+    pub hitbox_offset: Vec2i,
+    // How an ordinary enemy moves; the player and boss ignore it
+    pub kind: EnemyKind,
+    // Points for destroying it
+    pub score_value: usize,
+    // What hp it starts out with, and heals up to
+    pub max_hp: usize,
+    // The part of a pixel it's moved but not yet shown
+    pub remainder: Vec2f,
 }
 impl Collider for Mobile {
     fn move_pos(&mut self, dx: i32, dy: i32) {
@@ -101,30 +228,170 @@ impl Collider for Mobile {
         self.rect.x = x;
         self.rect.y = y;
     }
+
+    fn set_size(&mut self, w: u16, h: u16) {
+        self.rect.w = w;
+        self.rect.h = h;
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn hitbox_offset(&self) -> Vec2i {
+        self.hitbox_offset
+    }
 }
 impl Mobile {
     pub fn enemy(rect: Rect, vx: f32, vy: f32, hp: usize) -> Self {
         Self {
-            rect: rect,
-            vx: vx,
-            vy: vy,
-            hp: hp,
+            rect,
+            vx,
+            vy,
+            hp,
+            max_hp: hp,
+            is_player: false,
+            is_boss: false,
+            last_fired: 0,
+            fire_cooldown: ENEMY_FIRE_COOLDOWN,
+            invuln_until: 0,
+            shield_until: 0,
+            armor: 0,
+            kind: EnemyKind::Chaser,
+            score_value: EnemyKind::Chaser.score_value(),
+            hitbox_offset: Vec2i(0, 0),
+            remainder: Vec2f(0.0, 0.0),
+        }
+    }
+
+    pub fn boss(rect: Rect, hp: usize) -> Self {
+        Self {
+            rect,
+            vx: 0.0,
+            vy: 0.0,
+            hp,
+            max_hp: hp,
             is_player: false,
+            is_boss: true,
+            last_fired: 0,
+            fire_cooldown: ENEMY_FIRE_COOLDOWN,
+            invuln_until: 0,
+            shield_until: 0,
+            armor: 0,
+            kind: EnemyKind::Chaser,
+            score_value: BOSS_SCORE_VALUE,
+            hitbox_offset: Vec2i(0, 0),
+            remainder: Vec2f(0.0, 0.0),
+        }
+    }
+
+    pub fn team(&self) -> Team {
+        if self.is_player {
+            Team::Player
+        } else {
+            Team::Enemy
+        }
+    }
+
+    // Has fire_cooldown frames passed since this mobile last fired?
+    pub fn ready_to_fire(&self, now: usize) -> bool {
+        now.saturating_sub(self.last_fired) >= self.fire_cooldown
+    }
+
+    // Changes the fire rate; a cooldown of 0 would mean firing every frame anyway
+    pub fn set_fire_cooldown(&mut self, frames: usize) {
+        self.fire_cooldown = frames.max(1);
+    }
+
+    pub fn velocity(&self) -> Vec2f {
+        Vec2f(self.vx, self.vy)
+    }
+
+    pub fn set_velocity(&mut self, v: Vec2f) {
+        self.vx = v.0;
+        self.vy = v.1;
+    }
+
+    // How many whole pixels to move in an update covering this many frames
+    pub fn step(&mut self, frames: f32) -> Vec2i {
+        let delta = self.velocity() * frames;
+        sub_pixel_step(&mut self.remainder, delta)
+    }
+
+    pub fn is_invulnerable(&self, now: usize) -> bool {
+        now < self.invuln_until
+    }
+
+    pub fn is_shielded(&self, now: usize) -> bool {
+        now < self.shield_until
+    }
+
+    // What's left of damage once armor has taken its share, rounding the damage up
+    pub fn armored(&self, damage: usize) -> usize {
+        let armor = self.armor.min(100) as usize;
+        // damage * armor / 100, without overflowing on huge hits
+        let blocked = damage / 100 * armor + damage % 100 * armor / 100;
+        damage.saturating_sub(blocked)
+    }
+
+    /**
+     * Takes damage, less armor, unless still invulnerable from the last hit or
+     * shielded. A player that survives a hit gets INVULN_FRAMES of
+     * invulnerability; enemies don't.
+     */
+    pub fn hurt(&mut self, damage: usize, now: usize) {
+        let damage = self.armored(damage);
+        self.take_damage(damage, now);
+    }
+
+    // Flying into something is fatal whatever the armor, though not through a shield
+    pub fn crash(&mut self, now: usize) {
+        self.take_damage(self.hp, now);
+    }
+
+    fn take_damage(&mut self, damage: usize, now: usize) {
+        if self.is_invulnerable(now) {
+            return;
+        }
+        // A shield soaks up hits, but one that would have been fatal uses it up
+        if self.is_shielded(now) {
+            if damage >= self.hp {
+                self.shield_until = now;
+                self.invuln_until = now + INVULN_FRAMES;
+            }
+            return;
+        }
+        self.hp = self.hp.saturating_sub(damage);
+        if self.is_player && self.hp > 0 {
+            self.invuln_until = now + INVULN_FRAMES;
         }
     }
 
-    pub fn player(x: i32, y: i32) -> Self {
+    // A player at full health, max_hp, whose 36x25 sprite has its top left at (x, y)
+    pub fn player(x: i32, y: i32, max_hp: usize) -> Self {
+        let Vec2i(dx, dy) = PLAYER_HITBOX_INSET;
         Self {
             rect: Rect {
-                x: x,
-                y: y,
-                w: 36,
-                h: 25,
+                x: x + dx,
+                y: y + dy,
+                w: 36 - 2 * dx as u16,
+                h: 25 - 2 * dy as u16,
             },
             vx: 0.0,
             vy: 0.0,
-            hp: 100,
+            hp: max_hp,
+            max_hp,
             is_player: true,
+            is_boss: false,
+            last_fired: 0,
+            fire_cooldown: PLAYER_FIRE_COOLDOWN,
+            invuln_until: 0,
+            shield_until: 0,
+            armor: 0,
+            kind: EnemyKind::Chaser,
+            score_value: 0,
+            hitbox_offset: PLAYER_HITBOX_INSET,
+            remainder: Vec2f(0.0, 0.0),
         }
     }
 
@@ -135,15 +402,70 @@ impl Mobile {
     }
 }
 
+// The player's hp when full, unless the game is set up otherwise
+pub const PLAYER_MAX_HP: usize = 100;
+
+// Holding fire longer than this many frames doesn't charge a shot any further
+const MAX_CHARGE: usize = 60;
+// Side length and damage of a fully charged shot
+const MAX_CHARGE_SIZE: u16 = 15;
+const MAX_CHARGE_HP: usize = 20;
+
+// Frames a projectile flies before it's dropped, if it hasn't hit anything or left the screen
+const PROJECTILE_LIFETIME: usize = 300;
+
+// Speed a hit adds to an enemy, along the shot's path
+const KNOCKBACK: f32 = 2.0;
+// Fastest a hit can leave an enemy moving relative to the camera, so it can't be shot off screen
+const MAX_KNOCKBACK_SPEED: f32 = 5.0;
+
+// How sharply homing projectiles can turn, in radians per frame
+const HOMING_TURN_RATE: f64 = 0.08;
+
+// Frames between the player's shots with the starting weapon
+pub const PLAYER_FIRE_COOLDOWN: usize = 6;
+// Frames between an ordinary enemy's shots
+const ENEMY_FIRE_COOLDOWN: usize = 90;
+// Points for destroying the boss, before any combo multiplier
+const BOSS_SCORE_VALUE: usize = 25;
+
+// How far the player's hitbox is inset from each edge of its sprite, so that
+// shots grazing the wingtips don't count
+const PLAYER_HITBOX_INSET: Vec2i = Vec2i(4, 3);
+
+// How long a shield power-up lasts
+const SHIELD_FRAMES: usize = 600;
+
+// How long the player is invulnerable for after taking damage
+pub const INVULN_FRAMES: usize = 60;
+
+/*
+    Which side something is on. Projectiles only hurt mobiles of the other team.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Team {
+    Player,
+    Enemy,
+}
+
 /*
     Projectiles can cross each others and they will only collide with terrains and mobiles. Since we might need it to point clearly the speed should be floats. (subject to change.)
 */
 #[derive(Clone)]
 pub struct Projectile {
     pub(crate) rect: Rect,
-    vx: f64,
-    vy: f64,
-    hp: usize,
+    pub(crate) vx: f64,
+    pub(crate) vy: f64,
+    pub(crate) hp: usize,
+    pub(crate) team: Team,
+    // Turns towards the nearest mobile of the other team as it flies
+    pub(crate) homing: bool,
+    // Where it's been, if it leaves a trail
+    pub(crate) trail: Option<Trail>,
+    // The part of a pixel it's flown but not yet moved
+    pub(crate) remainder: Vec2f,
+    // The frame it was fired on
+    pub(crate) created_at: usize,
 }
 impl Collider for Projectile {
     fn move_pos(&mut self, dx: i32, dy: i32) {
@@ -155,29 +477,242 @@ impl Collider for Projectile {
         self.rect.x = x;
         self.rect.y = y;
     }
+
+    fn set_size(&mut self, w: u16, h: u16) {
+        self.rect.w = w;
+        self.rect.h = h;
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
 }
 impl Projectile {
-    pub(crate) fn new(from: &Mobile) -> Self {
+    // A player shot flying straight ahead along axis
+    pub(crate) fn new(from: &Mobile, axis: ScrollAxis, now: usize) -> Self {
+        let Vec2f(vx, vy) = axis.turn_f(Vec2f(0.0, -10.0));
         Self {
-            rect: Rect {
-                x: from.rect.x + from.rect.w as i32 / 2,
-                y: from.rect.y - 10,
-                w: 5,
-                h: 5,
-            },
-            vx: 0.0,
-            vy: -10.0,
+            // Just past the middle of the ship's nose
+            rect: Rect::from_center(axis.nose(from.rect) + axis.turn(Vec2i(0, -8)), 5, 5),
+            vx: vx as f64,
+            vy: vy as f64,
             hp: 4,
+            team: Team::Player,
+            homing: false,
+            trail: Some(Trail::default()),
+            remainder: Vec2f(0.0, 0.0),
+            created_at: now,
+        }
+    }
+
+    /**
+     * A player shot that was charged for charge frames. It grows from a normal
+     * shot's size and hp up to MAX_CHARGE_SIZE and MAX_CHARGE_HP once charge
+     * reaches MAX_CHARGE.
+     */
+    pub(crate) fn charged(from: &Mobile, charge: usize, axis: ScrollAxis, now: usize) -> Self {
+        let charge = charge.min(MAX_CHARGE);
+        let base = Self::new(from, axis, now);
+        let size =
+            base.rect.w + ((MAX_CHARGE_SIZE - base.rect.w) as usize * charge / MAX_CHARGE) as u16;
+        // Keep the back edge where a normal shot's would be
+        let back = base.rect.bottom() - size as i32 / 2 - base.rect.center().1;
+        Self {
+            rect: Rect::from_center(base.rect.center() + axis.turn(Vec2i(0, back)), size, size),
+            hp: base.hp + (MAX_CHARGE_HP - base.hp) * charge / MAX_CHARGE,
+            ..base
+        }
+    }
+
+    // Shot backward along axis from just behind an enemy
+    pub(crate) fn from_enemy(from: &Mobile, axis: ScrollAxis, now: usize) -> Self {
+        let Vec2i(x, y) = axis.tail(from.rect) + axis.turn(Vec2i(0, 5));
+        let Vec2f(vx, vy) = axis.turn_f(Vec2f(0.0, 5.0));
+        Self::at(x, y, vx as f64, vy as f64, now)
+    }
+
+    // An enemy projectile centered on (x, y) travelling at (vx, vy), fired at now
+    pub(crate) fn at(x: i32, y: i32, vx: f64, vy: f64, now: usize) -> Self {
+        Self {
+            rect: Rect::from_center(Vec2i(x, y), 5, 5),
+            vx,
+            vy,
+            hp: 10,
+            team: Team::Enemy,
+            homing: false,
+            trail: None,
+            remainder: Vec2f(0.0, 0.0),
+            created_at: now,
+        }
+    }
+
+    pub(crate) fn with_velocity(self, vx: f64, vy: f64) -> Self {
+        Self { vx, vy, ..self }
+    }
+
+    /**
+     * Turned so that a shot that was flying straight up flies along direction
+     * (a unit vector) instead. Anything flying off to the side of straight up,
+     * like a spread shot, keeps its angle from the new direction.
+     */
+    pub(crate) fn aimed(self, direction: Vec2f) -> Self {
+        // The rotation taking (0, -1) to direction
+        let (sin, cos) = (direction.0 as f64, -direction.1 as f64);
+        Self {
+            vx: self.vx * cos - self.vy * sin,
+            vy: self.vx * sin + self.vy * cos,
+            ..self
         }
     }
 
+    pub(crate) fn homing(self) -> Self {
+        Self {
+            homing: true,
+            ..self
+        }
+    }
+
+    // Moves it along by its velocity for this many frames, leaving a point in its trail behind
+    pub(crate) fn fly(&mut self, frames: f32) {
+        if let Some(trail) = &mut self.trail {
+            trail.push(self.rect.center());
+        }
+        let delta = Vec2f(self.vx as f32, self.vy as f32) * frames;
+        let Vec2i(dx, dy) = sub_pixel_step(&mut self.remainder, delta);
+        self.move_pos(dx, dy);
+    }
+
+    // Has it been flying too long to still be worth keeping?
+    pub(crate) fn expired(&self, now: usize) -> bool {
+        now.saturating_sub(self.created_at) >= PROJECTILE_LIFETIME
+    }
+
+    // Which way it's flying, as a unit vector
+    pub(crate) fn direction(&self) -> Vec2f {
+        Vec2f(self.vx as f32, self.vy as f32).normalized()
+    }
+
     pub fn get_velocity(&self) -> (f64, f64) {
         (self.vx, self.vy)
     }
+
+    /**
+     * Turns the projectile towards target by at most HOMING_TURN_RATE radians
+     * a frame, for this many frames, keeping its speed.
+     */
+    pub(crate) fn steer_towards(&mut self, target: Vec2i, frames: f32) {
+        let Vec2i(x, y) = self.rect.center();
+        let heading = self.vy.atan2(self.vx);
+        let wanted = ((target.1 - y) as f64).atan2((target.0 - x) as f64);
+        // The shorter way round, in [-PI, PI)
+        let turn = (wanted - heading + PI).rem_euclid(2.0 * PI) - PI;
+        let max_turn = HOMING_TURN_RATE * frames as f64;
+        let heading = heading + turn.clamp(-max_turn, max_turn);
+        let speed = self.vx.hypot(self.vy);
+        self.vx = speed * heading.cos();
+        self.vy = speed * heading.sin();
+    }
+}
+
+/*
+    Power-ups are triggers: the player picks them up by overlapping them, and
+    they never push anything around or take damage.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PowerUpKind {
+    // Restores this much hp, up to the player's max
+    Heal(usize),
+    // Switches the player to this weapon
+    Weapon(Weapon),
+    // Shields the player for SHIELD_FRAMES
+    Shield,
+}
+
+#[derive(Clone)]
+pub struct PowerUp {
+    pub rect: Rect,
+    pub kind: PowerUpKind,
+    pub collected: bool,
+}
+impl Collider for PowerUp {
+    fn move_pos(&mut self, dx: i32, dy: i32) {
+        self.rect.x += dx;
+        self.rect.y += dy;
+    }
+
+    fn set_pos(&mut self, x: i32, y: i32) {
+        self.rect.x = x;
+        self.rect.y = y;
+    }
+
+    fn set_size(&mut self, w: u16, h: u16) {
+        self.rect.w = w;
+        self.rect.h = h;
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+}
+impl PowerUp {
+    pub fn new(rect: Rect, kind: PowerUpKind) -> Self {
+        Self {
+            rect,
+            kind,
+            collected: false,
+        }
+    }
+}
+
+/*
+    Triggers are zones that notice the player flying into them without being
+    solid: nothing is pushed out of them, shot, or hurt by them.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TriggerKind {
+    // Worth this many points when the player flies in
+    Bonus(usize),
+}
+
+#[derive(Clone)]
+pub struct Trigger {
+    pub rect: Rect,
+    pub kind: TriggerKind,
+    // Was the player inside last frame? Only coming in counts as entering
+    pub occupied: bool,
+}
+impl Collider for Trigger {
+    fn move_pos(&mut self, dx: i32, dy: i32) {
+        self.rect.x += dx;
+        self.rect.y += dy;
+    }
+
+    fn set_pos(&mut self, x: i32, y: i32) {
+        self.rect.x = x;
+        self.rect.y = y;
+    }
+
+    fn set_size(&mut self, w: u16, h: u16) {
+        self.rect.w = w;
+        self.rect.h = h;
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+}
+impl Trigger {
+    pub fn new(rect: Rect, kind: TriggerKind) -> Self {
+        Self {
+            rect,
+            kind,
+            occupied: false,
+        }
+    }
 }
 
 pub struct Wall {
-    rect: Rect,
+    pub(crate) rect: Rect,
 }
 impl Collider for Wall {
     fn move_pos(&mut self, dx: i32, dy: i32) {
@@ -189,10 +724,19 @@ impl Collider for Wall {
         self.rect.x = x;
         self.rect.y = y;
     }
+
+    fn set_size(&mut self, w: u16, h: u16) {
+        self.rect.w = w;
+        self.rect.h = h;
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
 }
 impl Wall {
     pub fn new(rect: Rect) -> Self {
-        Self { rect: rect }
+        Self { rect }
     }
 }
 
@@ -221,8 +765,8 @@ fn rect(fb: &mut [u8], r: Rect, c: Color) {
 }
 
 fn rect_displacement(r1: Rect, r2: Rect) -> Option<(i32, i32)> {
-    let x_overlap = (r1.x + r1.w as i32).min(r2.x + r2.w as i32) - r1.x.max(r2.x);
-    let y_overlap = (r1.y + r1.h as i32).min(r2.y + r2.h as i32) - r1.y.max(r2.y);
+    let x_overlap = r1.right().min(r2.right()) - r1.x.max(r2.x);
+    let y_overlap = r1.bottom().min(r2.bottom()) - r1.y.max(r2.y);
     if x_overlap > 0 && y_overlap > 0 {
         if x_overlap.abs() > y_overlap.abs() {
             Some((0, y_overlap))
@@ -235,243 +779,437 @@ fn rect_displacement(r1: Rect, r2: Rect) -> Option<(i32, i32)> {
 }
 
 // Here we will be using push() on into, so it can't be a slice
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn gather_contacts(
-    terrains: &[Entity<Terrain>],
-    mobiles: &[Entity<Mobile>],
+    terrains: &Arena<Entity<Terrain>>,
+    mobiles: &Arena<Entity<Mobile>>,
     walls: &[Wall],
+    tiles: &[Rect],
     projs: &[Projectile],
+    powerups: &[Entity<PowerUp>],
+    triggers: &[Trigger],
     into: &mut Vec<Contact>,
 ) {
-    // collide mobiles against mobiles
-    for (ai, a) in mobiles.iter().enumerate() {
-        let a = &a.collider;
-        for (bi, b) in mobiles.iter().enumerate().skip(ai + 1) {
-            let b = &b.collider;
-            if !separating_axis(
-                a.rect.x,
-                a.rect.x + a.rect.w as i32,
-                b.rect.x,
-                b.rect.x + b.rect.w as i32,
-            ) && !separating_axis(
-                a.rect.y,
-                a.rect.y + a.rect.h as i32,
-                b.rect.y,
-                b.rect.y + b.rect.h as i32,
-            ) {
-                let contact = Contact {
-                    a: ColliderID::Mobile(ai),
-                    b: ColliderID::Mobile(bi),
-                    mtv: (0, 0),
-                };
+    let mobile_colliders = || mobiles.entries().map(|(id, m)| (id, &m.collider));
+    let terrain_colliders = || terrains.entries().map(|(id, t)| (id, &t.collider));
 
-                into.push(contact);
-            }
+    // collide mobiles against mobiles
+    collide_all(mobile_colliders(), mobile_colliders(), |ai, _, bi, _| {
+        if bi > ai {
+            into.push(Contact {
+                a: ColliderID::Mobile(ai),
+                b: ColliderID::Mobile(bi),
+                mtv: (0, 0),
+            });
         }
-    }
+    });
     // collide mobiles against terrains
-    for (ai, a) in mobiles.iter().enumerate() {
-        let a = &a.collider;
-        for (bi, b) in terrains.iter().enumerate() {
-            let b = &b.collider;
-            if !separating_axis(
-                a.rect.x,
-                a.rect.x + a.rect.w as i32,
-                b.rect.x,
-                b.rect.x + b.rect.w as i32,
-            ) && !separating_axis(
-                a.rect.y,
-                a.rect.y + a.rect.h as i32,
-                b.rect.y,
-                b.rect.y + b.rect.h as i32,
-            ) {
-                let contact = Contact {
-                    a: ColliderID::Mobile(ai),
-                    b: ColliderID::Terrain(bi),
-                    mtv: (0, 0),
-                };
-
-                into.push(contact);
-            }
-        }
-    }
+    collide_all(mobile_colliders(), terrain_colliders(), |ai, _, bi, _| {
+        into.push(Contact {
+            a: ColliderID::Mobile(ai),
+            b: ColliderID::Terrain(bi),
+            mtv: (0, 0),
+        });
+    });
     // collide mobiles against walls
-    for (ai, a) in mobiles.iter().enumerate() {
-        let a = &a.collider;
-        for (bi, b) in walls.iter().enumerate() {
-            if !separating_axis(
-                a.rect.x,
-                a.rect.x + a.rect.w as i32,
-                b.rect.x,
-                b.rect.x + b.rect.w as i32,
-            ) && !separating_axis(
-                a.rect.y,
-                a.rect.y + a.rect.h as i32,
-                b.rect.y,
-                b.rect.y + b.rect.h as i32,
-            ) {
-                let contact = Contact {
+    collide_all(
+        mobile_colliders(),
+        walls.iter().enumerate(),
+        |ai, a, bi, b| {
+            into.push(Contact {
+                a: ColliderID::Mobile(ai),
+                b: ColliderID::Wall(bi),
+                mtv: rect_displacement(a.rect, b.rect).unwrap_or((0, 0)),
+            });
+        },
+    );
+    // collide mobiles against solid tiles
+    collide_all(
+        mobile_colliders(),
+        tiles.iter().enumerate(),
+        |ai, _, bi, _| {
+            into.push(Contact {
+                a: ColliderID::Mobile(ai),
+                b: ColliderID::Tile(bi),
+                mtv: (0, 0),
+            });
+        },
+    );
+    // collide the player against power-ups
+    let powerup_colliders = powerups.iter().map(|p| &p.collider).enumerate();
+    collide_all(mobile_colliders(), powerup_colliders, |ai, a, bi, _| {
+        if a.is_player {
+            into.push(Contact {
+                a: ColliderID::Mobile(ai),
+                b: ColliderID::PowerUp(bi),
+                mtv: (0, 0),
+            });
+        }
+    });
+    // collide the player against triggers; with no mtv, nothing gets pushed out
+    collide_all(
+        mobile_colliders(),
+        triggers.iter().enumerate(),
+        |ai, a, bi, _| {
+            if a.is_player {
+                into.push(Contact {
                     a: ColliderID::Mobile(ai),
-                    b: ColliderID::Wall(bi),
-                    mtv: match rect_displacement(a.rect, b.rect) {
-                        Some((x, y)) => (x, y),
-                        None => (0, 0),
-                    },
-                };
-
-                into.push(contact);
+                    b: ColliderID::Trigger(bi),
+                    mtv: (0, 0),
+                });
             }
-        }
-    }
-    // collide projs against mobiles
-    for (ai, a) in projs.iter().enumerate() {
-        for (bi, b) in mobiles.iter().enumerate() {
-            let b = &b.collider;
-            if !separating_axis(
-                a.rect.x,
-                a.rect.x + a.rect.w as i32,
-                b.rect.x,
-                b.rect.x + b.rect.w as i32,
-            ) && !separating_axis(
-                a.rect.y,
-                a.rect.y + a.rect.h as i32,
-                b.rect.y,
-                b.rect.y + b.rect.h as i32,
-            ) {
-                let contact = Contact {
+        },
+    );
+    // collide projs against mobiles of the other team
+    collide_all(
+        projs.iter().enumerate(),
+        mobile_colliders(),
+        |ai, a, bi, b| {
+            if a.team != b.team() {
+                into.push(Contact {
                     a: ColliderID::Projectile(ai),
                     b: ColliderID::Mobile(bi),
                     mtv: (0, 0),
-                };
-
-                into.push(contact);
+                });
             }
-        }
-    }
+        },
+    );
     // collide projs against terrains
-    for (ai, a) in projs.iter().enumerate() {
-        for (bi, b) in terrains.iter().enumerate() {
-            let b = &b.collider;
-            if !separating_axis(
-                a.rect.x,
-                a.rect.x + a.rect.w as i32,
-                b.rect.x,
-                b.rect.x + b.rect.w as i32,
-            ) && !separating_axis(
-                a.rect.y,
-                a.rect.y + a.rect.h as i32,
-                b.rect.y,
-                b.rect.y + b.rect.h as i32,
-            ) {
-                let contact = Contact {
-                    a: ColliderID::Projectile(ai),
-                    b: ColliderID::Terrain(bi),
-                    mtv: (0, 0),
-                };
+    collide_all(
+        projs.iter().enumerate(),
+        terrain_colliders(),
+        |ai, _, bi, _| {
+            into.push(Contact {
+                a: ColliderID::Projectile(ai),
+                b: ColliderID::Terrain(bi),
+                mtv: (0, 0),
+            });
+        },
+    );
+    // collide projs against solid tiles
+    collide_all(
+        projs.iter().enumerate(),
+        tiles.iter().enumerate(),
+        |ai, _, bi, _| {
+            into.push(Contact {
+                a: ColliderID::Projectile(ai),
+                b: ColliderID::Tile(bi),
+                mtv: (0, 0),
+            });
+        },
+    );
+}
+
+/*
+Whole pixels to move for delta pixels of motion. The fractions are saved up in
+remainder until they add up to a pixel, so slow movers (and everything, at a
+high update rate) still cover the right distance over time.
+*/
+fn sub_pixel_step(remainder: &mut Vec2f, delta: Vec2f) -> Vec2i {
+    let total = *remainder + delta;
+    let step = total.truncate();
+    *remainder = total - Vec2f(step.0 as f32, step.1 as f32);
+    step
+}
+
+// Most times clear_placement pushes a new rect out of something before giving up on it
+const MAX_PLACEMENT_NUDGES: usize = 3;
+
+/*
+Where to put rect so it doesn't overlap any of obstacles: where it is if that's
+clear, otherwise pushed out of whatever it hits, the short way out and away
+from that thing's center. None if it's still stuck after MAX_PLACEMENT_NUDGES
+pushes, say wedged between two things.
+*/
+pub(crate) fn clear_placement(mut rect: Rect, obstacles: &[Rect]) -> Option<Rect> {
+    for _ in 0..MAX_PLACEMENT_NUDGES {
+        let hit = match obstacles.iter().find(|&&o| overlaps(rect, o)) {
+            Some(&hit) => hit,
+            None => return Some(rect),
+        };
+        let (dx, dy) = rect_displacement(rect, hit)?;
+        let away = rect.center() - hit.center();
+        rect.x += if away.0 < 0 { -dx } else { dx };
+        rect.y += if away.1 < 0 { -dy } else { dy };
+    }
+    obstacles
+        .iter()
+        .all(|&o| !overlaps(rect, o))
+        .then_some(rect)
+}
+
+// Do these two rects overlap? Rects that only share an edge don't.
+pub(crate) fn overlaps(a: Rect, b: Rect) -> bool {
+    a.intersects(&b)
+}
 
-                into.push(contact);
+/*
+Call on_overlap(ai, a, bi, b) for every pair of (id, collider)s from a_side and
+b_side whose rects overlap, in the order the two sides are given in.
+*/
+fn collide_all<'a, I: Copy, J: Copy, A: Collider + 'a, B: Collider + 'a>(
+    a_side: impl Iterator<Item = (I, &'a A)>,
+    b_side: impl Iterator<Item = (J, &'a B)> + Clone,
+    mut on_overlap: impl FnMut(I, &A, J, &B),
+) {
+    for (ai, a) in a_side {
+        for (bi, b) in b_side.clone() {
+            if overlaps(a.rect(), b.rect()) {
+                on_overlap(ai, a, bi, b);
             }
         }
     }
 }
 
 /*
-Modify the hp of the objects and remove unnecessary objects.
-Return a boolean indicating if the player is alive.
+Modify the hp of the objects, apply collected power-ups and remove unnecessary objects.
+player is the player's id in mobiles; the player stays in mobiles even once it's dead.
+What happened along the way is pushed onto events for the caller to react to: see
+GameEvent. Heals and shields are already applied by then, and what becomes of shot
+tiles is up to the caller. Mobiles pushed off walls are left moving with the camera,
+which is moving at camera pixels a frame.
+Return a boolean indicating if the player is alive, the number of kills, and the
+points they're worth (the sum of their score_values).
 */
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn handle_contact(
-    terrains: &mut Vec<Entity<Terrain>>,
-    mobiles: &mut Vec<Entity<Mobile>>,
+    terrains: &mut Arena<Entity<Terrain>>,
+    mobiles: &mut Arena<Entity<Mobile>>,
+    player: EntityId,
     projs: &mut Vec<Projectile>,
-    contacts: &mut Vec<Contact>,
-) -> (bool, usize) {
+    powerups: &mut Vec<Entity<PowerUp>>,
+    triggers: &mut [Trigger],
+    contacts: &mut [Contact],
+    events: &mut Vec<GameEvent>,
+    popups: &mut Vec<Popup>,
+    now: usize,
+    camera: Vec2f,
+    god_mode: bool,
+) -> (bool, usize, usize) {
     // Restitute before calculating hp to avoid restituting objects after they die
-    restitute(terrains, mobiles, contacts);
+    restitute(terrains, mobiles, contacts, camera);
+
+    // A player missing from mobiles counts as dead
+    let player_hp =
+        |mobiles: &Arena<Entity<Mobile>>| mobiles.get(player).map_or(0, |p| p.collider.hp);
+    let hp_before = player_hp(mobiles);
+
+    // Which triggers the player is inside this frame
+    let mut inside = vec![false; triggers.len()];
 
     // We first modify the hp of the collision objects.
     for contact in contacts.iter() {
+        // Skip contacts with anything removed since they were gathered
+        if !resolves(contact.a, terrains, mobiles) || !resolves(contact.b, terrains, mobiles) {
+            continue;
+        }
         match (contact.a, contact.b) {
             // By design a contact will always be MM MT PM PT
-            // MT collide will kill the mobile
+            // PM contacts only happen between different teams, and shove ordinary enemies back
+            // MT collide will kill the mobile, solid tiles count as terrain
+            // P-Tile stops the projectile and leaves the tile to the caller
+            // M-PowerUp applies the power-up to the player (only players touch them)
+            // M-Trigger only notes that the player is inside, for the caller to act on
             // MM collide will destroy the lower hp mobile and cause 30 pt damage to the higher hp mobile, except enemies don't damage each other
-            (ColliderID::Mobile(a), ColliderID::Terrain(_)) => {
-                if mobiles[a].collider.is_player {
-                    mobiles[a].collider.hp = 0;
+            // Damage to the player is skipped while it's invulnerable from a recent hit
+            // In god mode nothing damages the player, and it wins every ram unhurt
+            (ColliderID::Mobile(a), ColliderID::Terrain(_) | ColliderID::Tile(_))
+                if a == player && !god_mode =>
+            {
+                mobiles[a].collider.crash(now);
+            }
+            (ColliderID::Mobile(a), ColliderID::Mobile(b)) if a == player || b == player => {
+                let a_wins = if god_mode {
+                    a == player
+                } else {
+                    mobiles[a].collider.hp > mobiles[b].collider.hp
+                };
+                let (winner, loser) = if a_wins { (a, b) } else { (b, a) };
+                mobiles[loser].collider.crash(now);
+                if !god_mode {
+                    mobiles[winner].collider.hurt(30, now);
                 }
             }
-            (ColliderID::Mobile(a), ColliderID::Mobile(b)) => {
-                if mobiles[a].collider.is_player || mobiles[b].collider.is_player {
-                    if mobiles[a].collider.hp > mobiles[b].collider.hp {
-                        mobiles[b].collider.hp = 0;
-                        mobiles[a].collider.hp = if mobiles[a].collider.hp >= 30 {
-                            mobiles[a].collider.hp - 30
-                        } else {
-                            0
-                        };
-                    } else {
-                        mobiles[a].collider.hp = 0;
-                        mobiles[b].collider.hp = if mobiles[b].collider.hp >= 30 {
-                            mobiles[b].collider.hp - 30
-                        } else {
-                            0
-                        };
+            // Dead players can't pick anything up, so a heal can't undo a fatal hit
+            (ColliderID::Mobile(a), ColliderID::PowerUp(b))
+                if !powerups[b].collider.collected && mobiles[a].collider.hp > 0 =>
+            {
+                match powerups[b].collider.kind {
+                    PowerUpKind::Heal(amount) => {
+                        mobiles[a].collider.hp =
+                            (mobiles[a].collider.hp + amount).min(mobiles[a].collider.max_hp);
                     }
+                    PowerUpKind::Shield => mobiles[a].collider.shield_until = now + SHIELD_FRAMES,
+                    PowerUpKind::Weapon(_) => {}
                 }
+                powerups[b].collider.collected = true;
+                events.push(GameEvent::PowerUpCollected(powerups[b].collider.kind));
             }
+            (ColliderID::Mobile(_), ColliderID::Trigger(b)) => inside[b] = true,
             (ColliderID::Projectile(a), ColliderID::Terrain(b)) => {
                 if terrains[b].collider.destructible {
+                    let before = terrains[b].collider.hp;
                     if terrains[b].collider.hp >= projs[a].hp {
                         terrains[b].collider.hp -= projs[a].hp;
                     } else {
                         terrains[b].collider.hp = 0;
                     }
+                    let dealt = before - terrains[b].collider.hp;
+                    popups.push(Popup::new(format!("-{}", dealt), terrains[b].position, now));
 
-                    terrains[b].sprite.animation_sm.input("hit", 0);
+                    // Crack the sprite as far as the damage so far says
+                    let terrain = &mut terrains[b];
+                    let anims = &mut terrain.sprite.animation_sm;
+                    let stage = terrain.collider.crack_stage(anims.len());
+                    if stage != anims.current_index() {
+                        anims.jump_to(stage, now);
+                    }
                 }
                 projs[a].hp = 0;
             }
+            (ColliderID::Projectile(a), ColliderID::Tile(b)) => {
+                events.push(GameEvent::TileShot(b));
+                projs[a].hp = 0;
+            }
+            (ColliderID::Projectile(a), ColliderID::Mobile(b)) if god_mode && b == player => {
+                projs[a].hp = 0;
+            }
             (ColliderID::Projectile(a), ColliderID::Mobile(b)) => {
-                if mobiles[b].collider.hp >= projs[a].hp {
-                    mobiles[b].collider.hp -= projs[a].hp;
-                } else {
-                    mobiles[b].collider.hp = 0;
+                let before = mobiles[b].collider.hp;
+                mobiles[b].collider.hurt(projs[a].hp, now);
+                let dealt = before - mobiles[b].collider.hp;
+                if dealt > 0 {
+                    popups.push(Popup::new(format!("-{}", dealt), mobiles[b].position, now));
+                }
+                let target = &mut mobiles[b].collider;
+                if !target.is_player && !target.is_boss {
+                    knock_back(target, projs[a].direction(), camera);
                 }
                 projs[a].hp = 0;
             }
             _ => {}
         }
     }
-    let player_is_alive = mobiles[0].collider.hp != 0;
+    for (i, (trigger, &now_inside)) in triggers.iter_mut().zip(inside.iter()).enumerate() {
+        if now_inside && !trigger.occupied {
+            events.push(GameEvent::TriggerEntered(i));
+        }
+        trigger.occupied = now_inside;
+    }
+    let hp_after = player_hp(mobiles);
+    if hp_after < hp_before {
+        events.push(GameEvent::PlayerDamaged {
+            amount: hp_before - hp_after,
+        });
+    }
+    let player_is_alive = hp_after != 0;
+    for terrain in terrains.iter().filter(|t| t.collider.hp == 0) {
+        events.push(GameEvent::TerrainDestroyed {
+            pos: terrain.position,
+            center: terrain.collider.rect.center(),
+        });
+    }
+    let mut points = 0;
+    for mobile in mobiles
+        .iter()
+        .filter(|m| m.collider.hp == 0 && !m.collider.is_player)
+    {
+        points += mobile.collider.score_value;
+        events.push(GameEvent::EnemyDestroyed {
+            pos: mobile.position,
+            center: mobile.collider.rect.center(),
+            points: mobile.collider.score_value,
+        });
+    }
     terrains.retain(|terrain| terrain.collider.hp > 0);
     let ori = mobiles.len();
     mobiles.retain(|mobile| mobile.collider.hp > 0 || mobile.collider.is_player);
     let new = mobiles.len();
     projs.retain(|proj| proj.hp > 0);
+    powerups.retain(|powerup| !powerup.collider.collected);
+
+    (player_is_alive, ori - new, points)
+}
+
+/*
+Shoves a shot mobile along direction by KNOCKBACK. Its speed relative to the
+camera (which is moving at camera) is then capped at MAX_KNOCKBACK_SPEED
+each way, so a volley can't fling it off screen.
+*/
+fn knock_back(mobile: &mut Mobile, direction: Vec2f, camera: Vec2f) {
+    let relative = mobile.velocity() + direction * KNOCKBACK - camera;
+    let capped = Vec2f(
+        relative.0.clamp(-MAX_KNOCKBACK_SPEED, MAX_KNOCKBACK_SPEED),
+        relative.1.clamp(-MAX_KNOCKBACK_SPEED, MAX_KNOCKBACK_SPEED),
+    );
+    mobile.set_velocity(capped + camera);
+}
 
-    (player_is_alive, ori - new)
+// Does id still name a live entity?
+fn resolves(
+    id: ColliderID,
+    terrains: &Arena<Entity<Terrain>>,
+    mobiles: &Arena<Entity<Mobile>>,
+) -> bool {
+    match id {
+        ColliderID::Terrain(t) => terrains.contains(t),
+        ColliderID::Mobile(m) => mobiles.contains(m),
+        _ => true,
+    }
 }
 
+/**
+ * The two rects behind a contact, looked up in the same collections that were
+ * passed to gather_contacts (so call this before handle_contact shuffles them).
+ */
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn contact_rects(
+    contact: &Contact,
+    terrains: &Arena<Entity<Terrain>>,
+    mobiles: &Arena<Entity<Mobile>>,
+    walls: &[Wall],
+    tiles: &[Rect],
+    projs: &[Projectile],
+    powerups: &[Entity<PowerUp>],
+    triggers: &[Trigger],
+) -> (Rect, Rect) {
+    let rect = |id| match id {
+        ColliderID::Terrain(i) => terrains[i].collider.rect(),
+        ColliderID::Mobile(i) => mobiles[i].collider.rect(),
+        ColliderID::Projectile(i) => projs[i].rect(),
+        ColliderID::Wall(i) => walls[i].rect(),
+        ColliderID::Tile(i) => tiles[i],
+        ColliderID::PowerUp(i) => powerups[i].collider.rect(),
+        ColliderID::Trigger(i) => triggers[i].rect(),
+    };
+    (rect(contact.a), rect(contact.b))
+}
+
+#[allow(clippy::single_match)]
 fn restitute(
-    _statics: &[Entity<Terrain>],
-    dynamics: &mut [Entity<Mobile>],
+    _statics: &Arena<Entity<Terrain>>,
+    dynamics: &mut Arena<Entity<Mobile>>,
     contacts: &mut [Contact],
+    camera: Vec2f,
 ) {
     contacts.sort_unstable_by_key(|c| -(c.mtv.0 * c.mtv.0 + c.mtv.1 * c.mtv.1));
 
     for contact in contacts.iter() {
         match (contact.a, contact.b) {
             (ColliderID::Mobile(ai), ColliderID::Wall(_)) => {
-                dynamics[ai].move_pos(
-                    -contact.mtv.0 * dynamics[ai].collider.vx.signum() as i32,
-                    -contact.mtv.1 * (dynamics[ai].collider.vy + 1.0).signum() as i32,
+                let mobile = &mut dynamics[ai];
+                mobile.move_pos(
+                    -contact.mtv.0 * (mobile.collider.vx - camera.0).signum() as i32,
+                    -contact.mtv.1 * (mobile.collider.vy - camera.1).signum() as i32,
                 );
 
+                // match the camera, which is scrolling camera pixels per frame
+                // need this or AI will get to the bottom of the screen
                 if contact.mtv.0 != 0 {
-                    dynamics[ai].collider.vx = 0.0;
+                    mobile.collider.vx = camera.0;
                 }
                 if contact.mtv.1 != 0 {
-                    // set vy = -1 because camera is scrolling up -1 pixels per frame
-                    // need this or AI will get to the bottom of the screen
-                    dynamics[ai].collider.vy = -1.0;
+                    mobile.collider.vy = camera.1;
                 }
             }
             /*
@@ -498,7 +1236,540 @@ fn restitute(
     }
 }
 
-fn separating_axis(ax1: i32, ax2: i32, bx1: i32, bx2: i32) -> bool {
-    assert!(ax1 <= ax2 && bx1 <= bx2);
-    ax2 <= bx1 || bx2 <= ax1
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::rock_entity;
+    use crate::atlas::{Atlas, TextureRegion};
+    use crate::popup::{update_popups, POPUP_TTL};
+    use crate::sprite::Sprite;
+    use crate::texture::Texture;
+    use crate::tiles::{Tile, Tilemap, Tileset};
+    use crate::trail::TRAIL_LEN;
+    use crate::types::Vec2i;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    // collider in an entity whose sprite is a blank picture the size of its hitbox
+    fn entity<T: Collider>(collider: T) -> Entity<T> {
+        let r = collider.rect();
+        let region = TextureRegion {
+            texture: Rc::new(Texture::new(image::RgbaImage::new(r.w as u32, r.h as u32))),
+            rect: Rect { x: 0, y: 0, ..r },
+        };
+        let position = Vec2i(r.x, r.y) - collider.hitbox_offset();
+        Entity::new(
+            Sprite::from_region(&region, 0, position),
+            position,
+            collider,
+        )
+    }
+
+    fn rect(x: i32, y: i32, w: u16, h: u16) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    // Everything there is to collide, with a player at (100, 100)
+    struct World {
+        terrains: Arena<Entity<Terrain>>,
+        mobiles: Arena<Entity<Mobile>>,
+        player: EntityId,
+        walls: Vec<Wall>,
+        tiles: Vec<Rect>,
+        projs: Vec<Projectile>,
+        powerups: Vec<Entity<PowerUp>>,
+        triggers: Vec<Trigger>,
+        popups: Vec<Popup>,
+    }
+
+    impl World {
+        fn new() -> Self {
+            let mut mobiles = Arena::new();
+            let player = mobiles.insert(entity(Mobile::player(100, 100, PLAYER_MAX_HP)));
+            Self {
+                terrains: Arena::new(),
+                mobiles,
+                player,
+                walls: vec![],
+                tiles: vec![],
+                projs: vec![],
+                powerups: vec![],
+                triggers: vec![],
+                popups: vec![],
+            }
+        }
+
+        fn player(&self) -> &Mobile {
+            &self.mobiles[self.player].collider
+        }
+
+        fn contacts(&self) -> Vec<Contact> {
+            let mut contacts = vec![];
+            gather_contacts(
+                &self.terrains,
+                &self.mobiles,
+                &self.walls,
+                &self.tiles,
+                &self.projs,
+                &self.powerups,
+                &self.triggers,
+                &mut contacts,
+            );
+            contacts
+        }
+
+        // One frame's collisions at frame now: whether the player lived, the
+        // kills and their points, and what happened
+        fn collide(&mut self, now: usize) -> (bool, usize, usize, Vec<GameEvent>) {
+            let mut contacts = self.contacts();
+            let mut events = vec![];
+            let (alive, kills, points) = handle_contact(
+                &mut self.terrains,
+                &mut self.mobiles,
+                self.player,
+                &mut self.projs,
+                &mut self.powerups,
+                &mut self.triggers,
+                &mut contacts,
+                &mut events,
+                &mut self.popups,
+                now,
+                Vec2f(0.0, 0.0),
+                false,
+            );
+            (alive, kills, points, events)
+        }
+    }
+
+    #[test]
+    fn mobile_on_a_solid_tile_touches_it() {
+        let texture = Rc::new(Texture::new(image::RgbaImage::new(32, 16)));
+        let tiles = vec![Tile { solid: false }, Tile { solid: true }];
+        let tileset = Rc::new(Tileset::new(tiles, &texture, HashMap::new()));
+        // Scrolled up the world a way, with its one solid tile in the middle
+        #[rustfmt::skip]
+        let map = vec![
+            0, 0, 0,
+            0, 1, 0,
+            0, 0, 0,
+        ];
+        let tilemap = Tilemap::new(Vec2i(0, -32), (3, 3), &tileset, map);
+        let view = rect(0, -40, 64, 64);
+        let solid = tilemap.solid_rects_in(view);
+        assert_eq!(solid, vec![rect(16, -16, 16, 16)]);
+
+        let mut world = World::new();
+        let enemy = entity(Mobile::enemy(rect(20, -10, 8, 8), 0.0, 0.0, 10));
+        let enemy = world.mobiles.insert(enemy);
+        world.tiles = solid;
+        assert_eq!(
+            world.contacts(),
+            vec![Contact {
+                a: ColliderID::Mobile(enemy),
+                b: ColliderID::Tile(0),
+                mtv: (0, 0),
+            }]
+        );
+    }
+
+    #[test]
+    fn heal_pickup_heals_the_player_and_goes() {
+        let mut world = World::new();
+        world.mobiles[world.player].collider.hp = 50;
+        let at = world.player().rect;
+        let heal = PowerUp::new(rect(at.x, at.y, 10, 10), PowerUpKind::Heal(20));
+        world.powerups.push(entity(heal));
+        let (alive, _, _, events) = world.collide(0);
+        assert!(alive);
+        assert_eq!(world.player().hp, 70);
+        assert!(world.powerups.is_empty());
+        assert_eq!(
+            events,
+            vec![GameEvent::PowerUpCollected(PowerUpKind::Heal(20))]
+        );
+    }
+
+    #[test]
+    fn no_damage_while_invulnerable() {
+        let mut world = World::new();
+        let at = world.player().rect;
+        // Ramming an enemy hurts, and starts the player's invulnerability
+        let enemy = Mobile::enemy(rect(at.x, at.y, 10, 10), 0.0, 0.0, 10);
+        world.mobiles.insert(entity(enemy));
+        world.collide(10);
+        assert_eq!(world.player().hp, PLAYER_MAX_HP - 30);
+        assert!(world.player().is_invulnerable(10 + INVULN_FRAMES - 1));
+
+        // So hitting a rock straight after does nothing...
+        let rock = Terrain::new(rect(at.x, at.y, 16, 16), 0, false, 10);
+        world.terrains.insert(entity(rock));
+        let (alive, _, _, events) = world.collide(11);
+        assert!(alive);
+        assert_eq!(world.player().hp, PLAYER_MAX_HP - 30);
+        assert!(events.is_empty());
+
+        // ...until the window's over
+        let (alive, _, _, _) = world.collide(10 + INVULN_FRAMES);
+        assert!(!alive);
+    }
+
+    // A player shot centered on (x, y), standing still
+    fn shot(x: i32, y: i32) -> Projectile {
+        Projectile {
+            team: Team::Player,
+            hp: 4,
+            ..Projectile::at(x, y, 0.0, 0.0, 0)
+        }
+    }
+
+    #[test]
+    fn hits_pop_up_their_damage_for_a_while() {
+        let mut world = World::new();
+        let rock = Terrain::new(rect(200, 20, 16, 16), 0, true, 10);
+        world.terrains.insert(entity(rock));
+        world.projs.push(shot(208, 28));
+        world.collide(5);
+        assert_eq!(world.popups.len(), 1);
+        assert_eq!(world.popups[0].text, "-4");
+        assert_eq!(world.popups[0].pos, Vec2i(200, 20));
+
+        update_popups(&mut world.popups, 5 + POPUP_TTL - 1, Vec2i(0, 0), 1);
+        assert_eq!(world.popups.len(), 1);
+        update_popups(&mut world.popups, 5 + POPUP_TTL, Vec2i(0, 0), 1);
+        assert!(world.popups.is_empty());
+    }
+
+    #[test]
+    fn contacts_are_every_overlap_in_order() {
+        let mut world = World::new();
+        let player = world.player;
+        // The player's hitbox is (104, 103) to (132, 122)
+        let enemy = entity(Mobile::enemy(rect(120, 110, 20, 20), 0.0, 0.0, 10));
+        let enemy = world.mobiles.insert(enemy);
+        let touching =
+            world
+                .terrains
+                .insert(entity(Terrain::new(rect(135, 125, 10, 10), 0, true, 10)));
+        let far = world
+            .terrains
+            .insert(entity(Terrain::new(rect(300, 300, 10, 10), 0, true, 10)));
+        world.walls.push(Wall::new(rect(90, 100, 16, 40)));
+        world.projs = vec![
+            shot(125, 115),
+            Projectile::at(110, 110, 0.0, 5.0, 0),
+            shot(302, 302),
+        ];
+        let contact = |a, b, mtv| Contact { a, b, mtv };
+        assert_eq!(
+            world.contacts(),
+            vec![
+                contact(
+                    ColliderID::Mobile(player),
+                    ColliderID::Mobile(enemy),
+                    (0, 0)
+                ),
+                contact(
+                    ColliderID::Mobile(enemy),
+                    ColliderID::Terrain(touching),
+                    (0, 0)
+                ),
+                contact(ColliderID::Mobile(player), ColliderID::Wall(0), (2, 0)),
+                contact(ColliderID::Projectile(0), ColliderID::Mobile(enemy), (0, 0)),
+                contact(
+                    ColliderID::Projectile(1),
+                    ColliderID::Mobile(player),
+                    (0, 0)
+                ),
+                contact(ColliderID::Projectile(2), ColliderID::Terrain(far), (0, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn grazing_the_players_sprite_misses_its_hitbox() {
+        let mut world = World::new();
+        // The player's sprite is (100, 100) to (136, 125), inset 4 and 3 for the hitbox
+        assert_eq!(world.mobiles[world.player].position, Vec2i(100, 100));
+        assert_eq!(world.player().rect, rect(104, 103, 28, 19));
+        world.projs = vec![
+            Projectile::at(101, 110, 0.0, 0.0, 0),
+            Projectile::at(134, 110, 0.0, 0.0, 0),
+            Projectile::at(118, 100, 0.0, 0.0, 0),
+            Projectile::at(118, 124, 0.0, 0.0, 0),
+        ];
+        assert!(world.contacts().is_empty());
+        world.collide(0);
+        assert_eq!(world.player().hp, PLAYER_MAX_HP);
+
+        // A pixel further in is a hit
+        world.projs = vec![Projectile::at(102, 110, 0.0, 0.0, 0)];
+        world.collide(0);
+        assert!(world.player().hp < PLAYER_MAX_HP);
+    }
+
+    #[test]
+    fn removing_an_earlier_entity_leaves_later_contacts_alone() {
+        let mut world = World::new();
+        let first = entity(Mobile::enemy(rect(10, 10, 10, 10), 0.0, 0.0, 10));
+        let first = world.mobiles.insert(first);
+        let second = entity(Mobile::enemy(rect(50, 10, 10, 10), 0.0, 0.0, 10));
+        let second = world.mobiles.insert(second);
+        world.projs = vec![shot(15, 15), shot(55, 15)];
+        let mut contacts = world.contacts();
+
+        // Something else does away with the first enemy before the contacts are handled
+        world.mobiles.remove(first);
+        let mut events = vec![];
+        handle_contact(
+            &mut world.terrains,
+            &mut world.mobiles,
+            world.player,
+            &mut world.projs,
+            &mut world.powerups,
+            &mut world.triggers,
+            &mut contacts,
+            &mut events,
+            &mut world.popups,
+            0,
+            Vec2f(0.0, 0.0),
+            false,
+        );
+        assert_eq!(world.mobiles[second].collider.hp, 6);
+        assert_eq!(world.player().hp, PLAYER_MAX_HP);
+        // Only the shot that hit something is used up
+        assert_eq!(world.projs.len(), 1);
+        assert_eq!(world.projs[0].rect.center(), Vec2i(15, 15));
+    }
+
+    #[test]
+    fn a_full_charge_beats_a_tap() {
+        let player = Mobile::player(100, 100, PLAYER_MAX_HP);
+        let tap = Projectile::new(&player, ScrollAxis::Vertical, 0);
+        let charged = Projectile::charged(&player, MAX_CHARGE, ScrollAxis::Vertical, 0);
+        assert!(charged.hp > tap.hp);
+        assert!(charged.rect.w > tap.rect.w && charged.rect.h > tap.rect.h);
+        assert_eq!(charged.hp, MAX_CHARGE_HP);
+        // Holding past the cap doesn't help
+        let held = Projectile::charged(&player, MAX_CHARGE * 2, ScrollAxis::Vertical, 0);
+        assert_eq!((held.hp, held.rect.w), (charged.hp, charged.rect.w));
+    }
+
+    #[test]
+    fn shields_block_hits_until_they_run_out() {
+        let mut world = World::new();
+        world.mobiles[world.player].collider.shield_until = 100;
+        let Vec2i(x, y) = world.player().rect.center();
+        world.projs.push(Projectile::at(x, y, 0.0, 0.0, 10));
+        world.collide(10);
+        assert_eq!(world.player().hp, PLAYER_MAX_HP);
+        assert!(world.player().is_shielded(99));
+
+        world.projs.push(Projectile::at(x, y, 0.0, 0.0, 100));
+        world.collide(100);
+        assert!(!world.player().is_shielded(100));
+        assert!(world.player().hp < PLAYER_MAX_HP);
+    }
+
+    #[test]
+    fn a_fatal_hit_uses_the_shield_up() {
+        let mut world = World::new();
+        world.mobiles[world.player].collider.shield_until = 100;
+        let at = world.player().rect;
+        let rock = Terrain::new(rect(at.x, at.y, 16, 16), 0, false, 10);
+        world.terrains.insert(entity(rock));
+        let (alive, _, _, _) = world.collide(10);
+        assert!(alive);
+        assert_eq!(world.player().hp, PLAYER_MAX_HP);
+        assert!(!world.player().is_shielded(11));
+    }
+
+    #[test]
+    fn rocks_crack_with_the_hp_they_have_lost() {
+        let texture = Rc::new(Texture::new(image::RgbaImage::new(64, 16)));
+        let descriptor = "rock, 0, 0, 16, 16\n\
+                          rock_cracked_1, 16, 0, 16, 16\n\
+                          rock_cracked_2, 32, 0, 16, 16\n\
+                          rock_cracked_3, 48, 0, 16, 16";
+        let atlas = Atlas::from_str(descriptor, &texture).unwrap();
+        let mut world = World::new();
+        let rock = world
+            .terrains
+            .insert(rock_entity(&atlas, 0, Vec2i(200, 20)));
+        let looks = |world: &World| world.terrains[rock].sprite.animation_sm.current_index();
+        assert_eq!(world.terrains[rock].collider.max_hp, 16);
+        assert_eq!(looks(&world), 0);
+
+        // Each shot takes a quarter of its hp, down to 25% after three
+        for (frame, expected) in [(1, 1), (2, 2), (3, 3)] {
+            world.projs.push(shot(208, 28));
+            world.collide(frame);
+            assert_eq!(looks(&world), expected);
+        }
+        assert_eq!(world.terrains[rock].collider.hp, 4);
+    }
+
+    #[test]
+    fn trails_keep_only_the_latest_points() {
+        let player = Mobile::player(100, 300, PLAYER_MAX_HP);
+        let mut shot = Projectile::new(&player, ScrollAxis::Vertical, 0);
+        let start = shot.rect.center();
+        let trail =
+            |shot: &Projectile| -> Vec<Vec2i> { shot.trail.as_ref().unwrap().points().collect() };
+        shot.fly(1.0);
+        shot.fly(1.0);
+        assert_eq!(trail(&shot), vec![start + Vec2i(0, -10), start]);
+
+        // Ten frames on, only the last TRAIL_LEN places it's been are left, newest first
+        for _ in 2..10 {
+            shot.fly(1.0);
+        }
+        let expected: Vec<Vec2i> = (10 - TRAIL_LEN as i32..10)
+            .rev()
+            .map(|frame| start + Vec2i(0, -10 * frame))
+            .collect();
+        assert_eq!(trail(&shot), expected);
+        assert_eq!(shot.rect.center(), start + Vec2i(0, -100));
+    }
+
+    #[test]
+    fn half_armor_halves_a_shots_damage() {
+        let mut world = World::new();
+        let plain = entity(Mobile::enemy(rect(10, 10, 10, 10), 0.0, 0.0, 10));
+        let plain = world.mobiles.insert(plain);
+        let mut armored = Mobile::enemy(rect(50, 10, 10, 10), 0.0, 0.0, 10);
+        armored.armor = 50;
+        let armored = world.mobiles.insert(entity(armored));
+        world.projs = vec![shot(15, 15), shot(55, 15)];
+        world.collide(0);
+        assert_eq!(world.mobiles[plain].collider.hp, 6);
+        assert_eq!(world.mobiles[armored].collider.hp, 8);
+
+        // Odd damage rounds up, and armor can't go past blocking everything
+        let mut mobile = Mobile::enemy(rect(0, 0, 10, 10), 0.0, 0.0, 10);
+        mobile.armor = 50;
+        assert_eq!(mobile.armored(5), 3);
+        mobile.armor = 200;
+        assert_eq!(mobile.armored(usize::MAX), 0);
+    }
+
+    #[test]
+    fn flying_through_a_trigger_notices_without_pushing() {
+        let mut world = World::new();
+        let player = world.player;
+        let at = world.player().rect;
+        // Half over the player, the way a wall there would push it out
+        let zone = Trigger::new(rect(at.x + 10, at.y, 40, 40), TriggerKind::Bonus(50));
+        world.triggers.push(zone);
+        assert_eq!(
+            world.contacts(),
+            vec![Contact {
+                a: ColliderID::Mobile(player),
+                b: ColliderID::Trigger(0),
+                mtv: (0, 0),
+            }]
+        );
+        let (alive, _, _, events) = world.collide(0);
+        assert!(alive);
+        assert_eq!(world.player().rect, at);
+        assert_eq!(world.player().hp, PLAYER_MAX_HP);
+        assert_eq!(events, vec![GameEvent::TriggerEntered(0)]);
+
+        // Staying inside isn't entering again
+        let (_, _, _, events) = world.collide(1);
+        assert!(events.is_empty());
+        assert_eq!(world.player().rect, at);
+    }
+
+    #[test]
+    fn shooting_a_rock_to_pieces_reports_where_it_was() {
+        let mut world = World::new();
+        let rock = Terrain::new(rect(200, 20, 16, 16), 0, true, 4);
+        let rock = world.terrains.insert(entity(rock));
+        world.projs.push(shot(208, 28));
+        let (_, kills, _, events) = world.collide(0);
+        assert!(!world.terrains.contains(rock));
+        assert!(world.projs.is_empty());
+        // Rocks aren't kills, just terrain out of the way
+        assert_eq!(kills, 0);
+        assert_eq!(
+            events,
+            vec![GameEvent::TerrainDestroyed {
+                pos: Vec2i(200, 20),
+                center: Vec2i(208, 28),
+            }]
+        );
+    }
+
+    #[test]
+    fn shots_knock_enemies_back_but_only_so_far() {
+        let mut world = World::new();
+        let id = world
+            .mobiles
+            .insert(entity(Mobile::enemy(rect(200, 20, 16, 16), 0.0, 1.0, 100)));
+        // A shot flying right into its middle
+        let hit = |world: &mut World| {
+            world.projs.push(Projectile {
+                vx: 8.0,
+                ..shot(208, 28)
+            });
+            world.collide(0);
+        };
+        hit(&mut world);
+        assert_eq!(world.mobiles[id].collider.velocity(), Vec2f(KNOCKBACK, 1.0));
+
+        // However many land, it can't be flung off screen
+        for _ in 0..10 {
+            hit(&mut world);
+        }
+        let Vec2f(vx, vy) = world.mobiles[id].collider.velocity();
+        assert_eq!((vx, vy), (MAX_KNOCKBACK_SPEED, 1.0));
+    }
+
+    #[test]
+    fn the_player_is_found_by_id_not_by_place() {
+        let mut world = World::new();
+        // An enemy in the first slot, with the player moved in after it
+        let mut mobiles = Arena::new();
+        let enemy = mobiles.insert(entity(Mobile::enemy(rect(200, 20, 16, 16), 0.0, 0.0, 10)));
+        world.player = mobiles.insert(entity(Mobile::player(100, 100, PLAYER_MAX_HP)));
+        world.mobiles = mobiles;
+
+        // Shooting the enemy dead isn't the player dying or being hurt
+        world.projs.push(Projectile {
+            hp: 10,
+            ..shot(208, 28)
+        });
+        let (alive, kills, _, events) = world.collide(0);
+        assert!(alive);
+        assert_eq!(kills, 1);
+        assert!(!world.mobiles.contains(enemy));
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, GameEvent::PlayerDamaged { .. })));
+
+        // But a shot into the player is
+        let Vec2i(x, y) = world.player().rect.center();
+        world.projs.push(Projectile::at(x, y, 0.0, 0.0, 0));
+        let (_, _, _, events) = world.collide(1);
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, GameEvent::PlayerDamaged { .. })));
+        assert!(world.player().hp < PLAYER_MAX_HP);
+    }
+
+    #[test]
+    fn kills_are_worth_their_own_score_values() {
+        let mut world = World::new();
+        for (x, score_value) in [(200, 3), (260, 5)] {
+            let enemy = Mobile {
+                score_value,
+                ..Mobile::enemy(rect(x, 20, 16, 16), 0.0, 0.0, 4)
+            };
+            world.mobiles.insert(entity(enemy));
+            world.projs.push(shot(x + 8, 28));
+        }
+        let (alive, kills, points, _) = world.collide(0);
+        assert!(alive);
+        assert_eq!((kills, points), (2, 8));
+    }
 }
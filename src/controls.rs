@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use winit::event::VirtualKeyCode;
+use winit_input_helper::WinitInputHelper;
+
+/*
+Which key does what. The input layer asks Controls whether an Action is held or
+pressed instead of checking keycodes directly, so players can rebind keys.
+
+Controls are saved as plain text, one binding per line:
+
+    MoveLeft = A
+    Fire = Space
+
+Lines starting with # are comments. Actions left out of a file keep their
+default key.
+*/
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Fire,
+    Pause,
+    Quit,
+    Start,
+    Bomb,
+    Mute,
+    Aim,
+    Dash,
+}
+
+impl Action {
+    pub const ALL: [Action; 12] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Fire,
+        Action::Pause,
+        Action::Quit,
+        Action::Start,
+        Action::Bomb,
+        Action::Mute,
+        Action::Aim,
+        Action::Dash,
+    ];
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|a| format!("{:?}", a) == name)
+    }
+}
+
+// The keys a binding can name in a controls file
+const BINDABLE_KEYS: [VirtualKeyCode; 56] = {
+    use VirtualKeyCode::*;
+    [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0, Key1,
+        Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Left, Right, Up, Down, Space, Return,
+        Escape, Tab, Back, LShift, RShift, LControl, RControl, LAlt, RAlt, Comma, Period, Slash,
+        Semicolon, Apostrophe,
+    ]
+};
+
+fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    BINDABLE_KEYS
+        .iter()
+        .copied()
+        .find(|k| format!("{:?}", k) == name)
+}
+
+/// Ways loading controls from a file can fail
+#[derive(Debug)]
+pub enum ControlsError {
+    Io(std::io::Error),
+    /// A line that isn't "Action = Key", at this (1-based) line number
+    BadLine(usize, String),
+    UnknownAction(usize, String),
+    UnknownKey(usize, String),
+}
+
+impl fmt::Display for ControlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControlsError::Io(e) => write!(f, "couldn't read controls: {}", e),
+            ControlsError::BadLine(line, text) => {
+                write!(f, "line {}: expected Action = Key, got {:?}", line, text)
+            }
+            ControlsError::UnknownAction(line, name) => {
+                write!(f, "line {}: no action called {:?}", line, name)
+            }
+            ControlsError::UnknownKey(line, name) => {
+                write!(f, "line {}: no key called {:?}", line, name)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for ControlsError {
+    fn from(e: std::io::Error) -> Self {
+        ControlsError::Io(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Controls {
+    keys: HashMap<Action, VirtualKeyCode>,
+}
+
+impl Default for Controls {
+    // The original hard-coded controls
+    fn default() -> Self {
+        let keys = [
+            (Action::MoveLeft, VirtualKeyCode::Left),
+            (Action::MoveRight, VirtualKeyCode::Right),
+            (Action::MoveUp, VirtualKeyCode::Up),
+            (Action::MoveDown, VirtualKeyCode::Down),
+            (Action::Fire, VirtualKeyCode::Space),
+            (Action::Pause, VirtualKeyCode::P),
+            (Action::Quit, VirtualKeyCode::Escape),
+            (Action::Start, VirtualKeyCode::Return),
+            (Action::Bomb, VirtualKeyCode::X),
+            (Action::Mute, VirtualKeyCode::M),
+            (Action::Aim, VirtualKeyCode::LShift),
+            (Action::Dash, VirtualKeyCode::Z),
+        ];
+        Self {
+            keys: keys.iter().copied().collect(),
+        }
+    }
+}
+
+impl Controls {
+    pub fn key(&self, action: Action) -> VirtualKeyCode {
+        self.keys[&action]
+    }
+
+    pub fn bind(&mut self, action: Action, key: VirtualKeyCode) {
+        self.keys.insert(action, key);
+    }
+
+    /// Is the key for this action held down?
+    pub fn held(&self, input: &WinitInputHelper, action: Action) -> bool {
+        input.key_held(self.key(action))
+    }
+
+    /// Did the key for this action go down this frame?
+    pub fn pressed(&self, input: &WinitInputHelper, action: Action) -> bool {
+        input.key_pressed(self.key(action))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ControlsError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    // Binds the action and key with these names, as read from (1-based) line
+    pub fn bind_names(
+        &mut self,
+        line: usize,
+        action: &str,
+        key: &str,
+    ) -> Result<(), ControlsError> {
+        let action = Action::from_name(action)
+            .ok_or_else(|| ControlsError::UnknownAction(line, action.to_string()))?;
+        let key =
+            key_from_name(key).ok_or_else(|| ControlsError::UnknownKey(line, key.to_string()))?;
+        self.bind(action, key);
+        Ok(())
+    }
+}
+
+impl FromStr for Controls {
+    type Err = ControlsError;
+
+    /// Parses the format written by to_string, on top of the defaults
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut controls = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (action, key) = match line.find('=') {
+                Some(eq) => (line[..eq].trim(), line[eq + 1..].trim()),
+                None => return Err(ControlsError::BadLine(i + 1, line.to_string())),
+            };
+            controls.bind_names(i + 1, action, key)?;
+        }
+        Ok(controls)
+    }
+}
+
+impl fmt::Display for Controls {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for action in Action::ALL.iter() {
+            writeln!(f, "{:?} = {:?}", action, self.key(*action))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::{DeviceId, ElementState, Event, KeyboardInput, WindowEvent};
+    use winit::window::WindowId;
+
+    // Input as it stands once key has gone down
+    fn pressing(key: VirtualKeyCode) -> WinitInputHelper {
+        let mut input = WinitInputHelper::new();
+        // Safe enough: the dummy ids never get handed back to winit
+        let (window_id, device_id) = unsafe { (WindowId::dummy(), DeviceId::dummy()) };
+        #[allow(deprecated)]
+        let event: Event<()> = Event::WindowEvent {
+            window_id,
+            event: WindowEvent::KeyboardInput {
+                device_id,
+                input: KeyboardInput {
+                    scancode: 0,
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    modifiers: Default::default(),
+                },
+                is_synthetic: false,
+            },
+        };
+        input.update(event);
+        input
+    }
+
+    #[test]
+    fn rebound_action_follows_its_new_key() {
+        let controls = "# lefties\nMoveLeft = A\n".parse::<Controls>().unwrap();
+        assert_eq!(controls.key(Action::MoveLeft), VirtualKeyCode::A);
+        assert!(controls.held(&pressing(VirtualKeyCode::A), Action::MoveLeft));
+        assert!(controls.pressed(&pressing(VirtualKeyCode::A), Action::MoveLeft));
+        assert!(!controls.held(&pressing(VirtualKeyCode::Left), Action::MoveLeft));
+        // Everything else keeps its default
+        assert!(controls.held(&pressing(VirtualKeyCode::Right), Action::MoveRight));
+    }
+}
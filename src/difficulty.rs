@@ -0,0 +1,81 @@
+/*
+How hard the game is. Picked once at startup and fixed for the whole session;
+everything here scales the Normal tuning, which is how the game has always
+played.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        match name.to_ascii_lowercase().as_str() {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    // One step up or down, stopping at the ends
+    pub fn harder(self) -> Difficulty {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            _ => Difficulty::Hard,
+        }
+    }
+
+    pub fn easier(self) -> Difficulty {
+        match self {
+            Difficulty::Hard => Difficulty::Normal,
+            _ => Difficulty::Easy,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    // Scales the number of enemies in a Normal wave
+    pub fn enemy_count(&self, count: usize) -> usize {
+        match self {
+            Difficulty::Easy => count * 3 / 4,
+            Difficulty::Normal => count,
+            Difficulty::Hard => count * 3 / 2,
+        }
+    }
+
+    // Scales a Normal enemy's hp
+    pub fn enemy_hp(&self, hp: usize) -> usize {
+        match self {
+            Difficulty::Easy => hp * 3 / 4,
+            Difficulty::Normal => hp,
+            Difficulty::Hard => hp * 3 / 2,
+        }
+    }
+
+    // Each rock slot in a rock field is filled with chance 1 in this
+    pub fn rock_chance(&self) -> usize {
+        match self {
+            Difficulty::Easy => 8,
+            Difficulty::Normal => 6,
+            Difficulty::Hard => 4,
+        }
+    }
+
+    // How many pixels the camera climbs per frame while it's moving
+    pub fn scroll_speed(&self) -> i32 {
+        match self {
+            Difficulty::Easy | Difficulty::Normal => 1,
+            Difficulty::Hard => 2,
+        }
+    }
+}
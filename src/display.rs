@@ -0,0 +1,66 @@
+use crate::types::Rect;
+
+/*
+The game always draws into a fixed WIDTH x HEIGHT framebuffer. The window can
+be any size: pixels scales the framebuffer up by the biggest whole number that
+fits and centers it, leaving black bars around it instead of stretching.
+*/
+
+// How many window pixels wide each framebuffer pixel is when the window opens
+pub const WINDOW_SCALE: u32 = 2;
+
+/**
+ * Where a framebuffer of size fb lands in a window of size window (both in
+ * physical pixels), matching what pixels does when it renders: whole-number
+ * scaling, at least 1x, centered.
+ */
+pub fn letterbox(fb: (u32, u32), window: (u32, u32)) -> Rect {
+    let scale = (window.0 / fb.0).min(window.1 / fb.1).max(1);
+    let (w, h) = (fb.0 * scale, fb.1 * scale);
+    Rect {
+        x: (window.0 as i32 - w as i32) / 2,
+        y: (window.1 as i32 - h as i32) / 2,
+        w: w as u16,
+        h: h as u16,
+    }
+}
+
+/**
+ * The scale a window of size window shows the framebuffer at, in logical
+ * pixels (what WINDOW_SCALE counts) on a display with scale_factor physical
+ * pixels to each logical one.
+ */
+pub fn shown_scale(fb: (u32, u32), window: (u32, u32), scale_factor: f64) -> u32 {
+    let physical = letterbox(fb, window).w as u32 / fb.0;
+    (physical as f64 / scale_factor).round().max(1.0) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letterbox_centers_the_biggest_whole_scale_that_fits() {
+        // Room for 3x across but only 2x down, so bars on all four sides
+        let shown = letterbox((320, 576), (1000, 1300));
+        assert_eq!(
+            shown,
+            Rect {
+                x: 180,
+                y: 74,
+                w: 640,
+                h: 1152
+            }
+        );
+        // Too small a window still gets 1x, hanging off the edges
+        let shown = letterbox((320, 576), (300, 500));
+        assert_eq!((shown.x, shown.y, shown.w, shown.h), (-10, -38, 320, 576));
+    }
+
+    #[test]
+    fn shown_scale_is_in_logical_pixels() {
+        assert_eq!(shown_scale((320, 576), (1000, 1300), 1.0), 2);
+        assert_eq!(shown_scale((320, 576), (1280, 2304), 2.0), 2);
+        assert_eq!(shown_scale((320, 576), (320, 576), 2.0), 1);
+    }
+}
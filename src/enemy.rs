@@ -0,0 +1,308 @@
+use rand::Rng;
+
+use crate::arena::Arena;
+use crate::axis::ScrollAxis;
+use crate::collision::{Mobile, Terrain};
+use crate::entity::Entity;
+use crate::types::{Vec2f, Vec2i};
+
+/*
+How an ordinary enemy flies. Every kind keeps up with the camera on top of its
+own movement, and all of them shoot back once they're on screen; the kind only
+decides where they go.
+
+Steering is worked out as if the screen scrolled up, with "above" meaning
+ahead, then turned to match the axis it really scrolls along.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum EnemyKind {
+    // Homes in on the player and dodges terrain
+    #[default]
+    Chaser,
+    // Drops into view, then sweeps back and forth across the screen
+    Strafer,
+    // Hangs back near the top of the screen, lining up shots at the player
+    Shooter,
+}
+
+/*
+The shape a wave of enemies arrives in. Offsets are from the top left corner of
+the formation's lead enemy; each wave picks a shape at random.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Formation {
+    // Side by side in a row
+    Line,
+    // A row with the middle out in front and the ends trailing back
+    VShape,
+    // One behind the other
+    Column,
+}
+
+// Distance between neighbouring enemies in a formation
+const FORMATION_SPACING: i32 = 40;
+
+impl Formation {
+    pub fn random<R: Rng>(rng: &mut R) -> Formation {
+        match rng.gen_range(0..3) {
+            0 => Formation::Line,
+            1 => Formation::VShape,
+            _ => Formation::Column,
+        }
+    }
+
+    /**
+     * Where each of n enemies goes. Rows are centred on x = 0 (so even sized
+     * rows straddle it) and extend up, off the top of the screen, from y = 0.
+     */
+    pub fn offsets(self, n: usize) -> Vec<Vec2i> {
+        let n = n as i32;
+        (0..n)
+            .map(|i| {
+                // Twice the distance from the middle of the row, in spaces
+                let from_mid = 2 * i - (n - 1);
+                match self {
+                    Formation::Line => Vec2i(from_mid * FORMATION_SPACING / 2, 0),
+                    Formation::VShape => Vec2i(
+                        from_mid * FORMATION_SPACING / 2,
+                        -from_mid.abs() * FORMATION_SPACING / 4,
+                    ),
+                    Formation::Column => Vec2i(0, -i * FORMATION_SPACING),
+                }
+            })
+            .collect()
+    }
+}
+
+// What enemies steer by, gathered once per frame
+pub struct Surroundings<'a> {
+    pub player_pos: Vec2i,
+    pub terrains: &'a Arena<Entity<Terrain>>,
+    pub scroll: Vec2i,
+    // How fast the camera is moving
+    pub camera: Vec2f,
+    pub axis: ScrollAxis,
+    // How many frames this update covers, to scale accelerations by
+    pub frames: f32,
+}
+
+// Horizontal speed of a strafer's sweep
+const STRAFE_SPEED: f32 = 2.0;
+// How far below the top of the screen strafers and shooters settle
+const STRAFE_Y: i32 = 120;
+const HANG_BACK_Y: i32 = 40;
+// Fastest a shooter slides sideways to line up with the player
+const SHOOTER_MAX_VX: f32 = 1.5;
+
+impl EnemyKind {
+    // Half the enemies are chasers, the rest split evenly
+    pub fn random<R: Rng>(rng: &mut R) -> EnemyKind {
+        match rng.gen_range(0..4) {
+            0 | 1 => EnemyKind::Chaser,
+            2 => EnemyKind::Strafer,
+            _ => EnemyKind::Shooter,
+        }
+    }
+
+    // Points for destroying one, before any combo multiplier; the ones that
+    // keep their distance are harder to pin down
+    pub fn score_value(self) -> usize {
+        match self {
+            EnemyKind::Chaser => 1,
+            EnemyKind::Strafer => 2,
+            EnemyKind::Shooter => 3,
+        }
+    }
+
+    // Frames between shots, given the usual enemy cooldown
+    pub fn fire_cooldown(self, base: usize) -> usize {
+        match self {
+            EnemyKind::Shooter => base / 2,
+            _ => base,
+        }
+    }
+
+    // The velocity enemy wants for this frame
+    pub fn steer(self, enemy: &Entity<Mobile>, around: &Surroundings) -> Vec2f {
+        let vel = match self {
+            EnemyKind::Chaser => chase(enemy, around),
+            EnemyKind::Strafer => strafe(&enemy.collider, around),
+            EnemyKind::Shooter => hang_back(&enemy.collider, around),
+        };
+        around.axis.turn_f(vel)
+    }
+}
+
+// Moves v step closer to target, without overshooting
+fn approach(v: f32, target: f32, step: f32) -> f32 {
+    if v > target {
+        (v - step).max(target)
+    } else {
+        (v + step).min(target)
+    }
+}
+
+// Falls at this speed until it's dropped to y, then keeps level with the camera
+fn settle_vy(enemy: &Mobile, y: i32, around: &Surroundings) -> f32 {
+    let base_vy = around.axis.unturn_f(around.camera).1;
+    if around.axis.depth(around.scroll, enemy.rect) < y {
+        base_vy + 2.0
+    } else {
+        base_vy
+    }
+}
+
+fn chase(enemy: &Entity<Mobile>, around: &Surroundings) -> Vec2f {
+    let axis = around.axis;
+    let mut vel = axis.unturn_f(enemy.collider.velocity());
+
+    let frames = around.frames;
+
+    // Accelerate away from nearby terrain
+    for terrain in around.terrains.iter() {
+        let d = axis.unturn_f(Vec2f::from(terrain.position - enemy.position));
+
+        if d.0.abs() < 50.0 && d.1.abs() < 50.0 {
+            if d.0.abs() > d.1.abs() {
+                vel.0 -= 5.0 / d.0 * frames;
+            } else {
+                vel.1 -= 5.0 / d.1 * frames;
+            }
+        }
+    }
+
+    let to_player = axis.unturn_f(Vec2f::from(around.player_pos - enemy.position));
+
+    // Accelerate x towards player, harder the further away it is (up to a point)
+    let pull = to_player * (1.0 / 50.0);
+    let max_ax = 0.07;
+    vel.0 += pull.0.clamp(-max_ax, max_ax) * frames;
+
+    // Accelerate y upward if enemy is below player, downward if above
+    let max_vy = 5.0;
+    if to_player.1 < 0.0 {
+        vel.1 = (vel.1 - 0.03 * frames).max(-max_vy);
+    }
+    if to_player.1 > 0.0 {
+        vel.1 = (vel.1 + 0.03 * frames).min(max_vy);
+    }
+
+    // Decelerate naturally (due to friction or something)
+    // Note that base speed is the camera's so enemies keep up with it
+    let base_vy = axis.unturn_f(around.camera).1;
+    let friction = 0.01 * frames;
+    Vec2f(
+        approach(vel.0, 0.0, friction),
+        approach(vel.1, base_vy, friction),
+    )
+}
+
+// Bounces between the screen edges like the boss does
+fn strafe(enemy: &Mobile, around: &Surroundings) -> Vec2f {
+    let axis = around.axis;
+    let (left, right) = axis.span_across(around.scroll, enemy.rect);
+    let heading_left = if left <= 0 {
+        false
+    } else if right >= axis.breadth() {
+        true
+    } else {
+        axis.unturn_f(enemy.velocity()).0 < 0.0
+    };
+    let vx = if heading_left {
+        -STRAFE_SPEED
+    } else {
+        STRAFE_SPEED
+    };
+    Vec2f(vx, settle_vy(enemy, STRAFE_Y, around))
+}
+
+fn hang_back(enemy: &Mobile, around: &Surroundings) -> Vec2f {
+    let to_player = Vec2f::from(around.player_pos - Vec2i(enemy.rect.x, enemy.rect.y));
+    let dx = around.axis.unturn_f(to_player).0;
+    let vx = (dx * 0.05).clamp(-SHOOTER_MAX_VX, SHOOTER_MAX_VX);
+    Vec2f(vx, settle_vy(enemy, HANG_BACK_Y, around))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::TextureRegion;
+    use crate::sprite::Sprite;
+    use crate::texture::Texture;
+    use crate::types::Rect;
+    use std::rc::Rc;
+
+    // A still enemy of kind with its top left at pos, on a blank sprite
+    fn enemy(kind: EnemyKind, pos: Vec2i) -> Entity<Mobile> {
+        let rect = Rect {
+            x: pos.0,
+            y: pos.1,
+            w: 20,
+            h: 20,
+        };
+        let region = TextureRegion {
+            texture: Rc::new(Texture::new(image::RgbaImage::new(20, 20))),
+            rect: Rect { x: 0, y: 0, ..rect },
+        };
+        let mobile = Mobile {
+            kind,
+            ..Mobile::enemy(rect, 0.0, 0.0, 10)
+        };
+        Entity::new(Sprite::from_region(&region, 0, pos), pos, mobile)
+    }
+
+    // The horizontal velocity enemy picks each frame for frames frames, with
+    // the player standing still at player_pos and the camera stopped
+    fn fly(enemy: &mut Entity<Mobile>, player_pos: Vec2i, frames: usize) -> Vec<f32> {
+        let terrains = Arena::new();
+        let around = Surroundings {
+            player_pos,
+            terrains: &terrains,
+            scroll: Vec2i(0, 0),
+            camera: Vec2f(0.0, 0.0),
+            axis: ScrollAxis::Vertical,
+            frames: 1.0,
+        };
+        (0..frames)
+            .map(|_| {
+                let vel = enemy.collider.kind.steer(enemy, &around);
+                enemy.collider.set_velocity(vel);
+                let Vec2i(dx, dy) = enemy.collider.step(1.0);
+                enemy.move_pos(dx, dy);
+                vel.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strafers_sweep_back_and_forth() {
+        let mut strafer = enemy(EnemyKind::Strafer, Vec2i(100, STRAFE_Y));
+        let vxs = fly(&mut strafer, Vec2i(100, 300), 600);
+        let turns = vxs.windows(2).filter(|w| w[0] * w[1] < 0.0).count();
+        assert!(turns >= 2, "only turned {} times", turns);
+        assert!(vxs.iter().all(|vx| vx.abs() == STRAFE_SPEED));
+    }
+
+    #[test]
+    fn chasers_close_in_on_the_player() {
+        let player_pos = Vec2i(300, 100);
+        let mut chaser = enemy(EnemyKind::Chaser, Vec2i(20, 100));
+        let vxs = fly(&mut chaser, player_pos, 60);
+        assert!(vxs.iter().all(|&vx| vx > 0.0));
+        assert!(vxs.windows(2).all(|w| w[1] >= w[0]));
+        assert!(chaser.position.0 > 20);
+    }
+
+    #[test]
+    fn v_shapes_are_symmetric() {
+        let offsets = Formation::VShape.offsets(5);
+        assert_eq!(offsets.len(), 5);
+        for (a, b) in offsets.iter().zip(offsets.iter().rev()) {
+            assert_eq!(a.0, -b.0);
+            assert_eq!(a.1, b.1);
+        }
+        // The middle one leads, and it's centered
+        assert_eq!(offsets[2], Vec2i(0, 0));
+        assert!(offsets.iter().all(|o| o.1 <= 0));
+    }
+}
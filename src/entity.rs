@@ -12,6 +12,8 @@ pub struct Entity<T: Collider> {
 One issue is that sprite, entity and collider all have its own position info, and if these positions are out of alignment, it is almost certain that something would go wrong.
 
 Hence, when we initialize the entity, we must align the position informations.
+The collider may be inset from the sprite by its hitbox_offset. Changing the
+collider's size goes through resize, which moves everything together.
 */
 
 impl<T: Collider> Entity<T> {
@@ -35,6 +37,19 @@ impl<T: Collider> Entity<T> {
         self.position.1 += dy;
     }
 
+    /**
+     * Gives the collider a new size, keeping it centered where it was. The
+     * sprite and position move with the collider's top left, so it stays
+     * hitbox_offset in from the sprite.
+     */
+    pub fn resize(&mut self, w: u16, h: u16) {
+        let r = self.collider.rect();
+        self.position.0 += (r.w as i32 - w as i32) / 2;
+        self.position.1 += (r.h as i32 - h as i32) / 2;
+        self.collider.set_size(w, h);
+        self.align();
+    }
+
     fn align(&mut self) {
         if self.sprite.position.0 != self.position.0 {
             self.sprite.position.0 = self.position.0;
@@ -42,6 +57,65 @@ impl<T: Collider> Entity<T> {
         if self.sprite.position.1 != self.position.1 {
             self.sprite.position.1 = self.position.1;
         }
-        self.collider.set_pos(self.position.0, self.position.1);
+        let offset = self.collider.hitbox_offset();
+        self.collider
+            .set_pos(self.position.0 + offset.0, self.position.1 + offset.1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::TextureRegion;
+    use crate::collision::{Mobile, PLAYER_MAX_HP};
+    use crate::texture::Texture;
+    use crate::types::Rect;
+    use std::rc::Rc;
+
+    // The player, whose hitbox is inset from its sprite, drawn with a blank sprite
+    fn player_entity() -> Entity<Mobile> {
+        let region = TextureRegion {
+            texture: Rc::new(Texture::new(image::RgbaImage::new(36, 25))),
+            rect: Rect {
+                x: 0,
+                y: 0,
+                w: 36,
+                h: 25,
+            },
+        };
+        let position = Vec2i(100, 200);
+        let sprite = Sprite::from_region(&region, 0, position);
+        Entity::new(sprite, position, Mobile::player(0, 0, PLAYER_MAX_HP))
+    }
+
+    // Sprite, position and collider all agree on where the entity is
+    fn assert_aligned(entity: &Entity<Mobile>) {
+        let r = entity.collider.rect();
+        assert_eq!(entity.sprite.position, entity.position);
+        assert_eq!(
+            Vec2i(r.x, r.y),
+            entity.position + entity.collider.hitbox_offset()
+        );
+    }
+
+    #[test]
+    fn resizing_keeps_the_hitbox_centered_and_in_line_with_the_sprite() {
+        let mut entity = player_entity();
+        assert_aligned(&entity);
+        let before = entity.collider.rect();
+
+        entity.resize(before.w + 10, before.h);
+        let after = entity.collider.rect();
+        assert_eq!((after.w, after.h), (before.w + 10, before.h));
+        assert_eq!(after.center(), before.center());
+        assert_aligned(&entity);
+
+        // And it all still moves together afterwards
+        entity.move_pos(3, -4);
+        assert_aligned(&entity);
+        assert_eq!(
+            entity.collider.rect().center(),
+            before.center() + Vec2i(3, -4)
+        );
     }
 }
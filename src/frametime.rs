@@ -0,0 +1,68 @@
+/*
+How long frames are taking, averaged over the last few so the number on screen
+doesn't flicker. Samples go in a fixed ring buffer, so recording one never
+allocates.
+*/
+
+// How many frames the average covers
+const SAMPLES: usize = 30;
+
+#[derive(Default)]
+pub struct FrameTimer {
+    // Seconds each recent frame took; only the first filled are real
+    samples: [f64; SAMPLES],
+    // Where the next sample goes, overwriting the oldest once full
+    next: usize,
+    filled: usize,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a frame that took secs seconds, forgetting the oldest if full
+    pub fn record(&mut self, secs: f64) {
+        self.samples[self.next] = secs;
+        self.next = (self.next + 1) % SAMPLES;
+        self.filled = (self.filled + 1).min(SAMPLES);
+    }
+
+    // Mean seconds per frame over the recorded frames, None before the first
+    pub fn average(&self) -> Option<f64> {
+        if self.filled == 0 {
+            return None;
+        }
+        Some(self.samples[..self.filled].iter().sum::<f64>() / self.filled as f64)
+    }
+
+    // Frames per second going by the average, None until there's one to go by
+    pub fn fps(&self) -> Option<f64> {
+        self.average()
+            .filter(|&secs| secs > 0.0)
+            .map(|secs| 1.0 / secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_only_the_most_recent_frames() {
+        let mut timer = FrameTimer::new();
+        assert_eq!(timer.average(), None);
+        assert_eq!(timer.fps(), None);
+        timer.record(0.01);
+        timer.record(0.03);
+        assert!((timer.average().unwrap() - 0.02).abs() < 1e-12);
+        assert!((timer.fps().unwrap() - 50.0).abs() < 1e-9);
+
+        // A full window of quick frames pushes the slow ones out
+        for _ in 0..SAMPLES {
+            timer.record(0.025);
+        }
+        assert!((timer.average().unwrap() - 0.025).abs() < 1e-12);
+        assert!((timer.fps().unwrap() - 40.0).abs() < 1e-9);
+    }
+}
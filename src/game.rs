@@ -0,0 +1,4050 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::arena::{Arena, EntityId};
+use crate::assets::*;
+use crate::atlas::Atlas;
+use crate::audio::{sound_for, Sound};
+use crate::axis::ScrollAxis;
+use crate::boss::Boss;
+use crate::collision::{
+    self, Collider, Contact, GameEvent, Mobile, PowerUp, PowerUpKind, Projectile, Team, Terrain,
+    Trigger, TriggerKind, Wall, PLAYER_FIRE_COOLDOWN, PLAYER_MAX_HP,
+};
+use crate::difficulty::Difficulty;
+use crate::enemy::{EnemyKind, Formation, Surroundings};
+use crate::entity::Entity;
+use crate::highscore::{load_high_score, save_high_score};
+use crate::particle::{draw_particles, spawn_burst, update_particles, Particle};
+use crate::popup::{update_popups, Popup};
+use crate::replay::{Recorder, Replay, ReplayError};
+use crate::save::{self, Line, SaveError, Words};
+use crate::screen::Screen;
+use crate::sprite::*;
+use crate::starfield::Starfield;
+use crate::tiles::{AutotileSet, Tilemap, Tileset, TILE_SZ};
+use crate::types::*;
+use crate::waves::{StageTransition, TerrainType, Wave, WaveTable};
+use crate::weapon::Weapon;
+
+/*
+All of the game's rules live here, behind Game. Nothing in this module knows
+about windows or winit: main translates keyboard state into an InputState and
+hands it to Game::step once per simulation update, so the game can just as well
+be run headless.
+*/
+
+struct GameState {
+    terrains: Arena<Entity<Terrain>>,
+    tilemaps: Vec<Tilemap>,
+    mobiles: Arena<Entity<Mobile>>,
+    player: EntityId,
+    walls: Vec<Wall>,
+    projs: Vec<Projectile>,
+    powerups: Vec<Entity<PowerUp>>,
+    triggers: Vec<Trigger>,
+    popups: Vec<Popup>,
+    particles: Vec<Particle>,
+    // Muzzle flashes still playing; they're only for show, so aren't saved
+    flashes: Vec<Sprite>,
+    // Sound effects this step asked for, not yet played (or saved)
+    sounds: Vec<Sound>,
+    starfield: Starfield,
+    stage: GameStage,
+    // A fade to black and back that swaps in its stage when the screen is dark
+    transition: Option<Transition>,
+    // What the screen is cleared to, fading to each new stage's color
+    backdrop: Backdrop,
+    // Game time, in frames of FRAME_RATE whatever the update rate
+    frame_count: usize,
+    // Updates a second, and how far (in 1/update_rate of a frame) the clock
+    // is past frame_count
+    update_rate: u32,
+    subframe: u32,
+    scroll: Vec2i,
+    // The part of a pixel the camera has scrolled but not yet moved
+    scroll_remainder: f32,
+    score: usize,
+    // Enemies destroyed this run, the boss included
+    kills: usize,
+    boss: Option<Boss>,
+    weapon: Weapon,
+    // Including the current one; the game's over when the last is lost
+    lives: u8,
+    bombs: u8,
+    // Kills in the current streak, and when the latest was
+    combo: u32,
+    last_kill_frame: usize,
+    // When the last bomb went off, for the flash
+    last_bomb: Option<usize>,
+    // When the last wave of enemies was cleared, for the banner
+    last_wave_clear: Option<usize>,
+    // Frames the fire button has been held for, 0 when it's up
+    charge: usize,
+    // The latest dash lunges along dash_direction until dash_until, and the
+    // next can't start before dash_ready_at
+    dash_until: usize,
+    dash_ready_at: usize,
+    dash_direction: Vec2f,
+    // Which way shots are being aimed, relative to the screen, if they are;
+    // only the reticle cares, so it's not saved
+    aim: Option<Vec2f>,
+    paused: bool,
+    // Outline every collider and this frame's contacts
+    debug_draw: bool,
+    // Nothing hurts the player; only debug builds can turn it on
+    god_mode: bool,
+    // What the debug overlay shows; only filled in while debug_draw is on
+    debug_tiles: Vec<Rect>,
+    debug_contacts: Vec<(Rect, Rect)>,
+    difficulty: Difficulty,
+    // Rocks and boulders go into the tilemaps' terrain layer, not terrains
+    tile_terrain: bool,
+    // Which way the camera scrolls
+    axis: ScrollAxis,
+    // Where the player respawns, relative to the top left of the screen
+    player_start: Vec2i,
+    // All randomness in the game comes from here, so a seed fixes a whole run.
+    // It's ChaCha (what StdRng is underneath) so a save can say how far it got.
+    rng: ChaCha12Rng,
+    // What rng was seeded with, for saving it
+    seed: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameStage {
+    // Title screen, waiting for the player to start
+    Menu,
+    // Whether the current wave is still to spawn, and which wave it is (from 1)
+    Rocks(bool, usize),
+    Boulders(bool, usize),
+    // The camera stops and a warning flashes up before the boss arrives; from when
+    BossWarning(usize),
+    Boss,
+    Victory(usize),
+    GameOver(usize),
+}
+
+impl GameStage {
+    // What the screen is cleared to behind everything else during this stage
+    pub fn background(self) -> Rgba {
+        match self {
+            GameStage::Menu | GameStage::Rocks(_, _) | GameStage::Victory(_) => {
+                Rgba::from_hex(0xffc5ff)
+            }
+            GameStage::Boulders(_, _) => Rgba::from_hex(0xc5d0ff),
+            GameStage::BossWarning(_) | GameStage::Boss => Rgba::from_hex(0x702038),
+            GameStage::GameOver(_) => Rgba::from_hex(0x505050),
+        }
+    }
+}
+
+// Frames the background takes to fade from one stage's color to the next
+const BACKDROP_FADE_FRAMES: usize = 90;
+
+// The background color, partway through fading from one color to another
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Backdrop {
+    from: Rgba,
+    to: Rgba,
+    since: usize,
+}
+
+impl Backdrop {
+    fn new(color: Rgba) -> Self {
+        Self {
+            from: color,
+            to: color,
+            since: 0,
+        }
+    }
+
+    fn color(&self, now: usize) -> Rgba {
+        let t = (now - self.since) as f32 / BACKDROP_FADE_FRAMES as f32;
+        self.from.blend(self.to, t)
+    }
+
+    // Starts fading to color from wherever it's got to, unless it's already headed there
+    fn fade_to(&mut self, color: Rgba, now: usize) {
+        if color != self.to {
+            *self = Self {
+                from: self.color(now),
+                to: color,
+                since: now,
+            };
+        }
+    }
+}
+
+// Frames a whole fade out and back in takes
+const TRANSITION_FRAMES: usize = 60;
+
+// Frames the boss warning shows for before the boss comes on, and how fast it blinks
+const BOSS_WARNING_FRAMES: usize = 180;
+const BOSS_WARNING_BLINK: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    pub started_at: usize,
+    pub to: GameStage,
+}
+
+impl Transition {
+    fn elapsed(&self, now: usize) -> usize {
+        now - self.started_at
+    }
+
+    // The stage still hasn't been swapped
+    pub fn before_midpoint(&self, now: usize) -> bool {
+        self.elapsed(now) < TRANSITION_FRAMES / 2
+    }
+
+    // Time to swap stages, in an update covering frames frames from now
+    pub fn at_midpoint(&self, now: usize, frames: usize) -> bool {
+        let elapsed = self.elapsed(now);
+        elapsed <= TRANSITION_FRAMES / 2 && TRANSITION_FRAMES / 2 < elapsed + frames
+    }
+
+    pub fn done(&self, now: usize) -> bool {
+        self.elapsed(now) >= TRANSITION_FRAMES
+    }
+
+    // Opacity of the black overlay: up to fully black at the midpoint, then back down
+    pub fn alpha(&self, now: usize) -> u8 {
+        let half = TRANSITION_FRAMES / 2;
+        let t = self.elapsed(now).min(TRANSITION_FRAMES);
+        let dark = if t <= half { t } else { TRANSITION_FRAMES - t };
+        (255 * dark / half) as u8
+    }
+}
+
+// Frames of game time a second; timers count these and speeds are per one
+pub const FRAME_RATE: u32 = 60;
+
+pub const WIDTH: usize = 320;
+pub const HEIGHT: usize = 576;
+const TILEMAP_HT: usize = 256;
+// Ground is laid out at least this far above the screen, so there's somewhere
+// to stamp terrain before it scrolls into view
+const TILEMAP_LOOKAHEAD: usize = 128;
+// What a terrain layer is full of where there's no terrain; it isn't drawn
+const NO_TILE: usize = 0;
+
+const WALL_SZ: usize = 32;
+const ROCK_SZ: usize = 16;
+
+// how fast the player moves relative to the camera, in pixels per frame
+const PLAYER_SPEED: f32 = 3.0;
+// where the player starts (and respawns) relative to the top left of the
+// screen, and where when it scrolls sideways, unless PlayerConfig says otherwise
+const PLAYER_START: Vec2i = Vec2i(180, 500);
+const PLAYER_START_HORIZONTAL: Vec2i = Vec2i(40, 276);
+const STARTING_LIVES: u8 = 3;
+const STARTING_BOMBS: u8 = 3;
+// a bomb takes this much off the boss instead of destroying it outright
+const BOMB_BOSS_DAMAGE: usize = 50;
+// how long the screen flashes white after a bomb
+const BOMB_FLASH_FRAMES: usize = 20;
+// a kill within this many frames of the last one keeps the streak going
+const COMBO_WINDOW: usize = 90;
+// Points for flying through the gap in a boulder wall
+const GAP_BONUS: usize = 5;
+const MAX_COMBO_MULTIPLIER: usize = 5;
+// how long a respawned player can't be hurt for
+const RESPAWN_INVULN_FRAMES: usize = 120;
+// a dash lunges DASH_DISTANCE pixels over DASH_FRAMES, can't be hurt for
+// DASH_INVULN_FRAMES from the start, and can't be repeated for DASH_COOLDOWN
+const DASH_DISTANCE: f32 = 64.0;
+const DASH_FRAMES: usize = 8;
+const DASH_INVULN_FRAMES: usize = 16;
+const DASH_COOLDOWN: usize = 60;
+// holding fire at least this many frames charges a shot to let go on release
+const CHARGE_THRESHOLD: usize = 15;
+// clearing a wave of enemies is worth up to this much, in proportion to the hp left
+const WAVE_CLEAR_BONUS: usize = 10;
+// frames the wave clear banner shows for
+const WAVE_CLEAR_FRAMES: usize = 90;
+// hurt enemies' health bars are this tall, this far above them
+const ENEMY_BAR_H: u16 = 4;
+const ENEMY_BAR_GAP: i32 = 3;
+// how far out from the gun the aim reticle sits, and how far its arms reach
+const RETICLE_DISTANCE: f32 = 72.0;
+const RETICLE_SIZE: i32 = 5;
+// destroyed enemies and terrain drop a power-up 1 in POWERUP_CHANCE times
+const POWERUP_CHANCE: usize = 12;
+// ...and which one is picked by a roll in 0..DROP_ROLLS; see powerup_drop
+const DROP_ROLLS: u32 = 8;
+// How opaque the newest point of a projectile's trail is
+const TRAIL_ALPHA: u16 = 160;
+// 1 in DRIFT_CHANCE rocks in a rock field drift, at up to MAX_DRIFT pixels per
+// frame sideways and down
+const DRIFT_CHANCE: usize = 6;
+const MAX_DRIFT: f32 = 0.6;
+// Scores on the HUD are padded to this many digits
+const SCORE_DIGITS: usize = 6;
+
+/**
+ * One frame's worth of player input, decoupled from winit.
+ *
+ * fire: the fire button is held (it shoots on press, and charges while held)
+ * start: the start/confirm button is held
+ * bomb: the bomb button is held (a bomb goes off when it's pressed)
+ * aim: held, the direction buttons aim shots instead of moving the ship
+ * dash: the dash button is held (the ship dashes when it's pressed)
+ * skip_to: debug shortcut straight to a stage
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InputState {
+    pub left: bool,
+    pub right: bool,
+    pub up: bool,
+    pub down: bool,
+    pub fire: bool,
+    pub start: bool,
+    pub bomb: bool,
+    pub aim: bool,
+    pub dash: bool,
+    pub skip_to: Option<GameStage>,
+}
+
+impl InputState {
+    // Combine two input sources (e.g. keyboard and gamepad): held on either is held
+    pub fn or(self, other: InputState) -> InputState {
+        InputState {
+            left: self.left || other.left,
+            right: self.right || other.right,
+            up: self.up || other.up,
+            down: self.down || other.down,
+            fire: self.fire || other.fire,
+            start: self.start || other.start,
+            bomb: self.bomb || other.bomb,
+            aim: self.aim || other.aim,
+            dash: self.dash || other.dash,
+            skip_to: self.skip_to.or(other.skip_to),
+        }
+    }
+}
+
+// Everything a Game needs from outside to set itself up (and restart)
+pub struct GameConfig {
+    pub sprite_sheet: Rc<Atlas>,
+    pub tile_sheet: Rc<Atlas>,
+    pub tileset: Rc<Tileset>,
+    // Where the high score is kept between runs; None to not keep it
+    pub high_score_path: Option<PathBuf>,
+    pub difficulty: Difficulty,
+    pub waves: WaveTable,
+    // Stamp rocks and boulders into the scrolling tilemaps as solid tiles
+    // instead of spawning an entity for each one
+    pub tile_terrain: bool,
+    // Simulation updates a second; see FRAME_RATE
+    pub update_rate: u32,
+    pub scroll_axis: ScrollAxis,
+    pub player: PlayerConfig,
+    // The ground the run starts over, repeated to cover the first screen
+    pub start_ground: Tilemap,
+}
+
+// How the player starts out, and comes back after losing a life
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlayerConfig {
+    // Where the top left of the ship goes, relative to the top left of the screen
+    pub start: Vec2i,
+    pub max_hp: usize,
+}
+
+impl PlayerConfig {
+    // The usual start for a game scrolling along axis
+    pub fn for_axis(axis: ScrollAxis) -> Self {
+        Self {
+            start: match axis {
+                ScrollAxis::Vertical => PLAYER_START,
+                ScrollAxis::Horizontal => PLAYER_START_HORIZONTAL,
+            },
+            max_hp: PLAYER_MAX_HP,
+        }
+    }
+}
+
+/// Something that went wrong during a step that the game carried on past,
+/// for main to report
+#[derive(Debug)]
+pub enum GameError {
+    /// Writing a frame failed, so the recording was stopped
+    Recording(std::io::Error),
+    /// A new high score couldn't be saved to this path
+    HighScore(PathBuf, std::io::Error),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::Recording(e) => write!(f, "stopped recording: {}", e),
+            GameError::HighScore(path, e) => {
+                write!(f, "couldn't save high score to {}: {}", path.display(), e)
+            }
+        }
+    }
+}
+
+pub struct Game {
+    config: GameConfig,
+    state: GameState,
+    // Survives restarts, unlike everything in state
+    high_score: usize,
+    // Last frame's input, for telling presses from holds
+    prev_input: InputState,
+    // What the rng started from, so a recording can be replayed
+    seed: u64,
+    // Where each frame's input is written while recording
+    recorder: Option<Recorder>,
+    // Recorded input still to play back, used in place of live input
+    replay: VecDeque<InputState>,
+    // Errors since main last took them, kept across restarts
+    errors: Vec<GameError>,
+}
+
+impl Game {
+    pub fn new(config: GameConfig) -> Self {
+        Self::with_seed(config, StdRng::from_entropy().gen())
+    }
+
+    // Same seed, same inputs, same game
+    pub fn with_seed(config: GameConfig, seed: u64) -> Self {
+        let state = init(&config, seed, ChaCha12Rng::seed_from_u64(seed));
+        let high_score = config.high_score_path.as_deref().map_or(0, load_high_score);
+        Self {
+            config,
+            state,
+            high_score,
+            prev_input: InputState::default(),
+            seed,
+            recorder: None,
+            replay: VecDeque::new(),
+            errors: vec![],
+        }
+    }
+
+    /**
+     * Writes the seed, difficulty, settings and every frame's input from here
+     * on to path. Start it before the first step for a recording that replays from
+     * the beginning.
+     */
+    pub fn record(&mut self, path: &Path) -> std::io::Result<()> {
+        self.recorder = Some(Recorder::create(path, self.seed, &self.config)?);
+        Ok(())
+    }
+
+    // Flushes and closes the recording, if there is one
+    pub fn stop_recording(&mut self) {
+        self.recorder = None;
+    }
+
+    /**
+     * A game set up like the recorded one, on the recording's settings rather
+     * than config's, which plays back the recorded input instead of what step
+     * is given until the recording runs out.
+     */
+    pub fn replay(config: GameConfig, path: &Path) -> Result<Self, ReplayError> {
+        let replay = Replay::load(path)?;
+        let mut game = Self::with_seed(replay.apply(config), replay.seed);
+        game.replay = replay.inputs.into();
+        Ok(game)
+    }
+
+    /**
+     * Quick-saves the run to path. Saving leaves the run alone, and it goes the
+     * same way whether it carries on or is loaded later.
+     */
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, self.save_to_string())
+    }
+
+    pub fn save_to_string(&self) -> String {
+        save_state(&self.state, &self.prev_input, &self.config.sprite_sheet)
+    }
+
+    // Picks up a quick-saved run; if it can't be read the current run carries on
+    pub fn load(&mut self, path: &Path) -> Result<(), SaveError> {
+        self.load_from_str(&fs::read_to_string(path)?)
+    }
+
+    pub fn load_from_str(&mut self, text: &str) -> Result<(), SaveError> {
+        let (state, prev_input) = load_state(&self.config, text)?;
+        self.config.difficulty = state.difficulty;
+        self.state = state;
+        self.prev_input = prev_input;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn replaying(&self) -> bool {
+        !self.replay.is_empty()
+    }
+
+    // Advance the simulation by one update, 1/update_rate of a second
+    pub fn step(&mut self, input: InputState) {
+        let input = self.replay.pop_front().unwrap_or(input);
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.write(&input) {
+                self.errors.push(GameError::Recording(e));
+                self.recorder = None;
+            }
+        }
+
+        let prev_input = std::mem::replace(&mut self.prev_input, input);
+        self.state.sounds.clear();
+
+        // The world holds still on the title screen
+        if self.state.stage == GameStage::Menu {
+            self.update_menu(&input, &prev_input);
+            advance_clock(&mut self.state);
+            return;
+        }
+
+        // Game over event: back to the title screen
+        if let GameStage::GameOver(death_frame) = self.state.stage {
+            if self.state.frame_count - death_frame >= 150 {
+                // Keep drawing from the same rng so restarts are reproducible too
+                let rng = self.state.rng.clone();
+                self.state = init(&self.config, self.state.seed, rng);
+                return;
+            }
+        }
+
+        // One bomb per press, however long it's held
+        if input.bomb && !prev_input.bomb {
+            use_bomb(&mut self.state);
+        }
+        // Likewise dashes
+        if input.dash && !prev_input.dash {
+            start_dash(&mut self.state, &input);
+        }
+
+        let was_over = matches!(self.state.stage, GameStage::GameOver(_));
+        update_game(
+            &mut self.state,
+            &input,
+            &self.config.sprite_sheet,
+            &self.config.tile_sheet,
+            &self.config.waves,
+        );
+        if !was_over && matches!(self.state.stage, GameStage::GameOver(_)) {
+            self.record_score();
+        }
+
+        // Load and unload tilemaps if necessary
+        update_tilemaps(&mut self.state);
+
+        // Remove Terrain objects that have left screen
+        cleanup_terrain(&mut self.state);
+
+        // Move the clock on by this update's frames
+        advance_clock(&mut self.state);
+    }
+
+    // Start pressed starts the game; left and right pick the difficulty
+    fn update_menu(&mut self, input: &InputState, prev_input: &InputState) {
+        if input.start && !prev_input.start {
+            self.state.stage = GameStage::Rocks(true, 1);
+            return;
+        }
+        let difficulty = if input.left && !prev_input.left {
+            self.state.difficulty.easier()
+        } else if input.right && !prev_input.right {
+            self.state.difficulty.harder()
+        } else {
+            return;
+        };
+        self.state.difficulty = difficulty;
+        // Stick with the choice for the next run too
+        self.config.difficulty = difficulty;
+    }
+
+    // Called once when the player dies
+    fn record_score(&mut self) {
+        if self.state.score <= self.high_score {
+            return;
+        }
+        self.high_score = self.state.score;
+        if let Some(path) = &self.config.high_score_path {
+            if let Err(e) = save_high_score(path, self.high_score) {
+                self.errors.push(GameError::HighScore(path.clone(), e));
+            }
+        }
+    }
+
+    pub fn draw(&mut self, screen: &mut Screen, font: &Font) {
+        // A run that's beating the record shows as the high score right away
+        let high_score = self.high_score.max(self.state.score);
+        draw_game(
+            &mut self.state,
+            screen,
+            font,
+            high_score,
+            &self.config.waves,
+        );
+    }
+
+    pub fn scroll(&self) -> Vec2i {
+        self.state.scroll
+    }
+
+    pub fn stage(&self) -> GameStage {
+        self.state.stage
+    }
+
+    #[cfg(test)]
+    pub fn score(&self) -> usize {
+        self.state.score
+    }
+
+    #[cfg(test)]
+    pub fn frame_count(&self) -> usize {
+        self.state.frame_count
+    }
+
+    #[cfg(test)]
+    pub fn player_hp(&self) -> usize {
+        let player = self.state.mobiles.get(self.state.player);
+        player.map_or(0, |p| p.collider.hp)
+    }
+
+    #[cfg(test)]
+    pub fn lives(&self) -> u8 {
+        self.state.lives
+    }
+
+    #[cfg(test)]
+    pub fn bombs(&self) -> u8 {
+        self.state.bombs
+    }
+
+    // The sounds the last step made, for main to play
+    pub fn take_sounds(&mut self) -> Vec<Sound> {
+        std::mem::take(&mut self.state.sounds)
+    }
+
+    // What's gone wrong since the last call, for main to report
+    pub fn take_errors(&mut self) -> Vec<GameError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.state.difficulty
+    }
+
+    pub fn update_rate(&self) -> u32 {
+        self.state.update_rate
+    }
+
+    pub fn paused(&self) -> bool {
+        self.state.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.state.paused = !self.state.paused;
+    }
+
+    pub fn toggle_debug_draw(&mut self) {
+        self.state.debug_draw = !self.state.debug_draw;
+        self.state.debug_tiles.clear();
+        self.state.debug_contacts.clear();
+    }
+
+    // For getting to late stages while testing, so it's left out of release builds
+    #[cfg(debug_assertions)]
+    pub fn toggle_god_mode(&mut self) {
+        self.state.god_mode = !self.state.god_mode;
+    }
+}
+
+fn init(config: &GameConfig, seed: u64, mut rng: ChaCha12Rng) -> GameState {
+    let GameConfig {
+        sprite_sheet,
+        difficulty,
+        tile_terrain,
+        update_rate,
+        scroll_axis,
+        player,
+        start_ground,
+        ..
+    } = config;
+    let mut tilemaps: Vec<Tilemap> = vec![];
+    match scroll_axis {
+        ScrollAxis::Vertical => {
+            for i in 0..(HEIGHT / TILEMAP_HT + 1) {
+                tilemaps.push(start_map(
+                    Vec2i(0, HEIGHT as i32 - (i * TILEMAP_HT) as i32),
+                    start_ground,
+                    *scroll_axis,
+                ));
+            }
+        }
+        ScrollAxis::Horizontal => {
+            for i in 0..(WIDTH / TILEMAP_HT + 1) {
+                tilemaps.push(start_map(
+                    Vec2i((i * TILEMAP_HT) as i32, 0),
+                    start_ground,
+                    *scroll_axis,
+                ));
+            }
+        }
+    }
+
+    // Player sprite
+    let player_sprite = player_anim(sprite_sheet, 0);
+
+    // Player entity
+    let start = player.start;
+    let player = face_ahead(
+        Entity::new(
+            player_sprite,
+            start,
+            Mobile::player(start.0, start.1, player.max_hp),
+        ),
+        *scroll_axis,
+    );
+    let mut mobiles = Arena::new();
+    let player_id = mobiles.insert(player);
+
+    let starfield = Starfield::new(&mut rng, WIDTH, HEIGHT);
+
+    // Initial game state
+    GameState {
+        tilemaps,
+        terrains: Arena::new(),
+        mobiles,
+        player: player_id,
+        walls: walls_vec(WIDTH as u16, HEIGHT as u16, *scroll_axis),
+        projs: vec![],
+        powerups: vec![],
+        triggers: vec![],
+        popups: vec![],
+        particles: vec![],
+        flashes: vec![],
+        sounds: vec![],
+        starfield,
+        stage: GameStage::Menu,
+        transition: None,
+        backdrop: Backdrop::new(GameStage::Menu.background()),
+        frame_count: 0,
+        update_rate: (*update_rate).max(1),
+        subframe: 0,
+        scroll: Vec2i(0, 0),
+        scroll_remainder: 0.0,
+        score: 0,
+        kills: 0,
+        boss: None,
+        weapon: Weapon::Single,
+        charge: 0,
+        dash_until: 0,
+        dash_ready_at: 0,
+        dash_direction: Vec2f(0.0, 0.0),
+        aim: None,
+        lives: STARTING_LIVES,
+        bombs: STARTING_BOMBS,
+        combo: 0,
+        last_kill_frame: 0,
+        last_bomb: None,
+        last_wave_clear: None,
+        paused: false,
+        debug_draw: false,
+        god_mode: false,
+        debug_tiles: vec![],
+        debug_contacts: vec![],
+        difficulty: *difficulty,
+        tile_terrain: *tile_terrain,
+        axis: *scroll_axis,
+        player_start: start,
+        rng,
+        seed,
+    }
+}
+
+// Size in tiles of a screen-wide (or, scrolling sideways, screen-tall) tilemap
+fn ground_dims(axis: ScrollAxis) -> (usize, usize) {
+    match axis {
+        ScrollAxis::Vertical => (WIDTH / TILE_SZ, TILEMAP_HT / TILE_SZ),
+        ScrollAxis::Horizontal => (TILEMAP_HT / TILE_SZ, HEIGHT / TILE_SZ),
+    }
+}
+
+// A ground-sized tilemap all of tile_id, with an empty terrain layer on top
+fn ground_map(position: Vec2i, tileset: &Rc<Tileset>, tile_id: usize, axis: ScrollAxis) -> Tilemap {
+    let dims = ground_dims(axis);
+    let mut map = Tilemap::new(position, dims, tileset, vec![tile_id; dims.0 * dims.1]);
+    add_terrain_layer(&mut map);
+    map
+}
+
+// A ground-sized tilemap tiled with copies of start_ground, from its top left
+// corner, with an empty terrain layer on top
+fn start_map(position: Vec2i, start_ground: &Tilemap, axis: ScrollAxis) -> Tilemap {
+    let dims = ground_dims(axis);
+    let (w, h) = start_ground.size();
+    let tiles = (0..dims.0 * dims.1)
+        .map(|i| {
+            let x = (i % dims.0 % w * TILE_SZ) as i32;
+            let y = (i / dims.0 % h * TILE_SZ) as i32;
+            start_ground
+                .try_tile_idx_at(start_ground.position + Vec2i(x, y))
+                .expect("wrapped onto the start ground")
+        })
+        .collect();
+    let mut map = Tilemap::new(position, dims, &start_ground.tileset, tiles);
+    add_terrain_layer(&mut map);
+    map
+}
+
+// Terrain is what's collided with; the ground underneath is just for show
+fn add_terrain_layer(map: &mut Tilemap) {
+    let (w, h) = map.size();
+    map.add_layer("terrain", vec![NO_TILE; w * h], Some(NO_TILE));
+    map.set_collision_layer("terrain")
+        .expect("terrain layer was just added");
+}
+
+/**
+ * Writes out everything the game plays by, one line per value or entity. The
+ * rng is written as its seed and how many words it has handed out. The arenas'
+ * layouts are written after their entities, since their order decides who
+ * collides first and which slot the next one goes in.
+ */
+fn save_state(state: &GameState, prev_input: &InputState, sprite_sheet: &Atlas) -> String {
+    let mut lines = vec![
+        save::header(),
+        Line::new("settings")
+            .put(&state.axis)
+            .put(&state.tile_terrain)
+            .put(&state.update_rate)
+            .finish(),
+        Line::new("rng")
+            .put(&state.seed)
+            .put(&state.rng.get_word_pos())
+            .finish(),
+        Line::new("frame")
+            .put(&state.frame_count)
+            .put(&state.subframe)
+            .finish(),
+        Line::new("stage").put(&state.stage).finish(),
+        Line::new("transition").put(&state.transition).finish(),
+        Line::new("scroll")
+            .put(&state.scroll)
+            .put(&state.scroll_remainder)
+            .finish(),
+        Line::new("score")
+            .put(&state.score)
+            .put(&state.kills)
+            .finish(),
+        Line::new("difficulty").put(&state.difficulty).finish(),
+        Line::new("weapon").put(&state.weapon).finish(),
+        Line::new("lives").put(&state.lives).finish(),
+        Line::new("bombs").put(&state.bombs).finish(),
+        Line::new("combo")
+            .put(&state.combo)
+            .put(&state.last_kill_frame)
+            .finish(),
+        Line::new("last_bomb").put(&state.last_bomb).finish(),
+        Line::new("last_wave_clear")
+            .put(&state.last_wave_clear)
+            .finish(),
+        Line::new("charge").put(&state.charge).finish(),
+        Line::new("dash")
+            .put(&state.dash_until)
+            .put(&state.dash_ready_at)
+            .put(&state.dash_direction.0)
+            .put(&state.dash_direction.1)
+            .finish(),
+        Line::new("paused").put(&state.paused).finish(),
+        Line::new("boss").put(&state.boss).finish(),
+        Line::new("input").put(prev_input).finish(),
+    ];
+    // Rows are separated by ; to keep each map on one line. Terrain is
+    // sparse, so it's written a tile at a time after its map.
+    for map in state.tilemaps.iter() {
+        lines.push(
+            Line::new("tilemap")
+                .put(&map.position)
+                .put(&map.layer_to_csv(0).replace('\n', ";"))
+                .finish(),
+        );
+        for (pos, tile) in map.placed_tiles() {
+            lines.push(Line::new("stamp").put(&pos).put(&tile).finish());
+        }
+    }
+    for wall in state.walls.iter() {
+        lines.push(Line::new("wall").put(&wall.rect).finish());
+    }
+    for terrain in state.terrains.iter() {
+        lines.push(
+            Line::new("terrain")
+                .put(&terrain.position)
+                .put(&terrain.sprite.animation_sm.current_index())
+                .put(&terrain.collider)
+                .finish(),
+        );
+    }
+    lines.push(layout_line("terrain_slots", &state.terrains));
+    // Enemies' looks are saved so loading can give them the same sprite
+    let now = state.frame_count;
+    for mobile in state.mobiles.iter() {
+        let frame = mobile.sprite.animation_sm.playing().current_frame(now);
+        let look = ENEMY_LOOKS
+            .iter()
+            .position(|name| sprite_sheet.rect(name) == frame)
+            .unwrap_or(0);
+        lines.push(
+            Line::new("mobile")
+                .put(&mobile.position)
+                .put(&look)
+                .put(&mobile.sprite.animation_sm.current_index())
+                .put(&mobile.collider)
+                .finish(),
+        );
+    }
+    lines.push(layout_line("mobile_slots", &state.mobiles));
+    for proj in state.projs.iter() {
+        lines.push(Line::new("proj").put(proj).finish());
+    }
+    for powerup in state.powerups.iter() {
+        lines.push(
+            Line::new("powerup")
+                .put(&powerup.position)
+                .put(&powerup.collider)
+                .finish(),
+        );
+    }
+    for trigger in state.triggers.iter() {
+        lines.push(Line::new("trigger").put(trigger).finish());
+    }
+    for popup in state.popups.iter() {
+        lines.push(Line::new("popup").put(popup).finish());
+    }
+    for particle in state.particles.iter() {
+        lines.push(Line::new("particle").put(particle).finish());
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+// How many slots an arena has and which are free, in the order they're reused
+fn layout_line<T>(key: &str, arena: &Arena<T>) -> String {
+    let (slots, free) = arena.layout();
+    Line::new(key).put(&slots).put(&free.to_vec()).finish()
+}
+
+// Rebuilds a GameState from what save_state wrote, and the last frame's input
+fn load_state(config: &GameConfig, text: &str) -> Result<(GameState, InputState), SaveError> {
+    let mut lines = text.lines();
+    save::check_header(lines.next())?;
+
+    // Anything the save leaves out starts the way a new game would
+    let mut state = init(config, 0, ChaCha12Rng::seed_from_u64(0));
+    state.tilemaps.clear();
+    state.mobiles = Arena::new();
+    state.walls.clear();
+    let mut prev_input = InputState::default();
+
+    for (i, line) in lines.enumerate() {
+        let mut words = Words::new(line);
+        let read = load_line(config, &mut state, &mut prev_input, &mut words);
+        if read.is_none() || !words.done() {
+            // After the header, and line numbers start from 1
+            return Err(SaveError::BadLine(i + 2, line.to_string()));
+        }
+    }
+
+    // A run on other settings would play out differently from here
+    if state.axis != config.scroll_axis {
+        return Err(SaveError::Settings("scroll axis"));
+    }
+    if state.tile_terrain != config.tile_terrain {
+        return Err(SaveError::Settings("terrain setting"));
+    }
+    if state.update_rate != config.update_rate.max(1) {
+        return Err(SaveError::Settings("update rate"));
+    }
+
+    state.player = find_player(&state.mobiles).ok_or(SaveError::NoPlayer)?;
+    // The stars are just for show; any will do as long as they don't use up the rng
+    state.starfield = Starfield::new(&mut state.rng.clone(), WIDTH, HEIGHT);
+    // Nor is the background, so it starts out on the loaded stage's color
+    state.backdrop = Backdrop::new(state.stage.background());
+    Ok((state, prev_input))
+}
+
+fn find_player(mobiles: &Arena<Entity<Mobile>>) -> Option<EntityId> {
+    mobiles
+        .entries()
+        .find(|(_, m)| m.collider.is_player)
+        .map(|(id, _)| id)
+}
+
+// Reads one line of a save into state, or None if it can't be read
+fn load_line(
+    config: &GameConfig,
+    state: &mut GameState,
+    prev_input: &mut InputState,
+    words: &mut Words,
+) -> Option<()> {
+    // Entities come after the frame count, so their animations start from it
+    let now = state.frame_count;
+    match words.word()? {
+        "settings" => {
+            state.axis = words.read()?;
+            state.tile_terrain = words.read()?;
+            state.update_rate = words.read()?;
+        }
+        "rng" => {
+            state.seed = words.read()?;
+            state.rng = ChaCha12Rng::seed_from_u64(state.seed);
+            state.rng.set_word_pos(words.read()?);
+        }
+        "frame" => {
+            state.frame_count = words.read()?;
+            state.subframe = words.read()?;
+        }
+        "stage" => state.stage = words.read()?,
+        "transition" => state.transition = words.read()?,
+        "scroll" => {
+            state.scroll = words.read()?;
+            state.scroll_remainder = words.read()?;
+        }
+        "score" => {
+            state.score = words.read()?;
+            state.kills = words.read()?;
+        }
+        "difficulty" => state.difficulty = words.read()?,
+        "weapon" => state.weapon = words.read()?,
+        "lives" => state.lives = words.read()?,
+        "bombs" => state.bombs = words.read()?,
+        "combo" => {
+            state.combo = words.read()?;
+            state.last_kill_frame = words.read()?;
+        }
+        "last_bomb" => state.last_bomb = words.read()?,
+        "last_wave_clear" => state.last_wave_clear = words.read()?,
+        "charge" => state.charge = words.read()?,
+        "dash" => {
+            state.dash_until = words.read()?;
+            state.dash_ready_at = words.read()?;
+            state.dash_direction = Vec2f(words.read()?, words.read()?);
+        }
+        "paused" => state.paused = words.read()?,
+        "boss" => state.boss = words.read()?,
+        "input" => *prev_input = words.read()?,
+        "tilemap" => {
+            let position = words.read()?;
+            let csv = words.read::<String>()?.replace(';', "\n");
+            let mut map = Tilemap::from_csv_str(&csv, position, &config.tileset).ok()?;
+            add_terrain_layer(&mut map);
+            state.tilemaps.push(map);
+        }
+        "stamp" => {
+            let pos = words.read()?;
+            let tile = words.read()?;
+            state.tilemaps.last_mut()?.set_tile(pos, tile).ok()?;
+        }
+        "wall" => state.walls.push(Wall::new(words.read()?)),
+        "terrain" => {
+            let position = words.read()?;
+            let anim = words.read()?;
+            let terrain: Terrain = words.read()?;
+            let sheet = &config.tile_sheet;
+            let made = if terrain.destructible {
+                rock_entity(sheet, now, position)
+            } else {
+                boulder_entity(sheet, now, position)
+            };
+            let mut entity = Entity::new(made.sprite, position, terrain);
+            entity.sprite.animation_sm.jump_to(anim, now);
+            state.terrains.insert(entity);
+        }
+        "terrain_slots" => state.terrains.relayout(words.read()?, words.read()?)?,
+        "mobile" => {
+            let position = words.read()?;
+            let look: usize = words.read()?;
+            let anim = words.read()?;
+            let mobile: Mobile = words.read()?;
+            let sheet = &config.sprite_sheet;
+            let sprite = if mobile.is_player {
+                player_anim(sheet, now)
+            } else if mobile.is_boss {
+                boss_entity(sheet, now, position).sprite
+            } else if look < ENEMY_LOOKS.len() {
+                enemy_entity_with_look(sheet, now, position, look).sprite
+            } else {
+                return None;
+            };
+            // The saved hitbox is already turned to face ahead; the sprite isn't
+            let mut entity = Entity::new(sprite, position, mobile);
+            entity.sprite.rotation = config.scroll_axis.rotation();
+            entity.sprite.animation_sm.jump_to(anim, now);
+            state.mobiles.insert(entity);
+        }
+        "mobile_slots" => state.mobiles.relayout(words.read()?, words.read()?)?,
+        "proj" => state.projs.push(words.read()?),
+        "powerup" => {
+            let position = words.read()?;
+            let powerup: PowerUp = words.read()?;
+            let sheet = &config.sprite_sheet;
+            let made = match powerup.kind {
+                PowerUpKind::Heal(_) => heal_entity(sheet, now, position),
+                PowerUpKind::Weapon(weapon) => weapon_entity(sheet, now, position, weapon),
+                PowerUpKind::Shield => shield_entity(sheet, now, position),
+            };
+            state
+                .powerups
+                .push(Entity::new(made.sprite, position, powerup));
+        }
+        "trigger" => state.triggers.push(words.read()?),
+        "popup" => state.popups.push(words.read()?),
+        "particle" => state.particles.push(words.read()?),
+        _ => return None,
+    }
+    Some(())
+}
+
+fn update_tilemaps(state: &mut GameState) {
+    // Unload tilemaps that have scrolled off the bottom of the screen (or off
+    // the left, scrolling sideways)
+    let axis = state.axis;
+    let scroll = state.scroll;
+    state
+        .tilemaps
+        .retain(|map| !axis.behind(scroll, map.bounds()));
+
+    // Stack new tilemaps past the furthest one until there's enough ground
+    // beyond the leading edge of the screen. They sit flush so their tiles
+    // line up.
+    let tileset = Rc::clone(&state.tilemaps[0].tileset);
+    let ht = TILEMAP_HT as i32;
+    let mut reach = state
+        .tilemaps
+        .iter()
+        .map(|m| -axis.depth(scroll, m.bounds()))
+        .max()
+        .unwrap();
+    while reach < TILEMAP_LOOKAHEAD as i32 {
+        let tile_idx = state.rng.gen_range(0..tileset.tile_ids["ground"].len());
+        let tile_id = tileset.tile_ids["ground"][tile_idx];
+        reach += ht;
+        let at = axis.ahead_of(scroll, reach, 0, ht);
+        state.tilemaps.push(ground_map(at, &tileset, tile_id, axis));
+    }
+}
+
+fn draw_game(
+    state: &mut GameState,
+    screen: &mut Screen,
+    font: &Font,
+    high_score: usize,
+    waves: &WaveTable,
+) {
+    // Call screen's drawing methods to render the game state
+    screen.clear(state.backdrop.color(state.frame_count));
+
+    // The stars are furthest back, behind even the ground
+    state.starfield.draw(screen, state.scroll);
+
+    for map in state.tilemaps.iter() {
+        map.draw(screen, state.frame_count);
+    }
+
+    for proj in state.projs.iter() {
+        let col = match proj.team {
+            Team::Player => Rgba(0, 128, 0, 255),
+            Team::Enemy => Rgba(192, 0, 0, 255),
+        };
+        // Older points of the trail are smaller and fainter
+        if let Some(trail) = &proj.trail {
+            let len = trail.len() as u16 + 1;
+            for (age, point) in trail.points().enumerate() {
+                let left = len - 1 - age as u16;
+                let size = (proj.rect.w * left / len).max(1);
+                let alpha = (TRAIL_ALPHA * left / len) as u8;
+                screen.rect(Rect::from_center(point, size, size), col.with_alpha(alpha));
+            }
+        }
+        screen.rect(proj.rect, col);
+    }
+
+    // Show the shot that's charging, at the size it would be let go at
+    if let Some(player) = state.mobiles.get(state.player) {
+        if state.charge >= CHARGE_THRESHOLD {
+            let preview = Projectile::charged(
+                &player.collider,
+                state.charge,
+                state.axis,
+                state.frame_count,
+            );
+            screen.rect(preview.rect, Rgba(0, 128, 0, 160));
+        }
+    }
+
+    let sprites = state
+        .mobiles
+        .iter_mut()
+        .map(|m| &mut m.sprite)
+        .chain(state.terrains.iter_mut().map(|t| &mut t.sprite))
+        .chain(state.powerups.iter_mut().map(|p| &mut p.sprite))
+        .chain(state.flashes.iter_mut())
+        .collect();
+    screen.draw_sprites(sprites, state.frame_count);
+
+    // A little bar over each hurt enemy; the boss has its own up top
+    for m in state.mobiles.iter() {
+        let enemy = &m.collider;
+        if enemy.is_player || enemy.is_boss || enemy.hp == 0 || enemy.hp >= enemy.max_hp {
+            continue;
+        }
+        let bar = Rect {
+            x: enemy.rect.x,
+            y: enemy.rect.y - ENEMY_BAR_GAP - ENEMY_BAR_H as i32,
+            w: enemy.rect.w,
+            h: ENEMY_BAR_H,
+        };
+        draw_health_bar(screen, font, bar, enemy.hp, enemy.max_hp, "");
+    }
+
+    // A ring around the shielded player, flickering for its last second
+    if let Some(player) = state.mobiles.get(state.player).map(|p| &p.collider) {
+        if player.is_shielded(state.frame_count) {
+            let left = player.shield_until - state.frame_count;
+            if left > 60 || (state.frame_count / 4) % 2 == 0 {
+                screen.circle_outline(player.rect.center(), 24, Rgba(64, 160, 255, 255));
+            }
+        }
+    }
+
+    // A cross where aimed shots are headed
+    if let Some(at) = reticle_pos(state) {
+        let arm = |dx, dy| Vec2i(dx * RETICLE_SIZE, dy * RETICLE_SIZE);
+        let col = Rgba(200, 0, 0, 255);
+        // Lines stop a pixel short of their far end, so reach one further
+        screen.line(at - arm(1, 0), at + arm(1, 0) + Vec2i(1, 0), col);
+        screen.line(at - arm(0, 1), at + arm(0, 1) + Vec2i(0, 1), col);
+    }
+
+    draw_particles(&state.particles, screen, state.frame_count);
+
+    for popup in state.popups.iter() {
+        draw_string(
+            &popup.text,
+            screen,
+            font,
+            popup.pos - state.scroll,
+            state.scroll,
+            Rgba::BLACK.with_alpha(popup.alpha(state.frame_count)),
+        );
+    }
+
+    if state.debug_draw {
+        draw_debug_overlay(state, screen);
+    }
+
+    // The title screen has no HUD, just the title and options
+    if state.stage == GameStage::Menu {
+        let difficulty = format!("Difficulty\n{}", state.difficulty.name());
+        let lines = [
+            ("Space Shooter", 150),
+            ("Press Enter\nto start", 250),
+            (&difficulty, 350),
+            ("Arrows to change", 400),
+        ];
+        for (text, y) in lines.iter() {
+            draw_string_centered(
+                text,
+                screen,
+                font,
+                WIDTH as i32 / 2,
+                *y,
+                state.scroll,
+                Rgba::BLACK,
+            );
+        }
+        return;
+    }
+
+    if let Some(transition) = state.transition {
+        screen.rect(
+            view_rect(state.scroll),
+            Rgba::BLACK.with_alpha(transition.alpha(state.frame_count)),
+        );
+    }
+
+    // A bomb whites out the screen, fading over BOMB_FLASH_FRAMES
+    if let Some(since) = state.last_bomb.map(|at| state.frame_count - at) {
+        if since < BOMB_FLASH_FRAMES {
+            let alpha = 200 * (BOMB_FLASH_FRAMES - since) / BOMB_FLASH_FRAMES;
+            screen.rect(view_rect(state.scroll), Rgba::WHITE.with_alpha(alpha as u8));
+        }
+    }
+
+    // Draw HP bar, empty if the player's gone
+    let (hp, max_hp) = state
+        .mobiles
+        .get(state.player)
+        .map_or((0, 0), |p| (p.collider.hp, p.collider.max_hp));
+    draw_health_bar(
+        screen,
+        font,
+        Rect {
+            x: state.scroll.0 + 70,
+            y: state.scroll.1 + 520,
+            w: 200,
+            h: 18,
+        },
+        hp,
+        max_hp,
+        "HP",
+    );
+
+    // How far through the run it is, up the right hand side, while there's a run on
+    if !matches!(state.stage, GameStage::Menu | GameStage::GameOver(_)) {
+        let (done, total) = run_progress(state.stage, waves);
+        draw_progress_bar(
+            screen,
+            Rect {
+                x: state.scroll.0 + WIDTH as i32 - 14,
+                y: state.scroll.1 + 150,
+                w: 6,
+                h: 300,
+            },
+            done,
+            total,
+        );
+    }
+
+    // The boss gets its own bar across the top during the fight
+    if let (GameStage::Boss, Some(boss)) = (state.stage, &state.boss) {
+        let boss_hp = state
+            .mobiles
+            .iter()
+            .find(|m| m.collider.is_boss)
+            .map_or(0, |m| m.collider.hp);
+        draw_health_bar(
+            screen,
+            font,
+            Rect {
+                x: state.scroll.0 + 106,
+                y: state.scroll.1 + 66,
+                w: WIDTH as u16 - 126,
+                h: 18,
+            },
+            boss_hp,
+            boss.max_hp,
+            "Boss",
+        );
+    }
+
+    // Draw score, with the digits of both scores lined up
+    let score_msg = "Score ".to_string() + &format_score(state.score, SCORE_DIGITS);
+    draw_string(
+        &score_msg,
+        screen,
+        font,
+        Vec2i(20, 20),
+        state.scroll,
+        Rgba::BLACK,
+    );
+    let high_msg = "High  ".to_string() + &format_score(high_score, SCORE_DIGITS);
+    draw_string(
+        &high_msg,
+        screen,
+        font,
+        Vec2i(20, 42),
+        state.scroll,
+        Rgba::BLACK,
+    );
+    draw_string_right(
+        &format!("Lives {}\nBombs {}", state.lives, state.bombs),
+        screen,
+        font,
+        WIDTH as i32 - 20,
+        64,
+        state.scroll,
+        Rgba::BLACK,
+    );
+    // Only worth showing while a streak is paying off
+    let multiplier = combo_multiplier(state.combo);
+    if multiplier > 1 && state.frame_count - state.last_kill_frame <= COMBO_WINDOW {
+        draw_string_right(
+            &format!("Combo x{}", multiplier),
+            screen,
+            font,
+            WIDTH as i32 - 20,
+            108,
+            state.scroll,
+            Rgba::BLACK,
+        );
+    }
+    if state.god_mode {
+        draw_string_right(
+            "GOD",
+            screen,
+            font,
+            WIDTH as i32 - 20,
+            20,
+            state.scroll,
+            Rgba::BLACK,
+        );
+    }
+    let high_score_msg = format!("High Score {}", high_score);
+
+    if state.paused {
+        draw_string_centered(
+            "PAUSED",
+            screen,
+            font,
+            WIDTH as i32 / 2,
+            HEIGHT as i32 / 2,
+            state.scroll,
+            Rgba::BLACK,
+        );
+    }
+
+    if let GameStage::BossWarning(since) = state.stage {
+        if (state.frame_count - since) / BOSS_WARNING_BLINK % 2 == 0 {
+            draw_string_centered(
+                "WARNING",
+                screen,
+                font,
+                WIDTH as i32 / 2,
+                200,
+                state.scroll,
+                Rgba::BLACK,
+            );
+        }
+    }
+
+    let wave_clear = state.last_wave_clear.map(|at| state.frame_count - at);
+    let over = matches!(state.stage, GameStage::GameOver(_));
+    if !over && wave_clear.is_some_and(|since| since < WAVE_CLEAR_FRAMES) {
+        draw_string_centered(
+            "WAVE CLEAR",
+            screen,
+            font,
+            WIDTH as i32 / 2,
+            200,
+            state.scroll,
+            Rgba::BLACK,
+        );
+    }
+
+    if let GameStage::Victory(_) = state.stage {
+        draw_string_centered(
+            "Victory",
+            screen,
+            font,
+            WIDTH as i32 / 2,
+            200,
+            state.scroll,
+            Rgba::BLACK,
+        );
+    }
+
+    // Draw game over message if game is over
+    if let GameStage::GameOver(_) = state.stage {
+        draw_string_centered(
+            &format!(
+                "Game over\n\nKills {}\n{}\n\nRestarting",
+                state.kills, high_score_msg
+            ),
+            screen,
+            font,
+            WIDTH as i32 / 2,
+            200,
+            state.scroll,
+            Rgba::BLACK,
+        );
+    }
+}
+
+// Where the aim reticle goes, out ahead of the gun the way shots are aimed; None unless aiming
+fn reticle_pos(state: &GameState) -> Option<Vec2i> {
+    let direction = state.aim?;
+    let player = state.mobiles.get(state.player)?;
+    let gun = state.axis.nose(player.collider.rect);
+    Some(gun + (direction * RETICLE_DISTANCE).round())
+}
+
+/*
+Waves finished so far out of every wave before the boss, rocks then boulders.
+A stage that ends early on a Chance roll jumps ahead; reaching the boss is the
+end of the bar.
+*/
+fn run_progress(stage: GameStage, waves: &WaveTable) -> (usize, usize) {
+    let rocks = waves.rocks.len();
+    let total = rocks + waves.boulders.len();
+    let done = match stage {
+        GameStage::Menu | GameStage::GameOver(_) => 0,
+        GameStage::Rocks(_, wave) => (wave - 1).min(rocks),
+        GameStage::Boulders(_, wave) => rocks + wave - 1,
+        GameStage::BossWarning(_) | GameStage::Boss | GameStage::Victory(_) => total,
+    };
+    (done.min(total), total)
+}
+
+// Outlines every collider, with the ones touching something highlighted
+fn draw_debug_overlay(state: &GameState, screen: &mut Screen) {
+    for wall in state.walls.iter() {
+        screen.rect_outline(wall.rect, Rgba(0, 0, 255, 255));
+    }
+    for tile in state.debug_tiles.iter() {
+        screen.rect_outline(*tile, Rgba(128, 128, 128, 255));
+    }
+    for t in state.terrains.iter() {
+        screen.rect_outline(t.collider.rect, Rgba(128, 64, 0, 255));
+    }
+    for m in state.mobiles.iter() {
+        screen.rect_outline(m.collider.rect, Rgba(0, 160, 0, 255));
+    }
+    for p in state.powerups.iter() {
+        screen.rect_outline(p.collider.rect, Rgba(0, 160, 160, 255));
+    }
+    for p in state.projs.iter() {
+        screen.rect_outline(p.rect, Rgba(160, 0, 160, 255));
+    }
+    for t in state.triggers.iter() {
+        screen.rect_outline(t.rect, Rgba(255, 255, 255, 255));
+    }
+    for (a, b) in state.debug_contacts.iter() {
+        screen.rect_outline(*a, Rgba(255, 255, 0, 255));
+        screen.rect_outline(*b, Rgba(255, 255, 0, 255));
+    }
+}
+
+fn update_game(
+    state: &mut GameState,
+    input: &InputState,
+    sprite_sheet: &Atlas,
+    tile_sheet: &Atlas,
+    waves: &WaveTable,
+) {
+    // Speeds are in pixels a frame, so everything moves frames' worth this update
+    let frames = frames_per_step(state);
+    let whole_frames = frames_this_step(state);
+    let scroll_dy = scroll_speed(state.stage, state.difficulty);
+    let camera = state.axis.turn_f(Vec2f(0.0, scroll_dy as f32));
+    let scroll_total = state.scroll_remainder + scroll_dy as f32 * frames;
+    let scroll_step = scroll_total.trunc();
+    state.scroll_remainder = scroll_total - scroll_step;
+    let scroll_step = state.axis.turn(Vec2i(0, scroll_step as i32));
+    state.scroll = state.scroll + scroll_step;
+
+    // Stage changes wait for the screen to go dark
+    if let Some(transition) = state.transition {
+        if transition.at_midpoint(state.frame_count, whole_frames) {
+            match transition.to {
+                GameStage::Boss => state.stage = GameStage::BossWarning(state.frame_count),
+                stage => state.stage = stage,
+            }
+        } else if transition.done(state.frame_count) {
+            state.transition = None;
+        }
+    }
+    // Nothing new happens in the old stage while it's fading out
+    let fading_out = state
+        .transition
+        .is_some_and(|t| t.before_midpoint(state.frame_count));
+
+    match state.stage {
+        _ if fading_out => {}
+        GameStage::Rocks(spawning, wave) => {
+            // spawn rocks every 360 frames
+            if on_schedule(state, 360, 120) {
+                generate_terrain(state, tile_sheet, TerrainType::RockField);
+            }
+
+            match run_wave(
+                state,
+                sprite_sheet,
+                tile_sheet,
+                &waves.rocks,
+                spawning,
+                wave,
+            ) {
+                WaveProgress::Wave(spawning, wave) => {
+                    state.stage = GameStage::Rocks(spawning, wave)
+                }
+                WaveProgress::StageOver => begin_transition(state, GameStage::Boulders(true, 1)),
+            }
+        }
+
+        GameStage::Boulders(spawning, wave) => {
+            match run_wave(
+                state,
+                sprite_sheet,
+                tile_sheet,
+                &waves.boulders,
+                spawning,
+                wave,
+            ) {
+                WaveProgress::Wave(spawning, wave) => {
+                    state.stage = GameStage::Boulders(spawning, wave)
+                }
+                WaveProgress::StageOver => begin_transition(state, GameStage::Boss),
+            }
+        }
+
+        GameStage::BossWarning(since) => {
+            if state.frame_count - since >= BOSS_WARNING_FRAMES {
+                enter_boss_stage(state, sprite_sheet);
+            }
+        }
+
+        GameStage::Boss => {
+            update_boss(state);
+        }
+
+        GameStage::Victory(won_frame) => {
+            // Take a breather, then start the loop over
+            if state.frame_count - won_frame >= 150 {
+                begin_transition(state, GameStage::Rocks(true, 1));
+            }
+        }
+
+        GameStage::Menu | GameStage::GameOver(_) => {}
+    }
+
+    // Update player position
+    // Player control goes here
+    match state.stage {
+        GameStage::Rocks(_, _)
+        | GameStage::Boulders(_, _)
+        | GameStage::BossWarning(_)
+        | GameStage::Boss
+        | GameStage::Victory(_) => {
+            // The same speed in every direction (diagonals too), on top of
+            // keeping up with the camera. Aiming holds the ship still so the
+            // direction buttons can point the guns instead.
+            let speed = if input.aim { 0.0 } else { PLAYER_SPEED };
+            state.aim = aim_direction(input);
+            let mut vel = steering(input).normalized() * speed;
+            // A dash carries the ship along whatever's held
+            if state.frame_count < state.dash_until {
+                vel = state.dash_direction * (DASH_DISTANCE / DASH_FRAMES as f32);
+            }
+            let vel = vel + camera;
+            if let Some(player) = state.mobiles.get_mut(state.player) {
+                player.collider.set_velocity(vel);
+            }
+
+            // Debug jumps are instant and cancel any fade in progress
+            match input.skip_to {
+                Some(GameStage::Boss) if state.boss.is_none() => {
+                    state.transition = None;
+                    enter_boss_stage(state, sprite_sheet);
+                }
+                Some(GameStage::Boss) | None => {}
+                Some(stage) => {
+                    state.transition = None;
+                    state.stage = stage;
+                }
+            }
+        }
+
+        GameStage::Menu | GameStage::GameOver(_) => state.aim = None,
+    }
+
+    // Update enemy AI movements
+    update_enemies(state, camera, frames);
+
+    // Update position of mobiles
+    for m in state.mobiles.iter_mut() {
+        let Vec2i(dx, dy) = m.collider.step(frames);
+        m.move_pos(dx, dy);
+    }
+    clamp_player_to_view(state);
+
+    // Homing projectiles turn towards the closest target, or fly straight on
+    // if there isn't one
+    for proj in state.projs.iter_mut().filter(|p| p.homing) {
+        let from = proj.rect.center();
+        let target = state
+            .mobiles
+            .iter()
+            .filter(|m| m.collider.team() != proj.team)
+            .map(|m| m.collider.rect.center())
+            .min_by_key(|&c| {
+                let d = c - from;
+                d.0 * d.0 + d.1 * d.1
+            });
+        if let Some(target) = target {
+            proj.steer_towards(target, frames);
+        }
+    }
+
+    // Update proj position
+    for proj in state.projs.iter_mut() {
+        proj.fly(frames);
+    }
+    // Misses that have flown off screen (or lasted too long) are gone for good
+    let view = view_rect(state.scroll);
+    let now = state.frame_count;
+    state
+        .projs
+        .retain(|p| p.rect.touches_or_overlaps(&view) && !p.expired(now));
+
+    // Power-ups drift down the screen (or left, scrolling sideways), and are
+    // lost once they fall off the far side
+    let drift = state.axis.turn(Vec2i(0, whole_frames as i32));
+    for powerup in state.powerups.iter_mut() {
+        powerup.move_pos(drift.0, drift.1);
+    }
+    let (axis, scroll) = (state.axis, state.scroll);
+    state
+        .powerups
+        .retain(|p| !axis.behind(scroll, p.collider.rect));
+    state.triggers.retain(|t| !axis.behind(scroll, t.rect));
+
+    // Popups float up and fade out
+    update_popups(
+        &mut state.popups,
+        state.frame_count,
+        scroll_step,
+        whole_frames as i32,
+    );
+
+    // Explosion debris flies, falls and fades
+    update_particles(&mut state.particles, state.frame_count, frames);
+
+    // Muzzle flashes stay on the gun until they've played through
+    let now = state.frame_count;
+    state
+        .flashes
+        .retain_mut(|f| !f.animation_sm.current_anim(now).done(now));
+    if let Some(player) = state.mobiles.get(state.player) {
+        let gun = state.axis.nose(player.collider.rect);
+        for flash in state.flashes.iter_mut() {
+            center_on(flash, gun, now);
+        }
+    }
+
+    // Drifting rocks move through the world; the camera scrolls past them as usual
+    for t in state.terrains.iter_mut() {
+        let Vec2i(dx, dy) = t.collider.drift(frames);
+        if dx != 0 || dy != 0 {
+            t.move_pos(dx, dy);
+        }
+    }
+
+    // Update wall position (scroll with camera)
+    for wall in state.walls.iter_mut() {
+        wall.move_pos(scroll_step.0, scroll_step.1);
+    }
+
+    // Solid tiles within view act as static terrain
+    let view = view_rect(state.scroll);
+    let mut solid_tiles: Vec<Rect> = vec![];
+    for map in state.tilemaps.iter() {
+        solid_tiles.extend(map.solid_rects_in(view));
+    }
+
+    // Detect collisions: Generate contacts
+    let mut contacts: Vec<Contact> = vec![];
+    collision::gather_contacts(
+        &state.terrains,
+        &state.mobiles,
+        &state.walls,
+        &solid_tiles,
+        &state.projs,
+        &state.powerups,
+        &state.triggers,
+        &mut contacts,
+    );
+
+    // Remember what collided for the debug overlay, before handle_contact
+    // removes things and the contacts' indices go stale
+    if state.debug_draw {
+        state.debug_contacts = contacts
+            .iter()
+            .map(|c| {
+                collision::contact_rects(
+                    c,
+                    &state.terrains,
+                    &state.mobiles,
+                    &state.walls,
+                    &solid_tiles,
+                    &state.projs,
+                    &state.powerups,
+                    &state.triggers,
+                )
+            })
+            .collect();
+        state.debug_tiles = solid_tiles.clone();
+    }
+
+    // Handle collisions
+    let mut events: Vec<GameEvent> = vec![];
+    // A streak ends when the kills dry up
+    if state.frame_count - state.last_kill_frame > COMBO_WINDOW {
+        state.combo = 0;
+    }
+    let multiplier = combo_multiplier(state.combo);
+
+    let (mut player_is_alive, kills, points) = collision::handle_contact(
+        &mut state.terrains,
+        &mut state.mobiles,
+        state.player,
+        &mut state.projs,
+        &mut state.powerups,
+        &mut state.triggers,
+        &mut contacts,
+        &mut events,
+        &mut state.popups,
+        state.frame_count,
+        camera,
+        state.god_mode,
+    );
+
+    if kills > 0 {
+        state.kills += kills;
+        state.combo += kills as u32;
+        state.last_kill_frame = state.frame_count;
+    }
+
+    let mut destroyed: Vec<Vec2i> = vec![];
+    let mut hits: Vec<Vec2i> = vec![];
+    let mut entered: Vec<usize> = vec![];
+    let now = state.frame_count;
+    for event in events {
+        state.sounds.extend(sound_for(&event));
+        match event {
+            GameEvent::TerrainDestroyed { pos, center } => {
+                spawn_burst(&mut state.particles, center, Rgba(120, 100, 80, 255), now);
+                destroyed.push(pos);
+            }
+            GameEvent::EnemyDestroyed {
+                pos,
+                center,
+                points,
+            } => {
+                let gained = format!("+{}", points * multiplier);
+                state.popups.push(Popup::new(gained, pos, now));
+                spawn_burst(&mut state.particles, center, Rgba(255, 160, 40, 255), now);
+                destroyed.push(pos);
+            }
+            // ...or when the player gets hurt
+            GameEvent::PlayerDamaged { .. } => state.combo = 0,
+            // Heals were applied during collision handling, weapons are up to us
+            GameEvent::PowerUpCollected(PowerUpKind::Weapon(weapon)) => {
+                state.weapon = weapon;
+                if let Some(player) = state.mobiles.get_mut(state.player) {
+                    let cooldown = weapon.interval(PLAYER_FIRE_COOLDOWN);
+                    player.collider.set_fire_cooldown(cooldown);
+                }
+            }
+            GameEvent::PowerUpCollected(_) => {}
+            GameEvent::TileShot(i) => hits.push(Vec2i(solid_tiles[i].x, solid_tiles[i].y)),
+            GameEvent::TriggerEntered(i) => entered.push(i),
+        }
+    }
+
+    // Shot rock tiles crumble to rubble, which can be flown through
+    for pos in break_rock_tiles(state, &hits) {
+        let center = pos + Vec2i(TILE_SZ as i32 / 2, TILE_SZ as i32 / 2);
+        spawn_burst(
+            &mut state.particles,
+            center,
+            Rgba(120, 100, 80, 255),
+            state.frame_count,
+        );
+        destroyed.push(pos);
+    }
+
+    drop_powerups(state, sprite_sheet, &destroyed);
+
+    if let GameStage::Rocks(_, _)
+    | GameStage::Boulders(_, _)
+    | GameStage::BossWarning(_)
+    | GameStage::Boss
+    | GameStage::Victory(_) = state.stage
+    {
+        // Spend a life to carry on, if there's one to spare and a player to spend it on
+        if !player_is_alive && state.lives > 1 && state.mobiles.contains(state.player) {
+            state.lives -= 1;
+            respawn_player(state);
+            player_is_alive = true;
+        }
+
+        // Set GameOver stage if player is not alive
+        if !player_is_alive {
+            state.lives = 0;
+            if let Some(player) = state.mobiles.get_mut(state.player) {
+                player.sprite.animation_sm.input("die", state.frame_count);
+                // Drift with the camera, which starts scrolling again on game over
+                let scroll_dy = scroll_speed(GameStage::GameOver(0), state.difficulty);
+                let drift = state.axis.turn_f(Vec2f(0.0, scroll_dy as f32));
+                player.collider.set_velocity(drift);
+            }
+            state.stage = GameStage::GameOver(state.frame_count);
+            state.transition = None;
+            state.boss = None;
+        } else {
+            state.score += points * multiplier;
+
+            // Bonus zones pay out once, then go
+            for &i in entered.iter() {
+                let TriggerKind::Bonus(points) = state.triggers[i].kind;
+                state.score += points;
+                let at = Vec2i(state.triggers[i].rect.x, state.triggers[i].rect.y);
+                state
+                    .popups
+                    .push(Popup::new(format!("+{}", points), at, state.frame_count));
+            }
+            let mut i = 0;
+            state.triggers.retain(|_| {
+                i += 1;
+                !entered.contains(&(i - 1))
+            });
+
+            // The fight is won once the boss is gone from mobiles
+            if state.stage == GameStage::Boss && !state.mobiles.iter().any(|m| m.collider.is_boss) {
+                state.boss = None;
+                state.stage = GameStage::Victory(state.frame_count);
+            }
+        }
+
+        // Fire the current weapon once per press (if it has cooled down).
+        // Holding on charges instead of firing again, and letting go once
+        // charged past CHARGE_THRESHOLD adds one big charged shot.
+        // Shots go straight ahead unless they're being aimed
+        let axis = state.axis;
+        let point = |proj: Projectile| match aim_direction(input) {
+            Some(direction) => proj.aimed(axis.unturn_f(direction)),
+            None => proj,
+        };
+        // Nothing fires without a player
+        if let Some(player) = state.mobiles.get_mut(state.player) {
+            let player = &mut player.collider;
+            if input.fire {
+                // No charge yet means the button just went down
+                let pressed = state.charge == 0;
+                // Held counts as charging even in an update too short to cover a frame
+                state.charge = (state.charge + whole_frames).max(1);
+                if pressed && player.ready_to_fire(state.frame_count) {
+                    player.last_fired = state.frame_count;
+                    let shots = state.weapon.fire(player, axis, state.frame_count);
+                    state.projs.extend(shots.into_iter().map(point));
+                }
+            } else {
+                if state.charge >= CHARGE_THRESHOLD {
+                    player.last_fired = state.frame_count;
+                    let shot = Projectile::charged(player, state.charge, axis, state.frame_count);
+                    state.projs.push(point(shot));
+                }
+                state.charge = 0;
+            }
+            if player.last_fired == state.frame_count {
+                let mut flash = muzzle_flash(sprite_sheet, state.frame_count, Vec2i(0, 0));
+                center_on(&mut flash, axis.nose(player.rect), state.frame_count);
+                state.flashes.push(flash);
+                state.sounds.push(Sound::Fire);
+            }
+        }
+    }
+
+    // The background follows the stage
+    state
+        .backdrop
+        .fade_to(state.stage.background(), state.frame_count);
+
+    // Blink while invulnerable
+    let now = state.frame_count;
+    for m in state.mobiles.iter_mut() {
+        m.sprite.visible = !(m.collider.is_invulnerable(now) && (now / 4) % 2 == 0);
+    }
+}
+
+// Which way the direction buttons point, relative to the camera; (0, 0) for none
+fn steering(input: &InputState) -> Vec2f {
+    Vec2f(
+        if input.right {
+            1.0
+        } else if input.left {
+            -1.0
+        } else {
+            0.0
+        },
+        if input.up {
+            -1.0
+        } else if input.down {
+            1.0
+        } else {
+            0.0
+        },
+    )
+}
+
+// Where shots are aimed, as a unit vector, if aim and a direction are both held
+fn aim_direction(input: &InputState) -> Option<Vec2f> {
+    let steer = steering(input);
+    (input.aim && steer != Vec2f(0.0, 0.0)).then(|| steer.normalized())
+}
+
+// Moves sprite so the frame it's showing is centered on at
+fn center_on(sprite: &mut Sprite, at: Vec2i, now: usize) {
+    let frame = sprite.frame(now);
+    sprite.position = at - Vec2i(frame.w as i32 / 2, frame.h as i32 / 2);
+}
+
+// Where a stage's waves are up to after this frame
+enum WaveProgress {
+    // Still to spawn (true) or waiting for it to be cleared (false), and which wave
+    Wave(bool, usize),
+    // The last wave is over and the stage should move on
+    StageOver,
+}
+
+/**
+ * One frame of wave number wave (from 1) out of waves, which spawns on the first
+ * frame that's a multiple of its spawn_interval and is over once none of its
+ * enemies are left alive.
+ */
+fn run_wave(
+    state: &mut GameState,
+    sprite_sheet: &Atlas,
+    tile_sheet: &Atlas,
+    waves: &[Wave],
+    spawning: bool,
+    wave: usize,
+) -> WaveProgress {
+    let current = match waves.get(wave - 1) {
+        Some(current) => current,
+        None => return WaveProgress::StageOver,
+    };
+    if spawning {
+        if !on_schedule(state, current.spawn_interval, 0) {
+            return WaveProgress::Wave(true, wave);
+        }
+        // Only ever top up to the wave's count, whatever's still about
+        let target = state.difficulty.enemy_count(current.enemy_count);
+        let missing = target.saturating_sub(living_enemies(state));
+        if missing > 0 {
+            spawn_wave(state, sprite_sheet, missing);
+        }
+        if let Some(terrain_type) = current.terrain_type {
+            generate_terrain(state, tile_sheet, terrain_type);
+        }
+    }
+    if living_enemies(state) > 0 {
+        return WaveProgress::Wave(false, wave);
+    }
+    if current.enemy_count > 0 {
+        clear_wave(state);
+    }
+    let stage_over = match current.next {
+        StageTransition::NextWave => wave >= waves.len(),
+        StageTransition::NextStage => true,
+        StageTransition::Chance(n, d) => state.rng.gen_range(0..d) < n,
+    };
+    if stage_over {
+        WaveProgress::StageOver
+    } else {
+        WaveProgress::Wave(true, wave + 1)
+    }
+}
+
+// Pays out for a wave whose enemies are all gone, more the less hurt the player is
+fn clear_wave(state: &mut GameState) {
+    let player = match state.mobiles.get(state.player) {
+        Some(player) => player,
+        None => return,
+    };
+    let bonus = (WAVE_CLEAR_BONUS * player.collider.hp)
+        .checked_div(player.collider.max_hp)
+        .unwrap_or(0);
+    state.score += bonus;
+    state.last_wave_clear = Some(state.frame_count);
+    let text = format!("+{}", bonus);
+    state
+        .popups
+        .push(Popup::new(text, player.position, state.frame_count));
+}
+
+/*
+Enemies (the boss included) still around, however many players there are. One
+shot down this frame counts until the next contact pass clears it away, so its
+drop and score land before the wave can end.
+*/
+fn living_enemies(state: &GameState) -> usize {
+    state
+        .mobiles
+        .iter()
+        .filter(|m| !m.collider.is_player)
+        .count()
+}
+
+// What a kill's points are multiplied by: one more for each kill in the streak so far, up to a cap
+fn combo_multiplier(combo: u32) -> usize {
+    (1 + combo as usize).min(MAX_COMBO_MULTIPLIER)
+}
+
+// Occasionally leave a power-up behind where something was destroyed
+fn drop_powerups(state: &mut GameState, sprite_sheet: &Atlas, destroyed: &[Vec2i]) {
+    for &pos in destroyed {
+        if state.rng.gen_range(0..POWERUP_CHANCE) == 0 {
+            let roll = state.rng.gen_range(0..DROP_ROLLS);
+            let powerup = powerup_drop(sprite_sheet, state.frame_count, pos, roll);
+            state.powerups.push(powerup);
+        }
+    }
+}
+
+/*
+The power-up left behind for a roll in 0..DROP_ROLLS. Homing is the best
+weapon, so it's the rarest drop; heals are the likeliest, as they're the only
+way to get hp back mid-run.
+*/
+fn powerup_drop(sprite_sheet: &Atlas, now: usize, pos: Vec2i, roll: u32) -> Entity<PowerUp> {
+    match roll {
+        0 | 1 => weapon_entity(sprite_sheet, now, pos, Weapon::Spread(3)),
+        2 | 3 => weapon_entity(sprite_sheet, now, pos, Weapon::Rapid),
+        4 => weapon_entity(sprite_sheet, now, pos, Weapon::Homing),
+        5 => shield_entity(sprite_sheet, now, pos),
+        _ => heal_entity(sprite_sheet, now, pos),
+    }
+}
+
+// Back where it started at full hp, and briefly untouchable
+fn respawn_player(state: &mut GameState) {
+    let player = &mut state.mobiles[state.player];
+    let start = state.player_start + state.scroll;
+    player.move_pos(start.0 - player.position.0, start.1 - player.position.1);
+    player.collider.hp = player.collider.max_hp;
+    player.collider.set_velocity(Vec2f(0.0, 0.0));
+    player.collider.invuln_until = state.frame_count + RESPAWN_INVULN_FRAMES;
+    state.charge = 0;
+    state.dash_until = 0;
+}
+
+/**
+ * Turns a ship drawn facing up (or, for enemies, down) to face along axis:
+ * the sprite is rotated, and the hitbox with it around its middle.
+ */
+fn face_ahead(mut ship: Entity<Mobile>, axis: ScrollAxis) -> Entity<Mobile> {
+    if axis == ScrollAxis::Vertical {
+        return ship;
+    }
+    ship.sprite.rotation = axis.rotation();
+    let Vec2i(dx, dy) = ship.collider.hitbox_offset;
+    ship.collider.hitbox_offset = Vec2i(dy, dx);
+    let r = ship.collider.rect;
+    ship.resize(r.h, r.w);
+    ship
+}
+
+/**
+ * Randomly picks hexadecimal string of length 4 and uses it to generate terrain objects.
+ */
+fn generate_terrain(state: &mut GameState, tile_sheet: &Atlas, terrain_type: TerrainType) {
+    // Laid out as if the screen scrolled up, then turned to match the axis
+    let axis = state.axis;
+    let scroll = state.scroll;
+    let (rock, wall) = (ROCK_SZ as i32, WALL_SZ as i32);
+    if terrain_type == TerrainType::RockField {
+        for i in 0..(axis.breadth() / rock) {
+            for j in 0..6 {
+                if state.rng.gen_range(0..state.difficulty.rock_chance()) == 0 {
+                    let pos = axis.ahead_of(scroll, rock * (j + 1), i * rock, rock);
+                    let placed = place_terrain(state, tile_sheet, "rock", pos);
+                    if let Some(id) = placed {
+                        if state.rng.gen_range(0..DRIFT_CHANCE) == 0 {
+                            let vx = state.rng.gen_range(-MAX_DRIFT..MAX_DRIFT);
+                            let vy = state.rng.gen_range(0.0..MAX_DRIFT);
+                            state.terrains[id].collider.velocity = axis.turn_f(Vec2f(vx, vy));
+                        }
+                    }
+                }
+            }
+        }
+    } else if terrain_type == TerrainType::BoulderWall {
+        let seed = state.rng.gen_range(0..256);
+        let mut gaps = vec![];
+        for i in 0..(axis.breadth() / wall) as usize {
+            let slot = i as i32 * wall;
+            // ~1/3 chance of adding rocks instead of walls for 3 slots
+            if ((seed + i) / 3) % 3 == 0 {
+                gaps.push(i);
+                // let pos1 = Vec2i((i * WALL_SZ) as i32, state.scroll.1 - WALL_SZ as i32);
+                // let pos2 = Vec2i(
+                //     (i * WALL_SZ + ROCK_SZ) as i32,
+                //     state.scroll.1 - WALL_SZ as i32,
+                // );
+                let pos3 = axis.ahead_of(scroll, wall - rock, slot, rock);
+                let pos4 = axis.ahead_of(scroll, wall - rock, slot + rock, rock);
+
+                // place_terrain(state, tile_sheet, "rock", pos1);
+                // place_terrain(state, tile_sheet, "rock", pos2);
+                place_terrain(state, tile_sheet, "rock", pos3);
+                place_terrain(state, tile_sheet, "rock", pos4);
+            } else {
+                let pos = axis.ahead_of(scroll, wall, slot, wall);
+                place_terrain(state, tile_sheet, "boulder", pos);
+            }
+        }
+        add_gap_bonuses(state, &gaps);
+    }
+}
+
+// A bonus zone over each run of neighbouring gap slots (in order) in a boulder wall
+fn add_gap_bonuses(state: &mut GameState, gaps: &[usize]) {
+    let mut runs: Vec<(usize, usize)> = vec![];
+    for &i in gaps {
+        match runs.last_mut() {
+            Some((_, end)) if *end + 1 == i => *end = i,
+            _ => runs.push((i, i)),
+        }
+    }
+    let wall = WALL_SZ as i32;
+    for (start, end) in runs {
+        let across = ((end - start + 1) * WALL_SZ) as u16;
+        let at = state
+            .axis
+            .ahead_of(state.scroll, wall, start as i32 * wall, wall);
+        let (w, h) = match state.axis {
+            ScrollAxis::Vertical => (across, WALL_SZ as u16),
+            ScrollAxis::Horizontal => (WALL_SZ as u16, across),
+        };
+        let rect = Rect {
+            x: at.0,
+            y: at.1,
+            w,
+            h,
+        };
+        state
+            .triggers
+            .push(Trigger::new(rect, TriggerKind::Bonus(GAP_BONUS)));
+    }
+}
+
+/*
+Puts a rock or boulder (kind) with its top left at pos: either as an entity,
+or stamped into the newest tilemap there as the tileset's tiles of that name,
+laid out left to right and top to bottom in a square. Returns the entity, if
+it made one.
+
+An entity that would overlap other terrain or a wall is nudged clear, or left
+out if there's no room nearby, so spawns never pile up into one solid clump.
+Stamped tiles just replace what was there.
+*/
+fn place_terrain(
+    state: &mut GameState,
+    tile_sheet: &Atlas,
+    kind: &str,
+    pos: Vec2i,
+) -> Option<EntityId> {
+    if !state.tile_terrain {
+        let mut terrain = match kind {
+            "rock" => rock_entity(tile_sheet, state.frame_count, pos),
+            _ => boulder_entity(tile_sheet, state.frame_count, pos),
+        };
+        let taken: Vec<Rect> = state
+            .terrains
+            .iter()
+            .map(|t| t.collider.rect)
+            .chain(state.walls.iter().map(|w| w.rect))
+            .collect();
+        let rect = terrain.collider.rect;
+        let clear = collision::clear_placement(rect, &taken)?;
+        terrain.move_pos(clear.x - rect.x, clear.y - rect.y);
+        return Some(state.terrains.insert(terrain));
+    }
+    let tiles = state.tilemaps[0].tileset.tile_ids[kind].clone();
+    let side = (tiles.len() as f64).sqrt() as usize;
+    for (i, tile) in tiles.into_iter().enumerate() {
+        let at = pos + Vec2i((i % side * TILE_SZ) as i32, (i / side * TILE_SZ) as i32);
+        // There's always ground this close above the screen, see TILEMAP_LOOKAHEAD
+        if let Some(map) = state.tilemaps.iter_mut().find(|m| m.bounds().contains(at)) {
+            map.set_tile(at, tile)
+                .expect("terrain tiles are in the tileset");
+            if kind == "rock" {
+                join_rocks(map);
+            }
+        }
+    }
+    None
+}
+
+// Redraws rock tiles side by side on map as patches, with edges where they end
+fn join_rocks(map: &mut Tilemap) {
+    let tileset = Rc::clone(&map.tileset);
+    let rock = tileset.tile_ids["rock"][0];
+    let patch = AutotileSet::from_patch(rock, &tileset.tile_ids["rock_patch"]);
+    map.autotile(rock, &patch);
+}
+
+// Turns any rock tiles at the given positions to rubble, returning where they were
+fn break_rock_tiles(state: &mut GameState, hits: &[Vec2i]) -> Vec<Vec2i> {
+    let tileset = Rc::clone(&state.tilemaps[0].tileset);
+    let mut broken = vec![];
+    for &at in hits {
+        let map = match state.tilemaps.iter_mut().find(|m| m.bounds().contains(at)) {
+            Some(map) => map,
+            None => continue,
+        };
+        let is_rock = |t: usize| {
+            tileset.tile_ids["rock"].contains(&t) || tileset.tile_ids["rock_patch"].contains(&t)
+        };
+        if map.try_tile_idx_at(at).is_some_and(is_rock) {
+            map.set_tile(at, tileset.tile_ids["rubble"][0])
+                .expect("rubble is in the tileset");
+            // What's left of a patch gets new edges
+            join_rocks(map);
+            broken.push(at);
+        }
+    }
+    broken
+}
+
+/*
+Game time runs in frames of FRAME_RATE a second, and speeds are in pixels a
+frame, whatever rate the game is updated at. Each update covers
+FRAME_RATE / update_rate frames: movement scales by that fraction, and the
+clock carries the leftover fractions of a frame in subframe until they add up
+to a whole one. At 60 updates a second every update is exactly one frame.
+*/
+
+// Frames of movement one update covers
+fn frames_per_step(state: &GameState) -> f32 {
+    FRAME_RATE as f32 / state.update_rate as f32
+}
+
+// Whole frames the clock moves on by at the end of this update; can be 0 above FRAME_RATE
+fn frames_this_step(state: &GameState) -> usize {
+    ((state.subframe + FRAME_RATE) / state.update_rate) as usize
+}
+
+fn advance_clock(state: &mut GameState) {
+    state.subframe += FRAME_RATE;
+    state.frame_count += (state.subframe / state.update_rate) as usize;
+    state.subframe %= state.update_rate;
+}
+
+// Does a frame that's phase past a multiple of period fall in this update?
+// With a period of 0 every frame does.
+fn on_schedule(state: &GameState, period: usize, phase: usize) -> bool {
+    let now = state.frame_count;
+    (now..now + frames_this_step(state)).any(|f| period == 0 || f % period == phase)
+}
+
+// How far the camera moves vertically each frame. It speeds up through the
+// boulder field and holds still for the boss.
+fn scroll_speed(stage: GameStage, difficulty: Difficulty) -> i32 {
+    match stage {
+        GameStage::Menu | GameStage::BossWarning(_) | GameStage::Boss => 0,
+        GameStage::Boulders(_, _) => -(difficulty.scroll_speed() + 1),
+        _ => -difficulty.scroll_speed(),
+    }
+}
+
+fn begin_transition(state: &mut GameState, to: GameStage) {
+    if state.transition.is_none() {
+        state.transition = Some(Transition {
+            started_at: state.frame_count,
+            to,
+        });
+    }
+}
+
+fn enter_boss_stage(state: &mut GameState, sprite_sheet: &Atlas) {
+    // Boss starts just above the screen and flies down into view
+    let axis = state.axis;
+    let at = axis.ahead_of(state.scroll, 84, axis.breadth() / 2 - 46, 84);
+    let boss = face_ahead(boss_entity(sprite_sheet, state.frame_count, at), axis);
+    state.boss = Some(Boss::new(boss.collider.hp));
+    state.mobiles.insert(boss);
+    state.stage = GameStage::Boss;
+}
+
+fn update_boss(state: &mut GameState) {
+    // Nobody to fight; the run's about to end anyway
+    let player_pos = match state.mobiles.get(state.player) {
+        Some(player) => player.position,
+        None => return,
+    };
+    let boss = match state.boss.as_mut() {
+        Some(boss) => boss,
+        None => return,
+    };
+    if let Some(e) = state.mobiles.iter_mut().find(|m| m.collider.is_boss) {
+        boss.update_phase(e.collider.hp);
+        boss.steer(&mut e.collider, state.scroll, state.axis);
+        state.projs.extend(boss.fire(
+            &mut e.collider,
+            player_pos,
+            state.axis,
+            state.frame_count,
+            &mut state.rng,
+        ));
+    }
+}
+
+// Brings in a whole wave of enemies at once, just above the screen in a random formation
+fn spawn_wave(state: &mut GameState, sprite_sheet: &Atlas, enemy_count: usize) {
+    let formation = Formation::random(&mut state.rng);
+    // Enemy sprites are 32 wide, so this puts the formation's middle mid-screen
+    let axis = state.axis;
+    let lead = axis.ahead_of(state.scroll, 30, axis.breadth() / 2 - 16, 25);
+    for offset in formation.offsets(enemy_count) {
+        let enemy = enemy_entity(
+            sprite_sheet,
+            state.frame_count,
+            lead + axis.turn(offset),
+            &mut state.rng,
+        );
+        let mut enemy = face_ahead(enemy, axis);
+        enemy.collider.hp = state.difficulty.enemy_hp(enemy.collider.hp);
+        enemy.collider.max_hp = enemy.collider.hp;
+        let kind = EnemyKind::random(&mut state.rng);
+        enemy.collider.kind = kind;
+        enemy.collider.score_value = kind.score_value();
+        let cooldown = kind.fire_cooldown(enemy.collider.fire_cooldown);
+        enemy.collider.set_fire_cooldown(cooldown);
+        state.mobiles.insert(enemy);
+    }
+}
+
+fn cleanup_terrain(state: &mut GameState) {
+    let frame_count = state.frame_count;
+    let view = view_rect(state.scroll);
+    state.terrains.retain(|t| {
+        t.collider.rect.touches_or_overlaps(&view) || frame_count - t.collider.created_at < 300
+    });
+}
+
+/**
+ * Destroys every enemy, enemy projectile and destructible terrain on screen,
+ * if there's a bomb left and a game in progress. Things are only marked dead
+ * (hp 0) here; the next handle_contact clears them away and scores the kills,
+ * just as if they'd been shot.
+ */
+fn use_bomb(state: &mut GameState) {
+    let playing = matches!(
+        state.stage,
+        GameStage::Rocks(_, _)
+            | GameStage::Boulders(_, _)
+            | GameStage::BossWarning(_)
+            | GameStage::Boss
+    );
+    let player_alive = state
+        .mobiles
+        .get(state.player)
+        .is_some_and(|p| p.collider.hp > 0);
+    if !playing || state.bombs == 0 || !player_alive {
+        return;
+    }
+    state.bombs -= 1;
+    state.last_bomb = Some(state.frame_count);
+
+    let view = view_rect(state.scroll);
+    for m in state.mobiles.iter_mut() {
+        let enemy = &mut m.collider;
+        if enemy.is_player || !enemy.rect.touches_or_overlaps(&view) {
+            continue;
+        }
+        // The boss just takes a big hit
+        enemy.hp = if enemy.is_boss {
+            enemy.hp.saturating_sub(BOMB_BOSS_DAMAGE)
+        } else {
+            0
+        };
+    }
+    for t in state.terrains.iter_mut() {
+        if t.collider.destructible && t.collider.rect.touches_or_overlaps(&view) {
+            t.collider.hp = 0;
+        }
+    }
+    let solid: Vec<Vec2i> = state
+        .tilemaps
+        .iter()
+        .flat_map(|map| map.solid_rects_in(view))
+        .map(|r| Vec2i(r.x, r.y))
+        .collect();
+    break_rock_tiles(state, &solid);
+    state
+        .projs
+        .retain(|p| p.team == Team::Player || !p.rect.touches_or_overlaps(&view));
+}
+
+/**
+ * Lunges the player DASH_DISTANCE the way it's steering, if it's steering at
+ * all, there's a game in progress and the last dash has cooled down. The
+ * player can't be hurt for the first DASH_INVULN_FRAMES, so a well timed dash
+ * goes right through shots, enemies and rocks. Like any other movement it
+ * stops at the edges of the screen.
+ */
+fn start_dash(state: &mut GameState, input: &InputState) {
+    let playing = matches!(
+        state.stage,
+        GameStage::Rocks(_, _)
+            | GameStage::Boulders(_, _)
+            | GameStage::BossWarning(_)
+            | GameStage::Boss
+            | GameStage::Victory(_)
+    );
+    let now = state.frame_count;
+    let direction = steering(input);
+    let player = match state.mobiles.get_mut(state.player) {
+        Some(player) => &mut player.collider,
+        None => return,
+    };
+    if !playing || player.hp == 0 || now < state.dash_ready_at || direction == Vec2f(0.0, 0.0) {
+        return;
+    }
+    state.dash_direction = direction.normalized();
+    state.dash_until = now + DASH_FRAMES;
+    state.dash_ready_at = now + DASH_COOLDOWN;
+    player.invuln_until = player.invuln_until.max(now + DASH_INVULN_FRAMES);
+}
+
+// The part of the world the camera sees
+fn view_rect(scroll: Vec2i) -> Rect {
+    Rect {
+        x: scroll.0,
+        y: scroll.1,
+        w: WIDTH as u16,
+        h: HEIGHT as u16,
+    }
+}
+
+/**
+ * Keeps the player's hitbox inside the view even if the walls miss it. The
+ * walls sit just outside the view, so after this the player never overlaps
+ * them and restitution has nothing left to undo.
+ */
+fn clamp_player_to_view(state: &mut GameState) {
+    let view = view_rect(state.scroll);
+    let player = match state.mobiles.get_mut(state.player) {
+        Some(player) => player,
+        None => return,
+    };
+    let r = player.collider.rect;
+    let dx = (view.x - r.x).max(0) + (view.right() - r.right()).min(0);
+    let dy = (view.y - r.y).max(0) + (view.bottom() - r.bottom()).min(0);
+    player.move_pos(dx, dy);
+}
+
+fn update_enemies(state: &mut GameState, camera: Vec2f, frames: f32) {
+    // With no player about, enemies make for where one would start and hold their fire
+    let (player_pos, player_alive) = match state.mobiles.get(state.player) {
+        Some(player) => (player.position, player.collider.hp > 0),
+        None => (state.player_start + state.scroll, false),
+    };
+    let axis = state.axis;
+    let around = Surroundings {
+        player_pos,
+        terrains: &state.terrains,
+        scroll: state.scroll,
+        camera,
+        axis,
+        frames,
+    };
+
+    for enemy in state
+        .mobiles
+        .iter_mut()
+        // Skip anything a bomb has just finished off
+        .filter(|m| !m.collider.is_player && !m.collider.is_boss && m.collider.hp > 0)
+    {
+        let vel = enemy.collider.kind.steer(enemy, &around);
+        enemy.collider.set_velocity(vel);
+
+        // Shoot back once on screen, if there's anyone to shoot at
+        if player_alive
+            && axis.depth(state.scroll, enemy.collider.rect) >= 0
+            && enemy.collider.ready_to_fire(state.frame_count)
+        {
+            enemy.collider.last_fired = state.frame_count;
+            state.projs.push(Projectile::from_enemy(
+                &enemy.collider,
+                axis,
+                state.frame_count,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particle::BURST_SIZE;
+    use crate::texture::Texture;
+    use crate::tiles::Tile;
+    use std::collections::HashMap;
+
+    // The game's own sheets and tiles, set up the way main does
+    fn config(axis: ScrollAxis) -> GameConfig {
+        let sprite_sheet = Rc::new(Texture::with_file(Path::new(
+            "content/spaceshooter/Spritesheet/sheet.png",
+        )));
+        let tile_sheet = Rc::new(Texture::with_file(Path::new("content/tilesheet.png")));
+        let mut tile_ids = HashMap::new();
+        tile_ids.insert(
+            String::from("ground"),
+            vec![3169, 2905, 1, 356, 268, 312, 61, 144],
+        );
+        tile_ids.insert(String::from("rock"), vec![727]);
+        tile_ids.insert(String::from("rubble"), vec![991]);
+        tile_ids.insert(String::from("boulder"), vec![1763, 1764, 1851, 1852]);
+        let mut tiles = vec![Tile { solid: false }; 88 * 69];
+        for id in [727, 1763, 1764, 1851, 1852] {
+            tiles[id].solid = true;
+        }
+        let tile_atlas = Atlas::load(Path::new("content/tiles.csv"), &tile_sheet).unwrap();
+        let mut tileset = Tileset::new(tiles, &tile_sheet, tile_ids);
+        let rock_patch = tileset.tiles_in(tile_atlas.rect("rock_patch"));
+        for id in rock_patch.iter() {
+            tileset.tiles[*id].solid = true;
+        }
+        tileset
+            .tile_ids
+            .insert(String::from("rock_patch"), rock_patch);
+        let tileset = Rc::new(tileset);
+        let start_ground = Path::new("content/start_ground.csv");
+        GameConfig {
+            sprite_sheet: Rc::new(
+                Atlas::load(Path::new("content/sprites.csv"), &sprite_sheet).unwrap(),
+            ),
+            tile_sheet: Rc::new(tile_atlas),
+            tileset: Rc::clone(&tileset),
+            high_score_path: None,
+            difficulty: Difficulty::default(),
+            waves: WaveTable::default(),
+            tile_terrain: false,
+            update_rate: FRAME_RATE,
+            scroll_axis: axis,
+            player: PlayerConfig::for_axis(axis),
+            start_ground: Tilemap::from_csv(start_ground, Vec2i(0, 0), &tileset).unwrap(),
+        }
+    }
+
+    // A game that's just left the title screen
+    fn started(config: GameConfig) -> Game {
+        let mut game = Game::with_seed(config, 7);
+        game.step(InputState {
+            start: true,
+            ..InputState::default()
+        });
+        game
+    }
+
+    #[test]
+    fn the_first_screen_repeats_the_start_ground() {
+        let mut config = config(ScrollAxis::Vertical);
+        let tileset = Rc::clone(&config.tileset);
+        config.start_ground =
+            Tilemap::from_csv_str("3169,2905\n1,356", Vec2i(0, 0), &tileset).unwrap();
+        let game = Game::with_seed(config, 1);
+        let ground = game.state.tilemaps[0].layer_to_csv(0);
+        let mut rows = ground.lines();
+        assert!(rows.next().unwrap().starts_with("3169,2905,3169,2905,"));
+        assert!(rows.next().unwrap().starts_with("1,356,1,356,"));
+        assert!(rows.next().unwrap().starts_with("3169,2905,"));
+    }
+
+    fn boss(game: &Game) -> Option<&Mobile> {
+        let mut mobiles = game.state.mobiles.iter();
+        mobiles.find(|m| m.collider.is_boss).map(|m| &m.collider)
+    }
+
+    // A player shot into the middle of target, standing still
+    fn shoot(game: &mut Game, target: Rect) {
+        let Vec2i(x, y) = target.center();
+        let shot = Projectile::at(x, y, 0.0, 0.0, game.frame_count());
+        game.state.projs.push(Projectile {
+            team: Team::Player,
+            ..shot
+        });
+    }
+
+    #[test]
+    fn killing_the_boss_wins() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        game.step(InputState {
+            skip_to: Some(GameStage::Boss),
+            ..InputState::default()
+        });
+        assert_eq!(game.stage(), GameStage::Boss);
+        // A player shot into the middle of the boss every frame until it goes down
+        for _ in 0..1000 {
+            let target = match boss(&game) {
+                Some(boss) => boss.rect,
+                None => break,
+            };
+            shoot(&mut game, target);
+            game.step(InputState::default());
+        }
+        assert!(boss(&game).is_none());
+        assert!(matches!(game.stage(), GameStage::Victory(_)));
+        assert!(game.state.boss.is_none());
+    }
+
+    // An ordinary enemy with its sprite's top left at pos, which has just fired
+    fn add_enemy(game: &mut Game, pos: Vec2i) -> EntityId {
+        let sprite_sheet = &game.config.sprite_sheet;
+        let enemy = enemy_entity_with_look(sprite_sheet, game.state.frame_count, pos, 0);
+        game.state.mobiles.insert(enemy)
+    }
+
+    #[test]
+    fn enemies_fire_backwards_once_cooled_down() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        let at = game.scroll() + Vec2i(100, 100);
+        let enemy = add_enemy(&mut game, at);
+        let fired_at = game.frame_count();
+        let cooldown = game.state.mobiles[enemy].collider.fire_cooldown;
+        game.state.projs.clear();
+
+        game.state.frame_count = fired_at + cooldown - 1;
+        update_enemies(&mut game.state, Vec2f(0.0, 0.0), 1.0);
+        assert!(game.state.projs.is_empty());
+
+        game.state.frame_count = fired_at + cooldown;
+        update_enemies(&mut game.state, Vec2f(0.0, 0.0), 1.0);
+        assert_eq!(game.state.projs.len(), 1);
+        let shot = &game.state.projs[0];
+        assert_eq!(shot.team, Team::Enemy);
+        assert!(shot.vy > 0.0);
+        assert_eq!(shot.vx, 0.0);
+    }
+
+    #[test]
+    fn runs_a_thousand_frames_headless() {
+        let mut game = Game::with_seed(config(ScrollAxis::Vertical), 1);
+        let font = Font::monospace(&Rc::new(Texture::with_file(Path::new(
+            "content/monospace_font.png",
+        ))));
+        let mut fb = vec![0; WIDTH * HEIGHT * 4];
+        for i in 0..1000 {
+            game.step(InputState {
+                start: i == 0,
+                left: i % 200 < 60,
+                right: i % 200 >= 140,
+                fire: i % 20 < 5,
+                ..InputState::default()
+            });
+            // Drawing's checked too, just not every frame
+            if i % 10 == 0 {
+                let mut screen = Screen::wrap(&mut fb, WIDTH, HEIGHT, 4, game.scroll());
+                game.draw(&mut screen, &font);
+            }
+        }
+        assert_eq!(game.frame_count(), 1000);
+    }
+
+    // Where every terrain entity is
+    fn terrain_positions(game: &Game) -> Vec<Vec2i> {
+        game.state.terrains.iter().map(|t| t.position).collect()
+    }
+
+    #[test]
+    fn same_seed_same_terrain() {
+        let mut a = Game::with_seed(config(ScrollAxis::Vertical), 99);
+        let mut b = Game::with_seed(config(ScrollAxis::Vertical), 99);
+        for game in [&mut a, &mut b] {
+            game.state.god_mode = true;
+            for i in 0..400 {
+                game.step(InputState {
+                    start: i == 0,
+                    ..InputState::default()
+                });
+            }
+        }
+        assert!(!terrain_positions(&a).is_empty());
+        assert_eq!(terrain_positions(&a), terrain_positions(&b));
+    }
+
+    #[test]
+    fn hard_rock_fields_are_denser_than_easy() {
+        let rocks = |difficulty| {
+            let mut game = Game::with_seed(
+                GameConfig {
+                    difficulty,
+                    ..config(ScrollAxis::Vertical)
+                },
+                5,
+            );
+            let tile_sheet = Rc::clone(&game.config.tile_sheet);
+            generate_terrain(&mut game.state, &tile_sheet, TerrainType::RockField);
+            game.state.terrains.len()
+        };
+        assert!(rocks(Difficulty::Hard) > rocks(Difficulty::Easy));
+    }
+
+    #[test]
+    fn destroyed_rocks_burst_into_particles_that_fade() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        let at = game.scroll() + Vec2i(100, 200);
+        let mut rock = rock_entity(&game.config.tile_sheet, game.frame_count(), at);
+        rock.collider.hp = 1;
+        let target = rock.collider.rect;
+        let rock = game.state.terrains.insert(rock);
+        shoot(&mut game, target);
+        game.step(InputState::default());
+        assert!(!game.state.terrains.contains(rock));
+        assert_eq!(game.state.particles.len(), BURST_SIZE);
+
+        let ttl = game.state.particles[0].ttl;
+        for _ in 0..ttl {
+            game.step(InputState::default());
+        }
+        assert!(game.state.particles.is_empty());
+    }
+
+    #[test]
+    fn transition_swaps_stage_at_its_midpoint() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        let start = game.frame_count();
+        begin_transition(&mut game.state, GameStage::Boulders(true, 1));
+        let transition = game.state.transition.unwrap();
+        while game.frame_count() < start + TRANSITION_FRAMES / 2 {
+            game.step(InputState::default());
+            assert!(matches!(game.stage(), GameStage::Rocks(_, _)));
+        }
+        assert_eq!(transition.alpha(game.frame_count()), 255);
+        game.step(InputState::default());
+        assert_eq!(game.stage(), GameStage::Boulders(true, 1));
+        while game.frame_count() <= start + TRANSITION_FRAMES {
+            game.step(InputState::default());
+        }
+        assert!(game.state.transition.is_none());
+    }
+
+    #[test]
+    fn start_leaves_the_menu_for_the_first_stage() {
+        let mut game = Game::with_seed(config(ScrollAxis::Vertical), 7);
+        assert_eq!(game.stage(), GameStage::Menu);
+        // Nothing else starts the game, and the world holds still meanwhile
+        for _ in 0..10 {
+            game.step(InputState {
+                fire: true,
+                ..InputState::default()
+            });
+        }
+        assert_eq!(game.stage(), GameStage::Menu);
+        assert_eq!(game.scroll(), Vec2i(0, 0));
+        game.step(InputState {
+            start: true,
+            ..InputState::default()
+        });
+        assert_eq!(game.stage(), GameStage::Rocks(true, 1));
+    }
+
+    #[test]
+    fn debug_overlay_shows_what_collision_saw() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        game.toggle_debug_draw();
+        // A rock tile right under the player
+        let under = game.state.mobiles[game.state.player].collider.rect.center();
+        let mut maps = game.state.tilemaps.iter_mut();
+        let map = maps.find(|m| m.bounds().contains(under)).unwrap();
+        map.set_tile(under, 727).unwrap();
+        game.step(InputState::default());
+
+        let view = view_rect(game.scroll());
+        let solid: Vec<Rect> = game
+            .state
+            .tilemaps
+            .iter()
+            .flat_map(|m| m.solid_rects_in(view))
+            .collect();
+        assert_eq!(game.state.debug_tiles, solid);
+        let tile = *solid.iter().find(|r| r.contains(under)).unwrap();
+        let player = game.state.mobiles[game.state.player].collider.rect;
+        assert!(game.state.debug_contacts.contains(&(player, tile)));
+    }
+
+    fn player(game: &Game) -> &Mobile {
+        &game.state.mobiles[game.state.player].collider
+    }
+
+    #[test]
+    fn holding_left_never_leaves_the_screen() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        // Without walls only the clamp keeps the player in
+        game.state.walls.clear();
+        for _ in 0..300 {
+            game.step(InputState {
+                left: true,
+                ..InputState::default()
+            });
+            assert!(player(&game).rect.x >= 0);
+        }
+        assert_eq!(player(&game).rect.x, 0);
+    }
+
+    // How fast the player moves over the ground while holding input
+    fn steered_speed(input: InputState) -> f32 {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.step(input);
+        let state = &game.state;
+        let camera = Vec2f(0.0, scroll_speed(state.stage, state.difficulty) as f32);
+        (player(&game).velocity() - camera).length()
+    }
+
+    #[test]
+    fn diagonals_are_no_faster_than_straight_lines() {
+        let right = InputState {
+            right: true,
+            ..InputState::default()
+        };
+        let up_right = InputState { up: true, ..right };
+        let straight = steered_speed(right);
+        assert!((straight - PLAYER_SPEED).abs() < 1e-4);
+        assert!((steered_speed(up_right) - straight).abs() < 1e-4);
+    }
+
+    #[test]
+    fn the_camera_stops_for_the_boss() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        let before = game.scroll();
+        game.step(InputState::default());
+        assert!(game.scroll().1 < before.1);
+
+        game.step(InputState {
+            skip_to: Some(GameStage::Boss),
+            ..InputState::default()
+        });
+        assert_eq!(scroll_speed(game.stage(), game.difficulty()), 0);
+        let before = game.scroll();
+        for _ in 0..10 {
+            game.step(InputState::default());
+        }
+        assert_eq!(game.scroll(), before);
+    }
+
+    #[test]
+    fn homing_shots_turn_towards_an_enemy_off_to_the_side() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        let now = game.frame_count();
+        let shots = |weapon: Weapon| weapon.fire(player(&game), ScrollAxis::Vertical, now);
+        let (homing, straight) = (shots(Weapon::Homing), shots(Weapon::Single));
+        game.state.projs.extend(homing.into_iter().chain(straight));
+        let nose = game.state.projs[0].rect.center();
+        add_enemy(&mut game, nose + Vec2i(150, -100));
+        for _ in 0..5 {
+            game.step(InputState::default());
+        }
+        assert!(game.state.projs[0].get_velocity().0 > 0.0);
+        assert_eq!(game.state.projs[1].get_velocity().0, 0.0);
+    }
+
+    #[test]
+    fn homing_shots_fly_straight_with_nothing_to_chase() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        let now = game.frame_count();
+        let shot = Weapon::Homing.fire(player(&game), ScrollAxis::Vertical, now);
+        let velocity = shot[0].get_velocity();
+        game.state.projs.extend(shot);
+        for _ in 0..5 {
+            game.step(InputState::default());
+        }
+        assert_eq!(game.state.projs[0].get_velocity(), velocity);
+    }
+
+    #[test]
+    fn waves_spawn_on_their_tables_schedule() {
+        let mut config = config(ScrollAxis::Vertical);
+        let wave = Wave {
+            spawn_interval: 50,
+            enemy_count: 2,
+            terrain_type: None,
+            next: StageTransition::NextWave,
+        };
+        config.waves = WaveTable {
+            rocks: vec![
+                wave.clone(),
+                Wave {
+                    spawn_interval: 80,
+                    ..wave.clone()
+                },
+                wave.clone(),
+                // No wait at all
+                Wave {
+                    spawn_interval: 0,
+                    ..wave
+                },
+            ],
+            boulders: vec![],
+        };
+        let mut game = started(config);
+        game.state.god_mode = true;
+        // Each wave is wiped out the moment it shows up, so the next can come
+        let mut spawned_at = vec![];
+        while spawned_at.len() < 4 && game.frame_count() < 1000 {
+            let now = game.frame_count();
+            game.step(InputState::default());
+            if living_enemies(&game.state) > 0 {
+                spawned_at.push(now);
+                game.state.mobiles.retain(|m| m.collider.is_player);
+            }
+        }
+        assert_eq!(spawned_at, vec![50, 80, 100, 102]);
+        // All four done, the rocks are over
+        for _ in 0..TRANSITION_FRAMES {
+            game.step(InputState::default());
+        }
+        assert!(matches!(game.stage(), GameStage::Boulders(_, _)));
+    }
+
+    #[test]
+    fn a_high_score_that_cant_be_saved_is_left_for_main_to_report() {
+        // A file where the high score's directory should be
+        let blocker = std::env::temp_dir().join("high_score_blocker");
+        fs::write(&blocker, "").unwrap();
+        let mut game = started(GameConfig {
+            high_score_path: Some(blocker.join("highscore.dat")),
+            ..config(ScrollAxis::Vertical)
+        });
+        game.state.score = 10;
+        game.record_score();
+        let errors = game.take_errors();
+        assert!(
+            matches!(errors[..], [GameError::HighScore(_, _)]),
+            "{:?}",
+            errors
+        );
+        assert!(game.take_errors().is_empty());
+        fs::remove_file(&blocker).unwrap();
+    }
+
+    #[test]
+    fn a_replay_plays_back_on_the_settings_it_was_recorded_with() {
+        let path = std::env::temp_dir().join("replay_settings.rec");
+        let mut recorded = Game::with_seed(
+            GameConfig {
+                tile_terrain: true,
+                update_rate: 120,
+                ..config(ScrollAxis::Horizontal)
+            },
+            11,
+        );
+        recorded.record(&path).unwrap();
+        for frame in 0..300 {
+            recorded.step(scripted(frame / 2));
+        }
+        recorded.stop_recording();
+
+        let mut replayed = Game::replay(config(ScrollAxis::Vertical), &path).unwrap();
+        assert_eq!(replayed.config.scroll_axis, ScrollAxis::Horizontal);
+        assert_eq!(
+            replayed.config.player,
+            PlayerConfig::for_axis(ScrollAxis::Horizontal)
+        );
+        assert!(replayed.config.tile_terrain);
+        assert_eq!(replayed.update_rate(), 120);
+        while replayed.replaying() {
+            replayed.step(InputState::default());
+        }
+        assert_eq!(replayed.frame_count(), recorded.frame_count());
+        assert_eq!(replayed.score(), recorded.score());
+        let position = |game: &Game| game.state.mobiles[game.state.player].position;
+        assert_eq!(position(&replayed), position(&recorded));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_spare_life_brings_the_player_back_at_full_hp() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        game.state.lives = 2;
+        let id = game.state.player;
+        game.state.mobiles[id].collider.hp = 1;
+        // An enemy shot right in the middle of the player
+        let Vec2i(x, y) = player(&game).rect.center();
+        let now = game.frame_count();
+        game.state.projs.push(Projectile::at(x, y, 0.0, 0.0, now));
+        game.step(InputState::default());
+
+        assert!(!matches!(game.stage(), GameStage::GameOver(_)));
+        assert_eq!(game.lives(), 1);
+        let player = player(&game);
+        assert_eq!(player.hp, player.max_hp);
+        assert!(player.is_invulnerable(game.frame_count()));
+    }
+
+    #[test]
+    fn bombs_clear_the_screen_until_they_run_out() {
+        let bomb = InputState {
+            bomb: true,
+            ..InputState::default()
+        };
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        game.state.bombs = 1;
+        let scroll = game.scroll();
+        add_enemy(&mut game, scroll + Vec2i(40, 60));
+        add_enemy(&mut game, scroll + Vec2i(180, 60));
+        let now = game.frame_count();
+        let Vec2i(x, y) = scroll + Vec2i(20, 200);
+        game.state.projs.push(Projectile::at(x, y, 0.0, 0.0, now));
+        game.step(bomb);
+        assert_eq!(living_enemies(&game.state), 0);
+        assert!(game.state.projs.iter().all(|p| p.team == Team::Player));
+        assert_eq!(game.bombs(), 0);
+
+        // Out of bombs, pressing it again does nothing
+        game.step(InputState::default());
+        let scroll = game.scroll();
+        let enemy = add_enemy(&mut game, scroll + Vec2i(40, 60));
+        game.step(bomb);
+        assert!(game.state.mobiles.contains(enemy));
+        assert_eq!(game.bombs(), 0);
+    }
+
+    // Shoots enemy every frame until it's gone, leaving no stray shots about
+    fn kill(game: &mut Game, enemy: EntityId) {
+        while let Some(target) = game.state.mobiles.get(enemy).map(|e| e.collider.rect) {
+            shoot(game, target);
+            game.step(InputState::default());
+        }
+        game.state.projs.clear();
+    }
+
+    // Score from killing two enemies, gap frames apart, with no waves to get in the way
+    fn two_kills(gap: usize) -> usize {
+        let mut config = config(ScrollAxis::Vertical);
+        let never = Wave {
+            spawn_interval: usize::MAX,
+            enemy_count: 0,
+            terrain_type: None,
+            next: StageTransition::NextWave,
+        };
+        config.waves.rocks = vec![never];
+        let mut game = started(config);
+        game.state.god_mode = true;
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        let at = game.scroll() + Vec2i(40, 60);
+        let first = add_enemy(&mut game, at);
+        kill(&mut game, first);
+        for _ in 0..gap {
+            game.step(InputState::default());
+        }
+        // Brought in late, so it can't ram the player in the meantime
+        let at = game.scroll() + Vec2i(180, 60);
+        let second = add_enemy(&mut game, at);
+        kill(&mut game, second);
+        game.score()
+    }
+
+    #[test]
+    fn quick_kills_outscore_spaced_out_ones() {
+        let quick = two_kills(5);
+        let spaced = two_kills(COMBO_WINDOW + 10);
+        assert!(quick > spaced, "{} vs {}", quick, spaced);
+    }
+
+    #[test]
+    fn a_kills_popup_shows_the_points_it_scored() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        let at = game.scroll() + Vec2i(40, 60);
+        let enemy = add_enemy(&mut game, at);
+        game.state.mobiles[enemy].collider.score_value = EnemyKind::Shooter.score_value();
+        // Two kills into a streak
+        game.state.combo = 2;
+        game.state.last_kill_frame = game.frame_count();
+        game.state.popups.clear();
+        let before = game.score();
+        kill(&mut game, enemy);
+
+        let gained = game.score() - before;
+        assert_eq!(
+            gained,
+            EnemyKind::Shooter.score_value() * combo_multiplier(2)
+        );
+        let popup = game.state.popups.iter().find(|p| p.text.starts_with('+'));
+        assert_eq!(popup.unwrap().text, format!("+{}", gained));
+    }
+
+    // Weaving side to side and firing in bursts, starting the game on the first frame
+    fn scripted(frame: usize) -> InputState {
+        InputState {
+            start: frame == 0,
+            left: frame % 60 < 30,
+            right: frame % 60 >= 30,
+            fire: frame % 20 < 3,
+            ..InputState::default()
+        }
+    }
+
+    #[test]
+    fn a_replay_plays_out_the_same_as_the_recorded_run() {
+        let path = std::env::temp_dir().join("replay_round_trip.rec");
+        let mut recorded = Game::with_seed(config(ScrollAxis::Vertical), 11);
+        recorded.record(&path).unwrap();
+        for frame in 0..200 {
+            recorded.step(scripted(frame));
+        }
+        recorded.stop_recording();
+
+        let mut replayed = Game::replay(config(ScrollAxis::Vertical), &path).unwrap();
+        // What step is given is ignored while there's recording left
+        for _ in 0..200 {
+            assert!(replayed.replaying());
+            replayed.step(InputState::default());
+        }
+        assert!(!replayed.replaying());
+        assert_eq!(replayed.frame_count(), recorded.frame_count());
+        assert_eq!(replayed.score(), recorded.score());
+        assert_eq!(replayed.player_hp(), recorded.player_hp());
+        let position = |game: &Game| game.state.mobiles[game.state.player].position;
+        assert_eq!(position(&replayed), position(&recorded));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_quick_save_loads_back_the_same_run() {
+        let mut saved = Game::with_seed(config(ScrollAxis::Vertical), 5);
+        saved.state.god_mode = true;
+        for frame in 0..150 {
+            saved.step(scripted(frame));
+        }
+        let text = saved.save_to_string();
+        let mut loaded = Game::with_seed(config(ScrollAxis::Vertical), 99);
+        loaded.load_from_str(&text).unwrap();
+        // God mode is a debug switch rather than part of the run, so it isn't saved
+        loaded.state.god_mode = true;
+
+        let (a, b) = (&saved.state, &loaded.state);
+        assert_eq!(b.stage, a.stage);
+        assert_eq!(b.frame_count, a.frame_count);
+        assert_eq!(b.scroll, a.scroll);
+        assert_eq!(b.score, a.score);
+        assert_eq!((b.lives, b.bombs, b.weapon), (a.lives, a.bombs, a.weapon));
+        let mobiles = |s: &GameState| -> Vec<(Rect, usize, bool)> {
+            let mobiles = s.mobiles.iter().map(|m| &m.collider);
+            mobiles.map(|m| (m.rect, m.hp, m.is_player)).collect()
+        };
+        assert_eq!(mobiles(b), mobiles(a));
+        let terrains = |s: &GameState| -> Vec<(Rect, usize)> {
+            let terrains = s.terrains.iter().map(|t| &t.collider);
+            terrains.map(|t| (t.rect, t.hp)).collect()
+        };
+        assert_eq!(terrains(b), terrains(a));
+        let projs = |s: &GameState| -> Vec<Rect> { s.projs.iter().map(|p| p.rect).collect() };
+        assert_eq!(projs(b), projs(a));
+
+        // And they carry on the same way
+        for frame in 150..250 {
+            saved.step(scripted(frame));
+            loaded.step(scripted(frame));
+        }
+        assert_eq!(loaded.score(), saved.score());
+        assert_eq!(mobiles(&loaded.state), mobiles(&saved.state));
+    }
+
+    #[test]
+    fn saving_leaves_the_run_alone() {
+        let mut saved = Game::with_seed(config(ScrollAxis::Vertical), 5);
+        let mut unsaved = Game::with_seed(config(ScrollAxis::Vertical), 5);
+        for game in [&mut saved, &mut unsaved] {
+            game.state.god_mode = true;
+            for frame in 0..150 {
+                game.step(scripted(frame));
+            }
+        }
+        let text = saved.save_to_string();
+        assert_eq!(saved.save_to_string(), text);
+
+        for frame in 150..250 {
+            saved.step(scripted(frame));
+            unsaved.step(scripted(frame));
+        }
+        assert_eq!(saved.save_to_string(), unsaved.save_to_string());
+    }
+
+    #[test]
+    fn a_sideways_save_loads_back_sideways() {
+        let mut saved = Game::with_seed(config(ScrollAxis::Horizontal), 5);
+        saved.state.god_mode = true;
+        for frame in 0..150 {
+            saved.step(scripted(frame));
+        }
+        let mut loaded = Game::with_seed(config(ScrollAxis::Horizontal), 99);
+        loaded.load_from_str(&saved.save_to_string()).unwrap();
+
+        assert_eq!(loaded.state.axis, ScrollAxis::Horizontal);
+        assert_eq!(loaded.scroll(), saved.scroll());
+        let walls = |g: &Game| -> Vec<Rect> { g.state.walls.iter().map(|w| w.rect).collect() };
+        assert_eq!(walls(&loaded), walls(&saved));
+        assert_eq!(player(&loaded).rect, player(&saved).rect);
+    }
+
+    #[test]
+    fn a_save_from_other_settings_wont_load() {
+        let saved = started(config(ScrollAxis::Vertical));
+        let text = saved.save_to_string();
+        let others = [
+            config(ScrollAxis::Horizontal),
+            GameConfig {
+                tile_terrain: true,
+                ..config(ScrollAxis::Vertical)
+            },
+            GameConfig {
+                update_rate: 2,
+                ..config(ScrollAxis::Vertical)
+            },
+        ];
+        for other in others {
+            let mut game = Game::with_seed(other, 1);
+            assert!(matches!(
+                game.load_from_str(&text),
+                Err(SaveError::Settings(_))
+            ));
+        }
+    }
+
+    // A game with one rock a little way in front of the player, placed as an
+    // entity or stamped into the tilemaps, and where the rock is
+    fn with_rock(tile_terrain: bool) -> (Game, Rect) {
+        let mut game = started(GameConfig {
+            tile_terrain,
+            ..config(ScrollAxis::Vertical)
+        });
+        let tile_sheet = Rc::clone(&game.config.tile_sheet);
+        let at = game.scroll() + Vec2i(144, 320);
+        place_terrain(&mut game.state, &tile_sheet, "rock", at);
+        let rock = Rect {
+            x: at.0,
+            y: at.1,
+            w: ROCK_SZ as u16,
+            h: ROCK_SZ as u16,
+        };
+        (game, rock)
+    }
+
+    #[test]
+    fn rocks_stamped_side_by_side_join_up_and_break_apart() {
+        let (mut game, rock) = with_rock(true);
+        let tile_sheet = Rc::clone(&game.config.tile_sheet);
+        let right = Vec2i(rock.x + TILE_SZ as i32, rock.y);
+        place_terrain(&mut game.state, &tile_sheet, "rock", right);
+        let tileset = Rc::clone(&game.config.tileset);
+        let patch = &tileset.tile_ids["rock_patch"];
+        // Or whole maps of ground would count as rock
+        assert!(patch
+            .iter()
+            .all(|t| !tileset.tile_ids["ground"].contains(t)));
+        let tile_at = |game: &Game, at: Vec2i| {
+            game.state
+                .tilemaps
+                .iter()
+                .find_map(|m| m.try_tile_idx_at(at))
+                .unwrap()
+        };
+        // The left end of a row a tile high, then the right end
+        assert_eq!(tile_at(&game, Vec2i(rock.x, rock.y)), patch[3]);
+        assert_eq!(tile_at(&game, right), patch[5]);
+
+        // Breaking one leaves the other a rock on its own again
+        break_rock_tiles(&mut game.state, &[right]);
+        assert_eq!(tile_at(&game, right), tileset.tile_ids["rubble"][0]);
+        assert_eq!(
+            tile_at(&game, Vec2i(rock.x, rock.y)),
+            tileset.tile_ids["rock"][0]
+        );
+    }
+
+    #[test]
+    fn tile_rocks_collide_like_rock_entities() {
+        for tile_terrain in [false, true] {
+            // A shot into the rock stops there...
+            let (mut game, rock) = with_rock(tile_terrain);
+            shoot(&mut game, rock);
+            game.step(InputState::default());
+            assert!(
+                game.state.projs.iter().all(|p| p.team != Team::Player),
+                "tile_terrain {}",
+                tile_terrain
+            );
+
+            // ...and flying into it costs a life
+            let (mut game, rock) = with_rock(tile_terrain);
+            let lives = game.lives();
+            let id = game.state.player;
+            let at = game.state.mobiles[id].collider.rect;
+            game.state.mobiles[id].move_pos(rock.x - at.x, rock.y - at.y);
+            game.step(InputState::default());
+            assert_eq!(game.lives(), lives - 1, "tile_terrain {}", tile_terrain);
+        }
+    }
+
+    #[test]
+    fn firing_flashes_the_gun_until_the_flash_plays_out() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        // Wait out the cooldown from the start of the run
+        while !player(&game).ready_to_fire(game.frame_count()) {
+            game.step(InputState::default());
+        }
+        game.state.projs.clear();
+        // Shots go off as soon as fire's pressed
+        game.step(InputState {
+            fire: true,
+            ..InputState::default()
+        });
+        assert_eq!(game.state.projs.len(), 1);
+        assert_eq!(game.state.flashes.len(), 1);
+
+        // Two frames of three each, then it's gone
+        let fired_at = game.frame_count();
+        while !game.state.flashes.is_empty() {
+            assert!(game.frame_count() < fired_at + 6);
+            game.step(InputState::default());
+        }
+        assert_eq!(game.frame_count(), fired_at + 6);
+    }
+
+    #[test]
+    fn drifting_rocks_move_on_top_of_the_scroll() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        let tile_sheet = Rc::clone(&game.config.tile_sheet);
+        let mut add_rock = |at: Vec2i, velocity: Vec2f| {
+            let at = game.scroll() + at;
+            let mut rock = rock_entity(&tile_sheet, game.frame_count(), at);
+            rock.collider.velocity = velocity;
+            game.state.terrains.insert(rock)
+        };
+        let still = add_rock(Vec2i(40, 100), Vec2f(0.0, 0.0));
+        let drifting = add_rock(Vec2i(240, 100), Vec2f(-0.5, 0.25));
+        let position = |game: &Game, id| game.state.terrains[id].position;
+        let (still_at, drifting_at) = (position(&game, still), position(&game, drifting));
+        let scroll = game.scroll();
+
+        for _ in 0..20 {
+            game.step(InputState::default());
+        }
+        // The camera's moved on, but only the drifting rock has moved through the world
+        assert_ne!(game.scroll(), scroll);
+        assert_eq!(position(&game, still), still_at);
+        assert_eq!(position(&game, drifting), drifting_at + Vec2i(-10, 5));
+
+        // Once it's drifted off screen it's cleaned up like any other rock
+        game.state.terrains[drifting].move_pos(-300, 0);
+        game.state.frame_count += 300;
+        cleanup_terrain(&mut game.state);
+        assert!(!game.state.terrains.contains(drifting));
+        assert!(game.state.terrains.contains(still));
+    }
+
+    #[test]
+    fn a_lucky_kill_drops_a_heal_that_heals_up_to_max() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        // Force the rolls: a seed whose first two draw a drop, then a heal
+        let seed = (0..)
+            .find(|&seed| {
+                let mut rng = ChaCha12Rng::seed_from_u64(seed);
+                rng.gen_range(0..POWERUP_CHANCE) == 0 && rng.gen_range(0..DROP_ROLLS) >= 6
+            })
+            .unwrap();
+        game.state.rng = ChaCha12Rng::seed_from_u64(seed);
+        let at = player(&game).rect;
+        let sprite_sheet = Rc::clone(&game.config.sprite_sheet);
+        drop_powerups(&mut game.state, &sprite_sheet, &[Vec2i(at.x, at.y)]);
+        assert_eq!(game.state.powerups.len(), 1);
+        assert_eq!(
+            game.state.powerups[0].collider.kind,
+            PowerUpKind::Heal(HEAL_AMOUNT)
+        );
+
+        // It's dropped right on the player, who's only a little hurt
+        let id = game.state.player;
+        let max_hp = player(&game).max_hp;
+        game.state.mobiles[id].collider.hp = max_hp - HEAL_AMOUNT / 2;
+        game.step(InputState::default());
+        assert_eq!(player(&game).hp, max_hp);
+        assert!(game.state.powerups.is_empty());
+    }
+
+    #[test]
+    fn a_second_covers_the_same_ground_at_any_update_rate() {
+        // Where the player and camera have got to after a second of holding right and up
+        let after_a_second = |update_rate: u32| {
+            let mut game = started(GameConfig {
+                update_rate,
+                ..config(ScrollAxis::Vertical)
+            });
+            game.state.god_mode = true;
+            let start = (player(&game).rect, game.scroll(), game.frame_count());
+            for _ in 0..update_rate {
+                game.step(InputState {
+                    right: true,
+                    up: true,
+                    ..InputState::default()
+                });
+            }
+            let end = player(&game).rect;
+            (
+                Vec2i(end.x - start.0.x, end.y - start.0.y),
+                game.scroll() - start.1,
+                game.frame_count() - start.2,
+            )
+        };
+        let (moved, scrolled, frames) = after_a_second(FRAME_RATE);
+        assert_eq!(frames, FRAME_RATE as usize);
+        assert_ne!(moved, Vec2i(0, 0));
+        assert_ne!(scrolled, Vec2i(0, 0));
+        for rate in [30, 120] {
+            assert_eq!(
+                after_a_second(rate),
+                (moved, scrolled, frames),
+                "{} a second",
+                rate
+            );
+        }
+    }
+
+    #[test]
+    fn terrain_spawned_on_terrain_is_nudged_clear() {
+        // Nothing's laid out on the title screen
+        let mut game = Game::with_seed(config(ScrollAxis::Vertical), 3);
+        assert!(game.state.terrains.is_empty());
+        let tile_sheet = Rc::clone(&game.config.tile_sheet);
+        let at = Vec2i(150, -40);
+        let first = place_terrain(&mut game.state, &tile_sheet, "rock", at).unwrap();
+        let second = place_terrain(&mut game.state, &tile_sheet, "rock", at);
+        let rect = |id| game.state.terrains[id].collider.rect;
+        assert_eq!(Vec2i(rect(first).x, rect(first).y), at);
+        // Pushed off the first the short way, rather than on top of it
+        let second = second.unwrap();
+        assert_eq!(Vec2i(rect(second).x, rect(second).y), at + Vec2i(16, 0));
+        assert!(!rect(second).intersects(&rect(first)));
+
+        // Likewise a rock laid over a wall
+        let wall = game.state.walls[0].rect;
+        let rock = place_terrain(&mut game.state, &tile_sheet, "rock", Vec2i(wall.x, -40));
+        let rock = game.state.terrains[rock.unwrap()].collider.rect;
+        assert!(game.state.walls.iter().all(|w| !w.rect.intersects(&rock)));
+    }
+
+    #[test]
+    fn shots_that_leave_the_screen_or_fly_too_long_are_dropped() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        game.state.projs.clear();
+        let now = game.frame_count();
+        let Vec2i(x, y) = game.scroll() + Vec2i(20, 40);
+        // One heading up off the top, one hanging about where nothing will hit it
+        game.state.projs.push(Projectile {
+            team: Team::Player,
+            ..Projectile::at(x, y, 0.0, -60.0, now)
+        });
+        game.state.projs.push(Projectile {
+            team: Team::Player,
+            ..Projectile::at(x, y + 100, 0.0, 0.0, now)
+        });
+        game.step(InputState::default());
+        assert_eq!(game.state.projs.len(), 1);
+        assert_eq!(game.state.projs[0].get_velocity(), (0.0, 0.0));
+
+        // Old enough, it goes even though it's still on screen
+        game.state.projs[0].created_at = game.frame_count();
+        game.state.frame_count += 300;
+        game.step(InputState::default());
+        assert!(game.state.projs.is_empty());
+    }
+
+    #[test]
+    fn god_mode_shrugs_off_rocks_shots_and_rams() {
+        let (mut game, rock) = with_rock(false);
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        game.state.god_mode = true;
+        let lives = game.lives();
+        // Sat on the rock, with an enemy shot and an enemy right on top of it
+        let id = game.state.player;
+        let at = game.state.mobiles[id].collider.rect;
+        game.state.mobiles[id].move_pos(rock.x - at.x, rock.y - at.y);
+        let Vec2i(x, y) = player(&game).rect.center();
+        let now = game.frame_count();
+        game.state.projs.push(Projectile::at(x, y, 0.0, 0.0, now));
+        let enemy = add_enemy(&mut game, Vec2i(rock.x, rock.y));
+        game.step(InputState::default());
+
+        assert_eq!(game.lives(), lives);
+        assert_eq!(player(&game).hp, player(&game).max_hp);
+        assert!(!game.state.mobiles.contains(enemy));
+        assert!(game.state.projs.iter().all(|p| p.team == Team::Player));
+    }
+
+    #[test]
+    fn the_background_fades_to_each_stages_color() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        let rocks = game.stage().background();
+        assert_eq!(game.state.backdrop.color(game.frame_count()), rocks);
+        game.step(InputState {
+            skip_to: Some(GameStage::Boss),
+            ..InputState::default()
+        });
+        assert_eq!(game.stage(), GameStage::Boss);
+        let boss = GameStage::Boss.background();
+        let start = game.frame_count();
+        let color = |game: &Game| game.state.backdrop.color(game.frame_count());
+
+        // Partway there it's somewhere between the two
+        while game.frame_count() < start + BACKDROP_FADE_FRAMES / 2 {
+            game.step(InputState::default());
+        }
+        let mid = color(&game);
+        assert_ne!(mid, rocks);
+        assert_ne!(mid, boss);
+
+        while game.frame_count() < start + BACKDROP_FADE_FRAMES {
+            game.step(InputState::default());
+        }
+        assert_eq!(color(&game), boss);
+    }
+
+    #[test]
+    fn the_boss_waits_for_its_warning_to_play_out() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        begin_transition(&mut game.state, GameStage::Boss);
+        let since = loop {
+            game.step(InputState::default());
+            if let GameStage::BossWarning(since) = game.stage() {
+                break since;
+            }
+        };
+
+        // The camera holds while the warning's up, with no boss yet
+        let scroll = game.scroll();
+        while game.frame_count() < since + BOSS_WARNING_FRAMES {
+            game.step(InputState::default());
+            assert_eq!(game.scroll(), scroll);
+            assert!(boss(&game).is_none());
+        }
+        game.step(InputState::default());
+        assert_eq!(game.stage(), GameStage::Boss);
+        assert!(boss(&game).is_some());
+    }
+
+    #[test]
+    fn holding_fire_shoots_once_then_lets_go_a_charged_shot() {
+        // How many times the gun goes off over a second of holding fire
+        let shots_held = |weapon: Weapon| {
+            let mut game = started(config(ScrollAxis::Vertical));
+            game.state.god_mode = true;
+            game.state.mobiles.retain(|m| m.collider.is_player);
+            game.state.weapon = weapon;
+            let id = game.state.player;
+            let cooldown = weapon.interval(PLAYER_FIRE_COOLDOWN);
+            game.state.mobiles[id].collider.set_fire_cooldown(cooldown);
+            while !player(&game).ready_to_fire(game.frame_count()) {
+                game.step(InputState::default());
+            }
+            let mut shots = 0;
+            for _ in 0..60 {
+                game.step(InputState {
+                    fire: true,
+                    ..InputState::default()
+                });
+                if player(&game).last_fired + 1 == game.frame_count() {
+                    shots += 1;
+                }
+            }
+            (game, shots)
+        };
+        // Even the rapid gun only goes off on the press
+        let (mut game, single) = shots_held(Weapon::Single);
+        let (_, rapid) = shots_held(Weapon::Rapid);
+        assert_eq!(single, 1);
+        assert_eq!(rapid, 1);
+
+        // A second's hold is well past the threshold
+        game.state.projs.clear();
+        game.step(InputState::default());
+        assert_eq!(game.state.projs.len(), 1);
+        let normal = Weapon::Single.fire(player(&game), ScrollAxis::Vertical, 0)[0].rect;
+        assert!(game.state.projs[0].rect.w > normal.w);
+        assert_eq!(game.state.charge, 0);
+
+        // A tap lets go of nothing extra
+        while !player(&game).ready_to_fire(game.frame_count()) {
+            game.step(InputState::default());
+        }
+        game.state.projs.clear();
+        game.step(InputState {
+            fire: true,
+            ..InputState::default()
+        });
+        game.step(InputState::default());
+        assert_eq!(game.state.projs.len(), 1);
+    }
+
+    #[test]
+    fn aiming_holds_the_ship_and_points_its_shots() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        while !player(&game).ready_to_fire(game.frame_count()) {
+            game.step(InputState::default());
+        }
+        game.state.projs.clear();
+        let aim = InputState {
+            aim: true,
+            up: true,
+            right: true,
+            ..InputState::default()
+        };
+        let on_screen = |game: &Game| player(game).rect.center() - game.scroll();
+        let before = on_screen(&game);
+        game.step(InputState { fire: true, ..aim });
+        game.step(aim);
+        assert_eq!(on_screen(&game), before);
+
+        // Up and right, as fast as a shot straight up
+        assert_eq!(game.state.projs.len(), 1);
+        let (vx, vy) = game.state.projs[0].get_velocity();
+        let straight =
+            Weapon::Single.fire(player(&game), ScrollAxis::Vertical, 0)[0].get_velocity();
+        assert!(vx > 0.0 && vy < 0.0);
+        assert!((vx + vy).abs() < 1e-6);
+        assert!((vx.hypot(vy) - straight.1.abs()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn a_dash_lunges_untouchable_then_has_to_wait() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        let dash = InputState {
+            dash: true,
+            right: true,
+            ..InputState::default()
+        };
+        let on_screen = |game: &Game| player(game).rect.center() - game.scroll();
+        let before = on_screen(&game);
+        let start = game.frame_count();
+        game.step(dash);
+        assert!(player(&game).is_invulnerable(start + DASH_INVULN_FRAMES - 1));
+        assert!(!player(&game).is_invulnerable(start + DASH_INVULN_FRAMES));
+        while game.frame_count() < start + DASH_FRAMES {
+            game.step(InputState::default());
+        }
+        assert_eq!(on_screen(&game), before + Vec2i(DASH_DISTANCE as i32, 0));
+
+        // Too soon for another, so this one's just steering
+        let before = on_screen(&game);
+        game.step(InputState::default());
+        game.step(dash);
+        let moved = on_screen(&game) - before;
+        assert!(moved.0 > 0 && (moved.0 as f32) < DASH_DISTANCE / DASH_FRAMES as f32);
+    }
+
+    #[test]
+    fn a_horizontal_run_scrolls_right_and_enemies_come_from_the_right() {
+        let mut game = started(config(ScrollAxis::Horizontal));
+        game.state.god_mode = true;
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        let before = game.scroll();
+        game.step(InputState::default());
+        assert!(game.scroll().0 > before.0);
+        assert_eq!(game.scroll().1, before.1);
+
+        let sprite_sheet = Rc::clone(&game.config.sprite_sheet);
+        spawn_wave(&mut game.state, &sprite_sheet, 3);
+        let scroll = game.scroll();
+        let enemies = game.state.mobiles.iter().filter(|m| !m.collider.is_player);
+        for enemy in enemies {
+            let r = enemy.collider.rect;
+            assert!(r.x > scroll.0 + WIDTH as i32 / 2, "{:?}", r);
+            assert!(
+                r.y >= scroll.1 && r.bottom() <= scroll.1 + HEIGHT as i32,
+                "{:?}",
+                r
+            );
+        }
+        assert!(living_enemies(&game.state) > 0);
+    }
+
+    #[test]
+    fn a_bigger_player_starts_where_its_told_with_a_bar_to_match() {
+        let start = Vec2i(40, 300);
+        let mut game = started(GameConfig {
+            player: PlayerConfig { start, max_hp: 200 },
+            ..config(ScrollAxis::Vertical)
+        });
+        assert_eq!(player(&game).hp, 200);
+        let id = game.state.player;
+        assert_eq!(game.state.mobiles[id].position - game.scroll(), start);
+
+        // Three quarters full reaches three quarters of the way along
+        game.state.mobiles[id].collider.hp = 150;
+        let font = Font::monospace(&Rc::new(Texture::with_file(Path::new(
+            "content/monospace_font.png",
+        ))));
+        let mut fb = vec![0; WIDTH * HEIGHT * 4];
+        let mut screen = Screen::wrap(&mut fb, WIDTH, HEIGHT, 4, game.scroll());
+        game.draw(&mut screen, &font);
+        let pixel = |x: usize, y: usize| {
+            let i = (y * WIDTH + x) * 4;
+            Rgba(fb[i], fb[i + 1], fb[i + 2], fb[i + 3])
+        };
+        let green = Rgba(0, 128, 0, 255);
+        assert_eq!(pixel(70 + 148, 529), green);
+        assert_ne!(pixel(70 + 152, 529), green);
+    }
+
+    #[test]
+    fn waves_come_and_go_with_a_second_player_about() {
+        let mut config = config(ScrollAxis::Vertical);
+        let wave = Wave {
+            spawn_interval: 50,
+            enemy_count: 2,
+            terrain_type: None,
+            next: StageTransition::NextWave,
+        };
+        config.waves = WaveTable {
+            rocks: vec![wave.clone(), wave],
+            boulders: vec![],
+        };
+        let mut game = started(config);
+        game.state.god_mode = true;
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        let Vec2i(x, y) = game.scroll() + Vec2i(10, 400);
+        let sprite = player_anim(&game.config.sprite_sheet, 0);
+        let second = Entity::new(sprite, Vec2i(x, y), Mobile::player(x, y, PLAYER_MAX_HP));
+        game.state.mobiles.insert(second);
+
+        // Both waves still turn up in full, and shooting them down still ends the rocks
+        let mut spawned = vec![];
+        let mut before = 0;
+        while !matches!(game.stage(), GameStage::Boulders(_, _)) && game.frame_count() < 1000 {
+            let enemies = game.state.mobiles.iter().map(|m| &m.collider);
+            let targets: Vec<Rect> = enemies.filter(|m| !m.is_player).map(|m| m.rect).collect();
+            for target in targets {
+                shoot(&mut game, target);
+            }
+            game.step(InputState::default());
+            let now = living_enemies(&game.state);
+            if before == 0 && now > 0 {
+                spawned.push(now);
+            }
+            before = now;
+        }
+        assert_eq!(spawned, vec![2, 2]);
+        assert!(matches!(game.stage(), GameStage::Boulders(_, _)));
+        let players = game.state.mobiles.iter().filter(|m| m.collider.is_player);
+        assert_eq!(players.count(), 2);
+    }
+
+    #[test]
+    fn a_wave_only_tops_up_to_its_enemy_count() {
+        let mut config = config(ScrollAxis::Vertical);
+        config.waves = WaveTable {
+            rocks: vec![Wave {
+                spawn_interval: 50,
+                enemy_count: 3,
+                terrain_type: None,
+                next: StageTransition::NextStage,
+            }],
+            boulders: vec![],
+        };
+        let mut game = started(config);
+        game.state.god_mode = true;
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        let leftover = add_enemy(&mut game, Vec2i(100, 100));
+        game.state.mobiles[leftover]
+            .collider
+            .set_fire_cooldown(usize::MAX);
+        while game.frame_count() <= 50 {
+            // Kept in view so it's still about when the wave comes
+            let scroll = game.scroll();
+            let at = game.state.mobiles[leftover].position;
+            let Vec2i(dx, dy) = scroll + Vec2i(100, 100) - at;
+            game.state.mobiles[leftover].move_pos(dx, dy);
+            game.step(InputState::default());
+        }
+        assert_eq!(living_enemies(&game.state), 3);
+    }
+
+    #[test]
+    fn a_run_with_no_player_left_is_game_over() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.lives = 3;
+        let id = game.state.player;
+        game.state.mobiles.remove(id);
+        // Holding everything down, so every player-only path gets a look in
+        game.step(InputState {
+            left: true,
+            up: true,
+            fire: true,
+            dash: true,
+            aim: true,
+            bomb: true,
+            ..InputState::default()
+        });
+        game.step(InputState::default());
+        assert!(matches!(game.stage(), GameStage::GameOver(_)));
+        assert_eq!(game.player_hp(), 0);
+
+        let font = Font::monospace(&Rc::new(Texture::with_file(Path::new(
+            "content/monospace_font.png",
+        ))));
+        let mut fb = vec![0; WIDTH * HEIGHT * 4];
+        let mut screen = Screen::wrap(&mut fb, WIDTH, HEIGHT, 4, game.scroll());
+        game.draw(&mut screen, &font);
+    }
+
+    #[test]
+    fn the_reticle_is_drawn_where_shots_are_aimed() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.god_mode = true;
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        game.step(InputState::default());
+        assert_eq!(reticle_pos(&game.state), None);
+
+        // Aiming right puts it out to the right of the gun
+        game.step(InputState {
+            aim: true,
+            right: true,
+            ..InputState::default()
+        });
+        let gun = ScrollAxis::Vertical.nose(player(&game).rect);
+        let at = reticle_pos(&game.state).unwrap();
+        assert_eq!(at, gun + Vec2i(RETICLE_DISTANCE as i32, 0));
+
+        let font = Font::monospace(&Rc::new(Texture::with_file(Path::new(
+            "content/monospace_font.png",
+        ))));
+        let mut fb = vec![0; WIDTH * HEIGHT * 4];
+        let mut screen = Screen::wrap(&mut fb, WIDTH, HEIGHT, 4, game.scroll());
+        game.draw(&mut screen, &font);
+        let pixel = |p: Vec2i| {
+            let Vec2i(x, y) = p - game.scroll();
+            let i = (y as usize * WIDTH + x as usize) * 4;
+            Rgba(fb[i], fb[i + 1], fb[i + 2], fb[i + 3])
+        };
+        let red = Rgba(200, 0, 0, 255);
+        for arm in [Vec2i(0, 0), Vec2i(RETICLE_SIZE, 0), Vec2i(0, -RETICLE_SIZE)] {
+            assert_eq!(pixel(at + arm), red);
+        }
+    }
+
+    #[test]
+    fn only_hurt_enemies_get_a_health_bar() {
+        let mut game = started(config(ScrollAxis::Vertical));
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        let scroll = game.scroll();
+        let hurt = add_enemy(&mut game, scroll + Vec2i(40, 100));
+        let whole = add_enemy(&mut game, scroll + Vec2i(180, 100));
+        let enemy = &mut game.state.mobiles[hurt].collider;
+        enemy.max_hp = 40;
+        enemy.hp = 20;
+
+        let font = Font::monospace(&Rc::new(Texture::with_file(Path::new(
+            "content/monospace_font.png",
+        ))));
+        let mut fb = vec![0; WIDTH * HEIGHT * 4];
+        let mut screen = Screen::wrap(&mut fb, WIDTH, HEIGHT, 4, game.scroll());
+        game.draw(&mut screen, &font);
+        let pixel = |p: Vec2i| {
+            let Vec2i(x, y) = p - game.scroll();
+            let i = (y as usize * WIDTH + x as usize) * 4;
+            Rgba(fb[i], fb[i + 1], fb[i + 2], fb[i + 3])
+        };
+        // Sampled inside either end of where a bar would go
+        let ends = |id: EntityId| {
+            let r = game.state.mobiles[id].collider.rect;
+            let y = r.y - ENEMY_BAR_GAP - ENEMY_BAR_H as i32 / 2;
+            (pixel(Vec2i(r.x + 1, y)), pixel(Vec2i(r.right() - 2, y)))
+        };
+        let (green, red) = (Rgba(0, 128, 0, 255), Rgba(128, 0, 0, 255));
+        assert_eq!(ends(hurt), (green, red));
+        let (left, right) = ends(whole);
+        assert!(left != green && right != red);
+    }
+
+    #[test]
+    fn clearing_a_wave_pays_out_by_hp_and_shows_the_banner() {
+        let mut config = config(ScrollAxis::Vertical);
+        let wave = Wave {
+            spawn_interval: 50,
+            enemy_count: 2,
+            terrain_type: None,
+            next: StageTransition::NextWave,
+        };
+        config.waves = WaveTable {
+            rocks: vec![wave.clone(), wave],
+            boulders: vec![],
+        };
+        let mut game = started(config);
+        game.state.god_mode = true;
+        while living_enemies(&game.state) == 0 {
+            game.step(InputState::default());
+        }
+        assert_eq!(game.state.last_wave_clear, None);
+
+        // Half hp left is worth half the bonus
+        let id = game.state.player;
+        let player = &mut game.state.mobiles[id].collider;
+        player.hp = player.max_hp / 2;
+        game.state.mobiles.retain(|m| m.collider.is_player);
+        let score = game.score();
+        let now = game.frame_count();
+        game.step(InputState::default());
+        assert_eq!(game.score(), score + WAVE_CLEAR_BONUS / 2);
+        assert_eq!(game.state.last_wave_clear, Some(now));
+
+        // The screen as drawn, and as it would be without the banner with the
+        // banner's text drawn on top
+        let font = Font::monospace(&Rc::new(Texture::with_file(Path::new(
+            "content/monospace_font.png",
+        ))));
+        let draw = |game: &mut Game, banner: bool| {
+            let cleared = game.state.last_wave_clear;
+            let mut fb = vec![0; WIDTH * HEIGHT * 4];
+            let mut screen = Screen::wrap(&mut fb, WIDTH, HEIGHT, 4, game.scroll());
+            if banner {
+                game.draw(&mut screen, &font);
+            } else {
+                game.state.last_wave_clear = None;
+                game.draw(&mut screen, &font);
+                game.state.last_wave_clear = cleared;
+                let (x, scroll) = (WIDTH as i32 / 2, game.scroll());
+                draw_string_centered(
+                    "WAVE CLEAR",
+                    &mut screen,
+                    &font,
+                    x,
+                    200,
+                    scroll,
+                    Rgba::BLACK,
+                );
+            }
+            fb
+        };
+        assert!(draw(&mut game, true) == draw(&mut game, false));
+
+        // Gone again once WAVE_CLEAR_FRAMES are up
+        while game.frame_count() < now + WAVE_CLEAR_FRAMES {
+            game.step(InputState::default());
+        }
+        let shown = draw(&mut game, true);
+        game.state.last_wave_clear = None;
+        assert!(shown == draw(&mut game, true));
+    }
+}
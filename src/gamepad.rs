@@ -0,0 +1,137 @@
+use crate::game::InputState;
+
+/*
+Gamepad support. The mapping from a pad's sticks and buttons to an InputState
+is plain data and always compiled; actually talking to the hardware goes
+through gilrs and is only built with the "gamepad" feature. Without it (or
+with no pad plugged in) Gamepads::poll just returns None and the keyboard is
+all there is.
+*/
+
+// How far the stick has to be pushed before it counts as a direction
+const STICK_DEADZONE: f32 = 0.3;
+
+/**
+ * A snapshot of the parts of a pad we care about.
+ *
+ * stick: left stick, each axis in -1.0..=1.0 with +y pointing up (as gilrs reports it)
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PadState {
+    pub stick: (f32, f32),
+    pub dpad_left: bool,
+    pub dpad_right: bool,
+    pub dpad_up: bool,
+    pub dpad_down: bool,
+    pub fire: bool,
+    pub start: bool,
+    pub bomb: bool,
+    pub aim: bool,
+    pub dash: bool,
+}
+
+impl PadState {
+    // Stick and d-pad both steer; either one held in a direction is enough
+    pub fn to_input(self) -> InputState {
+        let (x, y) = self.stick;
+        InputState {
+            left: self.dpad_left || x < -STICK_DEADZONE,
+            right: self.dpad_right || x > STICK_DEADZONE,
+            up: self.dpad_up || y > STICK_DEADZONE,
+            down: self.dpad_down || y < -STICK_DEADZONE,
+            fire: self.fire,
+            start: self.start,
+            bomb: self.bomb,
+            aim: self.aim,
+            dash: self.dash,
+            skip_to: None,
+        }
+    }
+}
+
+#[cfg(feature = "gamepad")]
+pub struct Gamepads {
+    // None if gilrs couldn't start up on this machine
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+#[cfg(feature = "gamepad")]
+impl Gamepads {
+    pub fn new() -> Self {
+        let gilrs = match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            // Unsupported platform: gilrs hands back a dummy context that never sees a pad
+            Err(gilrs::Error::NotImplemented(dummy)) => Some(dummy),
+            Err(e) => {
+                eprintln!("Gamepad support unavailable: {}", e);
+                None
+            }
+        };
+        Self { gilrs }
+    }
+
+    /**
+     * Reads the first connected pad, if any. Pads can come and go at any time;
+     * draining gilrs's events here is what keeps its view of them up to date.
+     */
+    pub fn poll(&mut self) -> Option<PadState> {
+        use gilrs::{Axis, Button};
+
+        let gilrs = self.gilrs.as_mut()?;
+        while gilrs.next_event().is_some() {}
+        let (_, pad) = gilrs.gamepads().next()?;
+        Some(PadState {
+            stick: (pad.value(Axis::LeftStickX), pad.value(Axis::LeftStickY)),
+            dpad_left: pad.is_pressed(Button::DPadLeft),
+            dpad_right: pad.is_pressed(Button::DPadRight),
+            dpad_up: pad.is_pressed(Button::DPadUp),
+            dpad_down: pad.is_pressed(Button::DPadDown),
+            fire: pad.is_pressed(Button::South),
+            start: pad.is_pressed(Button::Start),
+            bomb: pad.is_pressed(Button::East),
+            aim: pad.is_pressed(Button::LeftTrigger),
+            dash: pad.is_pressed(Button::West),
+        })
+    }
+}
+
+#[cfg(not(feature = "gamepad"))]
+pub struct Gamepads;
+
+#[cfg(not(feature = "gamepad"))]
+impl Gamepads {
+    pub fn new() -> Self {
+        Gamepads
+    }
+
+    pub fn poll(&mut self) -> Option<PadState> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stick(x: f32, y: f32) -> InputState {
+        PadState {
+            stick: (x, y),
+            ..PadState::default()
+        }
+        .to_input()
+    }
+
+    #[test]
+    fn stick_steers_outside_the_deadzone() {
+        let up_left = stick(-0.8, 0.6);
+        assert!(up_left.left && up_left.up);
+        assert!(!up_left.right && !up_left.down);
+        let down_right = stick(0.5, -1.0);
+        assert!(down_right.right && down_right.down);
+        assert!(!down_right.left && !down_right.up);
+        // A slight tilt, or none at all, steers nowhere
+        for centered in [stick(0.2, -0.2), stick(0.0, 0.0)] {
+            assert_eq!(centered, InputState::default());
+        }
+    }
+}
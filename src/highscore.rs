@@ -0,0 +1,42 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/*
+The best score so far is kept between runs in a tiny text file holding just the
+number. Anything wrong with the file (missing, unreadable, garbage) means
+nobody has set a high score yet.
+*/
+
+pub fn load_high_score(path: &Path) -> usize {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+pub fn save_high_score(path: &Path, score: usize) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, format!("{}\n", score))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn high_score_survives_a_round_trip() {
+        let dir = std::env::temp_dir().join("highscore_round_trip");
+        let path = dir.join("highscore.dat");
+        let _ = fs::remove_dir_all(&dir);
+        // Nothing saved yet
+        assert_eq!(load_high_score(&path), 0);
+        save_high_score(&path, 1234).unwrap();
+        assert_eq!(load_high_score(&path), 1234);
+        fs::write(&path, "not a number").unwrap();
+        assert_eq!(load_high_score(&path), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,120 +1,146 @@
 use pixels::{Pixels, SurfaceTexture};
-use rand::Rng;
 use std::collections::HashMap;
-use std::path::Path;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::time::Instant;
 use winit::dpi::LogicalSize;
-use winit::event::{Event, VirtualKeyCode};
+use winit::event::{Event, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 use winit_input_helper::WinitInputHelper;
 
+mod display;
+
 mod screen;
 use screen::Screen;
 
+mod arena;
+mod atlas;
+use atlas::Atlas;
 mod collision;
-use collision::{Collider, Contact, Mobile, Projectile, Terrain, Wall};
+mod enemy;
 
 mod entity;
-use entity::Entity;
 
 mod texture;
 use texture::Texture;
 
 mod tiles;
-use tiles::{Tile, Tilemap, Tileset, TILE_SZ};
+use tiles::{AnimatedTile, Tile, Tilemap, Tileset};
 
 mod animation;
 
+mod boss;
+
 mod sprite;
-use sprite::*;
 
 mod types;
-use types::*;
+use types::{Rgba, Vec2i};
+
+mod waves;
+use waves::WaveTable;
+
+mod weapon;
+
+mod pattern;
+
+mod popup;
+
+mod particle;
+
+mod trail;
+
+mod starfield;
 
 mod assets;
 use assets::*;
 
-// Now this main module is just for the run-loop and rules processing.
-struct GameState {
-    terrains: Vec<Entity<Terrain>>,
-    tilemaps: Vec<Tilemap>,
-    mobiles: Vec<Entity<Mobile>>,
-    walls: Vec<Wall>,
-    projs: Vec<Projectile>,
-    stage: GameStage,
-    frame_count: usize,
-    scroll: Vec2i,
-    score: usize,
-}
+mod controls;
+use controls::{Action, Controls};
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum GameStage {
-    Rocks(bool, usize),
-    Boulders(usize),
-    GameOver(usize),
-}
+mod settings;
+use settings::{Settings, MAX_WINDOW_SCALE};
+
+mod gamepad;
+use gamepad::Gamepads;
+
+mod audio;
+use audio::{track_for, Audio};
+
+mod difficulty;
+use difficulty::Difficulty;
+
+mod axis;
+use axis::ScrollAxis;
+
+mod highscore;
+
+mod frametime;
+use frametime::FrameTimer;
+
+mod replay;
+
+mod save;
+
+mod game;
+use game::{Game, GameConfig, InputState, PlayerConfig, FRAME_RATE, HEIGHT, WIDTH};
+// Only the debug stage-skip keys name stages
+#[cfg(debug_assertions)]
+use game::GameStage;
 
-// seconds per frame
-const DT: f64 = 1.0 / 60.0;
+// Now this main module is just for the run-loop; the rules live in game.rs.
 
-const WIDTH: usize = 320;
-const HEIGHT: usize = 576;
 const DEPTH: usize = 4;
-const TILEMAP_HT: usize = 256;
-
-const WALL_SZ: usize = 32;
-const ROCK_SZ: usize = 16;
-
-// player shoots every PROJ_DT frames
-const PROJ_DT: usize = 6;
-
-fn init(tileset: &Rc<Tileset>, sprite_sheet: &Rc<Texture>) -> GameState {
-    let mut tilemaps: Vec<Tilemap> = vec![];
-    for i in 0..(HEIGHT / TILEMAP_HT + 1) {
-        tilemaps.push(Tilemap::new(
-            Vec2i(0, HEIGHT as i32 - (i * TILEMAP_HT) as i32),
-            (WIDTH / TILE_SZ, TILEMAP_HT / TILE_SZ),
-            tileset,
-            vec![3169; (WIDTH / TILE_SZ) * (TILEMAP_HT / TILE_SZ)],
-        ));
-    }
 
-    // Player sprite
-    let player_sprite = assets::player_anim(sprite_sheet, 0);
+// Most simulation steps to run for one rendered frame
+const MAX_CATCH_UP_STEPS: usize = 4;
 
-    // Player entity
-    let player = Entity {
-        collider: Mobile::player(180, 500),
-        position: Vec2i(180, 500),
-        sprite: player_sprite,
-    };
+// A resize counts as over once the window has kept its size this many seconds
+const RESIZE_SETTLE_SECS: f64 = 0.5;
 
-    // Initial game state
-    GameState {
-        tilemaps,
-        terrains: vec![],
-        mobiles: vec![player],
-        walls: walls_vec(WIDTH as u16, HEIGHT as u16),
-        projs: vec![],
-        stage: GameStage::Rocks(true, 1),
-        frame_count: 0,
-        scroll: Vec2i(0, 0),
-        score: 0,
-    }
-}
+// Frames each picture of running water shows for
+const WATER_FRAME_TIME: usize = 20;
+
+// Names for the pictures on the sprite sheet and tile sheet
+const SPRITE_ATLAS_PATH: &str = "content/sprites.csv";
+const TILE_ATLAS_PATH: &str = "content/tiles.csv";
+
+// The tiles the run starts over, one row per line
+const START_GROUND_PATH: &str = "content/start_ground.csv";
+
+// Where each letter is on the font sheet and how wide it is
+const FONT_PATH: &str = "content/proportional_font.txt";
+
+// Volume, difficulty, window size and key bindings, see settings.rs for the format
+const SETTINGS_PATH: &str = "settings.cfg";
+
+// Key bindings from before there was a settings file, still read when there isn't one
+const CONTROLS_PATH: &str = "controls.cfg";
+
+// Where the best score is remembered between runs
+const HIGH_SCORE_PATH: &str = "highscore.dat";
+
+// F5 saves the run in progress here and F9 picks it back up
+const QUICKSAVE_PATH: &str = "quicksave.sav";
+
+// Screenshots are numbered from 1, skipping any already taken
+const SCREENSHOT_PREFIX: &str = "screenshot_";
 
 fn main() {
+    let mut settings = load_settings(Path::new(SETTINGS_PATH));
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
     let window = {
         let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
+        let scaled = LogicalSize::new(
+            (WIDTH as u32 * settings.window_scale) as f64,
+            (HEIGHT as u32 * settings.window_scale) as f64,
+        );
         WindowBuilder::new()
             .with_title("Space Shooter")
-            .with_inner_size(size)
+            .with_inner_size(scaled)
             .with_min_inner_size(size)
-            .with_resizable(false)
             .build(&event_loop)
             .unwrap()
     };
@@ -124,38 +150,123 @@ fn main() {
         Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture).unwrap()
     };
 
-    let sprite_sheet = Rc::new(Texture::with_file(Path::new(
-        "content/spaceshooter/Spritesheet/sheet.png",
-    )));
-    let font_sheet = Rc::new(Texture::with_file(Path::new("content/monospace_font.png")));
+    let mut assets = Assets::new();
+    let sprite_sheet = load_texture(&mut assets, "content/spaceshooter/Spritesheet/sheet.png");
+    let font_sheet = load_texture(&mut assets, "content/monospace_font.png");
+    let font = load_font(&font_sheet);
     let mut terrain_tile_ids = HashMap::new();
     terrain_tile_ids.insert(
         String::from("ground"),
         vec![3169, 2905, 1, 356, 268, 312, 61, 144],
     );
-    let tile_sheet = Rc::new(Texture::with_file(Path::new("content/tilesheet.png")));
-    let tileset = Rc::new(Tileset::new(
-        vec![Tile { solid: false }; 88 * 69],
-        &tile_sheet,
-        terrain_tile_ids,
-    ));
+    // The same pictures as rock, rock_cracked_3 and boulder in tiles.csv, for
+    // terrain built out of tiles. Boulders are 2x2 tiles.
+    terrain_tile_ids.insert(String::from("rock"), vec![727]);
+    terrain_tile_ids.insert(String::from("rubble"), vec![991]);
+    terrain_tile_ids.insert(String::from("boulder"), vec![1763, 1764, 1851, 1852]);
+    let mut tiles = vec![Tile { solid: false }; 88 * 69];
+    for id in terrain_tile_ids["rock"]
+        .iter()
+        .chain(&terrain_tile_ids["boulder"])
+    {
+        tiles[*id].solid = true;
+    }
+    let tile_sheet = load_texture(&mut assets, "content/tilesheet.png");
+    let tile_atlas = load_atlas(TILE_ATLAS_PATH, &tile_sheet, &TILE_NAMES);
+    let mut tileset = Tileset::new(tiles, &tile_sheet, terrain_tile_ids);
+    // Stamped rocks that end up side by side are redrawn as one patch of these
+    let rock_patch = tileset.tiles_in(tile_atlas.rect("rock_patch"));
+    for id in rock_patch.iter() {
+        tileset.tiles[*id].solid = true;
+    }
+    tileset
+        .tile_ids
+        .insert(String::from("rock_patch"), rock_patch);
+    // Water runs by showing its frames in turn, wherever its first one is laid
+    let water = tileset.tiles_in(tile_atlas.rect("water"));
+    let frame_times = vec![WATER_FRAME_TIME; water.len()];
+    tileset.animate(
+        water[0],
+        AnimatedTile {
+            frames: water,
+            frame_times,
+            loops: true,
+        },
+    );
+    let tileset = Rc::new(tileset);
 
-    let mut state = init(&tileset, &sprite_sheet);
+    let mut gamepads = Gamepads::new();
+    let mut audio = Audio::new(settings.volume);
+    if settings.muted {
+        audio.toggle_mute();
+    }
+
+    let args = read_args();
+    let start_ground = load_start_ground(&tileset);
+    let config = GameConfig {
+        sprite_sheet: Rc::new(load_atlas(SPRITE_ATLAS_PATH, &sprite_sheet, &SPRITE_NAMES)),
+        tile_sheet: Rc::new(tile_atlas),
+        tileset,
+        high_score_path: Some(PathBuf::from(HIGH_SCORE_PATH)),
+        // Asking for a difficulty on the command line beats the remembered one
+        difficulty: args.difficulty.unwrap_or(settings.difficulty),
+        waves: WaveTable::default(),
+        tile_terrain: args.tile_terrain,
+        update_rate: args.rate,
+        scroll_axis: args.scroll_axis,
+        player: PlayerConfig::for_axis(args.scroll_axis),
+        start_ground,
+    };
+    let mut game = match &args.replay {
+        Some(path) => Game::replay(config, path).unwrap_or_else(|e| {
+            eprintln!("Can't replay {}: {}", path.display(), e);
+            std::process::exit(1);
+        }),
+        None => Game::new(config),
+    };
+    // seconds per simulation step, at the rate a replay was recorded at if there is one
+    let dt = 1.0 / game.update_rate() as f64;
+    if let Some(path) = &args.record {
+        if let Err(e) = game.record(path) {
+            eprintln!("Can't record to {}: {}", path.display(), e);
+        }
+    }
 
     // How many unsimulated frames have we saved up?
     let mut available_time = 0.0;
     // Track end of the last frame
-    let mut since = Instant::now(); //TODO: This seems to be similar?
+    let mut since = Instant::now();
+    // Update plus render time of recent frames, shown with F2
+    let mut frame_timer = FrameTimer::new();
+    let mut show_fps = false;
+    // Reused every frame so showing the FPS doesn't allocate
+    let mut fps_text = String::new();
+    // What the title screen was last left on, to notice the player picking another
+    let mut difficulty = game.difficulty();
+    // When the window was last resized, if its new scale hasn't been saved yet
+    let mut resized_at: Option<Instant> = None;
     event_loop.run(move |event, _, control_flow| {
         // Draw the current frame
         if let Event::RedrawRequested(_) = event {
-            let mut screen = Screen::wrap(pixels.get_frame(), WIDTH, HEIGHT, DEPTH, state.scroll);
-
-            // Load and unload tilemaps if necessary
-            update_tilemaps(&mut state);
+            let mut screen = Screen::wrap(pixels.get_frame(), WIDTH, HEIGHT, DEPTH, game.scroll());
 
             // Draw current game
-            draw_game(&mut state, &mut screen, &font_sheet);
+            game.draw(&mut screen, &font);
+
+            if show_fps {
+                if let Some(fps) = frame_timer.fps() {
+                    fps_text.clear();
+                    let _ = write!(fps_text, "FPS {:.0}", fps);
+                    draw_string(
+                        &fps_text,
+                        &mut screen,
+                        &font,
+                        Vec2i(20, 64),
+                        game.scroll(),
+                        Rgba::BLACK,
+                    );
+                }
+            }
 
             // Flip buffers
             if pixels.render().is_err() {
@@ -165,451 +276,381 @@ fn main() {
 
             // Rendering has used up some time.
             // The renderer "produces" time...
-            available_time += since.elapsed().as_secs_f64();
+            let elapsed = since.elapsed().as_secs_f64();
+            available_time += elapsed;
+            frame_timer.record(elapsed);
+
+            // Music runs on its own, but follows the stage and pauses with the game
+            audio.update_music(track_for(game.stage()), game.paused(), elapsed as f32);
         }
 
-        // Game over event
-        if let GameStage::GameOver(death_frame) = state.stage {
-            if state.frame_count - death_frame >= 150 {
-                state = init(&tileset, &sprite_sheet);
+        if let Event::WindowEvent {
+            event: WindowEvent::Focused(focused),
+            ..
+        } = event
+        {
+            if focus_changed(focused, game.paused(), &mut since, &mut available_time) {
+                game.toggle_pause();
             }
         }
 
         // Handle input events
         if input.update(event) {
             // Close events
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+            if settings.controls.pressed(&input, Action::Quit) || input.quit() {
+                game.stop_recording();
+                if resized_at.is_some() {
+                    save_settings(&settings);
+                }
                 *control_flow = ControlFlow::Exit;
                 return;
             }
 
-            // Resize the window if needed
+            // Resize the window if needed; pixels letterboxes the framebuffer into it
             if let Some(size) = input.window_resized() {
                 pixels.resize(size.width, size.height);
+                // Say how many window pixels each game pixel takes up now
+                let shown = display::letterbox((WIDTH as u32, HEIGHT as u32), size.into());
+                window.set_title(&format!("Space Shooter ({}x)", shown.w as usize / WIDTH));
+                // Open the window at the scale it was left at next time, saved
+                // once the resize is over rather than for every step of a drag
+                let fb = (WIDTH as u32, HEIGHT as u32);
+                let scale = display::shown_scale(fb, size.into(), window.scale_factor());
+                let scale = scale.min(MAX_WINDOW_SCALE);
+                if scale != settings.window_scale || resized_at.is_some() {
+                    settings.window_scale = scale;
+                    resized_at = Some(Instant::now());
+                }
             }
-        }
-
-        // And the simulation "consumes" it
-        while available_time >= DT {
-            // Eat up one frame worth of time
-            available_time -= DT;
-            update_game(&mut state, &input, &sprite_sheet, &tile_sheet);
-
-            // Increment the frame counter
-            state.frame_count += 1;
-        }
-
-        // Request redraw
-        window.request_redraw();
-        // When did the last frame end?
-        since = Instant::now();
-    });
-}
-
-fn update_tilemaps(state: &mut GameState) {
-    // Unload tilemaps that are off screen, and check if new tilemap needs to be loaded
-    let mut visible = vec![];
-    let mut no_need_load = false;
-    for map in state.tilemaps.iter() {
-        visible.push(map.is_visible(state.scroll, Vec2i(WIDTH as i32, HEIGHT as i32)));
-        no_need_load = no_need_load || ((map.position.1 + TILE_SZ as i32) < state.scroll.1);
-    }
-    let mut i = 0;
-    state.tilemaps.retain(|_| (visible[i], i += 1).0);
-
-    // Load new tilemap if need
-    if !no_need_load {
-        let mut rng = rand::thread_rng();
-        let tile_idx = rng.gen_range(0..state.tilemaps[0].tileset.tile_ids["ground"].len());
-        let tile_id = state.tilemaps[0].tileset.tile_ids["ground"][tile_idx];
-
-        let new_map = Tilemap::new(
-            Vec2i(
-                state.scroll.0,
-                state.scroll.1 - TILEMAP_HT as i32 + TILE_SZ as i32,
-            ),
-            (WIDTH / TILE_SZ, TILEMAP_HT / TILE_SZ),
-            &state.tilemaps[0].tileset,
-            vec![tile_id; WIDTH * TILEMAP_HT / TILE_SZ / TILE_SZ],
-        );
-        state.tilemaps.push(new_map);
-    }
-}
-
-fn draw_game(state: &mut GameState, screen: &mut Screen, font_sheet: &Rc<Texture>) {
-    // Call screen's drawing methods to render the game state
-    screen.clear(Rgba(255, 197, 255, 255));
-
-    // Remove Terrain objects that have left screen
-    cleanup_terrain(state, screen);
-
-    for map in state.tilemaps.iter() {
-        map.draw(screen);
-    }
-
-    for proj in state.projs.iter() {
-        screen.rect(proj.rect, Rgba(0, 128, 0, 255));
-    }
-
-    for e in state.mobiles.iter_mut() {
-        screen.draw_sprite(&mut e.sprite, state.frame_count);
-    }
 
-    for e in state.terrains.iter_mut() {
-        screen.draw_sprite(&mut e.sprite, state.frame_count);
-    }
+            if settings.controls.pressed(&input, Action::Pause) {
+                game.toggle_pause();
+            }
 
-    // Draw HP bar
-    draw_string("HP", screen, font_sheet, Vec2i(20, 520), state.scroll);
-    let hp = state.mobiles[0].collider.hp;
-    screen.rect(
-        Rect {
-            x: 70,
-            y: state.scroll.1 + 520,
-            w: hp as u16 * 2,
-            h: 18,
-        },
-        Rgba(0, 128, 0, 255),
-    );
-    screen.rect(
-        Rect {
-            x: 70 + (hp as i32 * 2),
-            y: state.scroll.1 + 520,
-            w: (100 - hp as u16) * 2,
-            h: 18,
-        },
-        Rgba(128, 0, 0, 255),
-    );
-    screen.line(
-        Vec2i(70, state.scroll.1 + 520),
-        Vec2i(270, state.scroll.1 + 520),
-        Rgba(0, 0, 0, 255),
-    );
-    screen.line(
-        Vec2i(270, state.scroll.1 + 520),
-        Vec2i(270, state.scroll.1 + 538),
-        Rgba(0, 0, 0, 255),
-    );
-    screen.line(
-        Vec2i(70, state.scroll.1 + 520),
-        Vec2i(70, state.scroll.1 + 538),
-        Rgba(0, 0, 0, 255),
-    );
-    screen.line(
-        Vec2i(70, state.scroll.1 + 538),
-        Vec2i(270, state.scroll.1 + 538),
-        Rgba(0, 0, 0, 255),
-    );
-    screen.line(
-        Vec2i(70 + (hp as i32 * 2), state.scroll.1 + 520),
-        Vec2i(70 + (hp as i32 * 2), state.scroll.1 + 538),
-        Rgba(0, 0, 0, 255),
-    );
+            if settings.controls.pressed(&input, Action::Mute) {
+                audio.toggle_mute();
+                settings.muted = !settings.muted;
+                save_settings(&settings);
+            }
 
-    // Draw score
-    let mut score_msg = "Score ".to_string();
-    score_msg.push_str(&state.score.to_string());
-    draw_string(&score_msg, screen, font_sheet, Vec2i(20, 20), state.scroll);
-
-    // Draw game over message if game is over
-    if let GameStage::GameOver(_) = state.stage {
-        draw_string(
-            "Game over",
-            screen,
-            font_sheet,
-            Vec2i(80, 200),
-            state.scroll,
-        );
-        draw_string(
-            "Restarting",
-            screen,
-            font_sheet,
-            Vec2i(80, 250),
-            state.scroll,
-        );
-    }
-}
+            if input.key_pressed(VirtualKeyCode::F2) {
+                show_fps = !show_fps;
+            }
 
-fn update_game(
-    state: &mut GameState,
-    input: &WinitInputHelper,
-    sprite_sheet: &Rc<Texture>,
-    tile_sheet: &Rc<Texture>,
-) {
-    state.scroll.1 -= 1;
-
-    match state.stage {
-        GameStage::Rocks(spawning_enemies, num_waves) => {
-            // spawn rocks every 360 frames
-            if state.frame_count % 360 == 120 {
-                generate_terrain(state, tile_sheet, 0);
+            // Debug: show hitboxes
+            if input.key_pressed(VirtualKeyCode::F3) {
+                game.toggle_debug_draw();
             }
 
-            // bool in Rocks keeps track of whether we are still spawning enemies
-            // to start the stage
-            if spawning_enemies {
-                if state.frame_count % 30 == 0 {
-                    state.mobiles.push(enemy_entity(
-                        sprite_sheet,
-                        state.frame_count,
-                        Vec2i(100, state.scroll.1 - 30),
-                    ));
-                }
+            // Debug: the player can't be hurt (debug builds only)
+            #[cfg(debug_assertions)]
+            if input.held_control() && input.key_pressed(VirtualKeyCode::G) {
+                game.toggle_god_mode();
+            }
 
-                // once 4 are spawned (5 including player), stop spawning
-                if state.mobiles.len() == 5 {
-                    state.stage = GameStage::Rocks(false, num_waves);
+            // Quick-save and quick-load
+            if input.key_pressed(VirtualKeyCode::F5) {
+                if let Err(e) = game.save(Path::new(QUICKSAVE_PATH)) {
+                    eprintln!("Couldn't save to {}: {}", QUICKSAVE_PATH, e);
                 }
             }
-            // once all enemies are dead, start spawning again
-            else if state.mobiles.len() == 1 {
-                let mut rng = rand::thread_rng();
-                // starts being possible to move on to next stage after wave 2
-                // guaranteed to move on after wave 5
-                if rng.gen_range(0..4) + num_waves >= 5 {
-                    state.stage = GameStage::Boulders(1);
-                } else {
-                    state.stage = GameStage::Rocks(true, num_waves + 1);
+            if input.key_pressed(VirtualKeyCode::F9) {
+                if let Err(e) = game.load(Path::new(QUICKSAVE_PATH)) {
+                    eprintln!("Couldn't load {}: {}", QUICKSAVE_PATH, e);
                 }
             }
-        }
 
-        GameStage::Boulders(num_waves) => {
-            // Spawn a boulder wall every n frames, number goes down as waves go up
-            if state.frame_count % (300 - num_waves * 8) == 0 {
-                generate_terrain(state, tile_sheet, 1);
-                // starts being possible to move on to next stage after wave 4
-                // guaranteed to move on after wave 7
-                let mut rng = rand::thread_rng();
-                if rng.gen_range(0..4) + num_waves >= 7 {
-                    state.stage = GameStage::Rocks(true, 1);
-                } else {
-                    state.stage = GameStage::Boulders(num_waves + 1);
+            // Screenshot of the last frame drawn
+            if input.key_pressed(VirtualKeyCode::F12) {
+                let path = screenshot_path();
+                let screen = Screen::wrap(pixels.get_frame(), WIDTH, HEIGHT, DEPTH, game.scroll());
+                if let Err(e) = screen.save_png(&path) {
+                    eprintln!("Couldn't save screenshot {}: {}", path.display(), e);
                 }
             }
         }
 
-        GameStage::GameOver(_) => {}
-    }
-
-    // Update player position
-    // Player control goes here
-    match state.stage {
-        GameStage::Rocks(_, _) | GameStage::Boulders(_) => {
-            if input.key_held(VirtualKeyCode::Right) {
-                state.mobiles[0].collider.vx = 3.0;
-            } else if input.key_held(VirtualKeyCode::Left) {
-                state.mobiles[0].collider.vx = -3.0;
-            } else {
-                state.mobiles[0].collider.vx = 0.0;
+        // And the simulation "consumes" it
+        for _ in 0..take_steps(&mut available_time, dt, game.paused()) {
+            // The keyboard always works; a pad, if one is plugged in, works too
+            let mut frame_input = read_input(&input, &settings.controls);
+            if let Some(pad) = gamepads.poll() {
+                frame_input = frame_input.or(pad.to_input());
             }
-            if input.key_held(VirtualKeyCode::Up) {
-                state.mobiles[0].collider.vy = -4.0;
-            } else if input.key_held(VirtualKeyCode::Down) {
-                state.mobiles[0].collider.vy = 2.0;
-            } else {
-                state.mobiles[0].collider.vy = -1.0;
+            game.step(frame_input);
+            for sound in game.take_sounds() {
+                audio.play(sound);
             }
-
-            if input.key_held(VirtualKeyCode::O) {
-                state.stage = GameStage::Rocks(true, 1);
-            } else if input.key_held(VirtualKeyCode::P) {
-                state.stage = GameStage::Boulders(1);
+            for e in game.take_errors() {
+                eprintln!("{}", e);
             }
         }
 
-        GameStage::GameOver(_) => {}
-    }
+        // Remember the player's pick of difficulty for next time
+        if game.difficulty() != difficulty {
+            difficulty = game.difficulty();
+            settings.difficulty = difficulty;
+            save_settings(&settings);
+        }
 
-    // Update enemy AI movements
-    update_enemies(state);
+        if let Some(at) = resized_at {
+            if at.elapsed().as_secs_f64() >= RESIZE_SETTLE_SECS {
+                resized_at = None;
+                save_settings(&settings);
+            }
+        }
 
-    // Update position of mobiles
-    for m in state.mobiles.iter_mut() {
-        m.move_pos(m.collider.vx as i32, m.collider.vy as i32);
-    }
+        // Request redraw
+        window.request_redraw();
+        // When did the last frame end?
+        since = Instant::now();
+    });
+}
 
-    // Update proj position
-    for proj in state.projs.iter_mut() {
-        proj.move_pos(proj.get_velocity().0 as i32, proj.get_velocity().1 as i32);
+/**
+ * Eats up as many whole steps of dt seconds as there are in available_time,
+ * returning how many simulation steps to run. While paused nothing runs and
+ * the saved-up time is thrown away, so unpausing doesn't fast-forward.
+ *
+ * At most MAX_CATCH_UP_STEPS steps are owed at once. After a hitch the rest
+ * is dropped, since running them all would make the next frame slower still.
+ */
+fn take_steps(available_time: &mut f64, dt: f64, paused: bool) -> usize {
+    if paused {
+        *available_time = 0.0;
+        return 0;
     }
-
-    // Update wall position (scroll with camera)
-    for wall in state.walls.iter_mut() {
-        wall.move_pos(0, -1);
+    *available_time = available_time.min(MAX_CATCH_UP_STEPS as f64 * dt);
+    let mut steps = 0;
+    while *available_time >= dt {
+        *available_time -= dt;
+        steps += 1;
     }
+    steps
+}
 
-    // Detect collisions: Generate contacts
-    let mut contacts: Vec<Contact> = vec![];
-    collision::gather_contacts(
-        &state.terrains,
-        &state.mobiles,
-        &state.walls,
-        &state.projs,
-        &mut contacts,
-    );
-
-    // Handle collisions
-    let (player_is_alive, scores_gained) = collision::handle_contact(
-        &mut state.terrains,
-        &mut state.mobiles,
-        &mut state.projs,
-        &mut contacts,
-    );
-
-    if let GameStage::Rocks(_, _) | GameStage::Boulders(_) = state.stage {
-        // Set GameOver stage if player is not alive
-        if !player_is_alive {
-            state.mobiles[0]
-                .sprite
-                .animation_sm
-                .input("die", state.frame_count);
-            state.mobiles[0].collider.vx = 0.0;
-            state.mobiles[0].collider.vy = -1.0;
-            state.stage = GameStage::GameOver(state.frame_count);
-        } else {
-            state.score += scores_gained;
-        }
+struct Args {
+    // None to go with the settings
+    difficulty: Option<Difficulty>,
+    // Where to record this run's input, if anywhere
+    record: Option<PathBuf>,
+    // A recording to play back instead of taking input
+    replay: Option<PathBuf>,
+    tile_terrain: bool,
+    // Simulation steps a second
+    rate: u32,
+    scroll_axis: ScrollAxis,
+}
 
-        // Fire projectile
-        if state.frame_count % PROJ_DT == 0 {
-            state
-                .projs
-                .push(Projectile::new(&state.mobiles[0].collider));
+/**
+ * The command line: easy, normal or hard picks the difficulty, --record FILE
+ * saves the run's input to FILE and --replay FILE plays a saved run back.
+ * --tile-terrain builds rocks and boulders out of tiles instead of entities.
+ * --rate N runs the simulation N times a second instead of FRAME_RATE; the
+ * game plays at the same speed either way, just more or less smoothly.
+ * --horizontal scrolls sideways instead of up the screen.
+ */
+fn read_args() -> Args {
+    let mut args = Args {
+        difficulty: None,
+        record: None,
+        replay: None,
+        tile_terrain: false,
+        rate: FRAME_RATE,
+        scroll_axis: ScrollAxis::Vertical,
+    };
+    let mut words = std::env::args().skip(1);
+    while let Some(arg) = words.next() {
+        match arg.as_str() {
+            "--record" => args.record = words.next().map(PathBuf::from),
+            "--replay" => args.replay = words.next().map(PathBuf::from),
+            "--tile-terrain" => args.tile_terrain = true,
+            "--horizontal" => args.scroll_axis = ScrollAxis::Horizontal,
+            "--rate" => match words.next().and_then(|n| n.parse().ok()) {
+                Some(rate) if rate > 0 => args.rate = rate,
+                _ => eprintln!("--rate needs a whole number of steps a second"),
+            },
+            _ => match Difficulty::from_name(&arg) {
+                Some(difficulty) => args.difficulty = Some(difficulty),
+                None => eprintln!(
+                    "Unknown difficulty {:?}, playing on the one in {}",
+                    arg, SETTINGS_PATH
+                ),
+            },
         }
     }
+    args
+}
+
+// The first screenshot_N.png that doesn't exist yet
+fn screenshot_path() -> PathBuf {
+    (1..)
+        .map(|n| PathBuf::from(format!("{}{}.png", SCREENSHOT_PREFIX, n)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+// Like the atlases, the game's textures have to be there for it to run at all
+fn load_texture(assets: &mut Assets, path: &str) -> Rc<Texture> {
+    assets.get_texture(Path::new(path)).unwrap_or_else(|e| {
+        eprintln!("Can't load {}: {}", path, e);
+        std::process::exit(1);
+    })
 }
 
 /**
- * Randomly picks hexadecimal string of length 4 and uses it to generate terrain objects.
- *
- * terrain_type: 0 = random rocks, 1 = wall with some rocks
+ * Leaving the window pauses the game. Coming back doesn't unpause it, but
+ * starts the clock over so no time builds up while away. Returns whether the
+ * game needs pausing.
  */
-fn generate_terrain(state: &mut GameState, tile_sheet: &Rc<Texture>, terrain_type: usize) {
-    let mut rng = rand::thread_rng();
-
-    if terrain_type == 0 {
-        for i in 0..(WIDTH / ROCK_SZ) {
-            for j in 0..6 {
-                if rng.gen_range(0..6) == 0 {
-                    let pos = Vec2i(
-                        (i * ROCK_SZ) as i32,
-                        state.scroll.1 - (ROCK_SZ * (j + 1)) as i32,
-                    );
-                    state
-                        .terrains
-                        .push(rock_entity(tile_sheet, state.frame_count, pos));
-                }
-            }
-        }
-    } else if terrain_type == 1 {
-        let seed = rng.gen_range(0..256);
-        for i in 0..(WIDTH / WALL_SZ) {
-            // ~1/3 chance of adding rocks instead of walls for 3 slots
-            if ((seed + i) / 3) % 3 == 0 {
-                // let pos1 = Vec2i((i * WALL_SZ) as i32, state.scroll.1 - WALL_SZ as i32);
-                // let pos2 = Vec2i(
-                //     (i * WALL_SZ + ROCK_SZ) as i32,
-                //     state.scroll.1 - WALL_SZ as i32,
-                // );
-                let pos3 = Vec2i(
-                    (i * WALL_SZ) as i32,
-                    state.scroll.1 - WALL_SZ as i32 + ROCK_SZ as i32,
-                );
-                let pos4 = Vec2i(
-                    (i * WALL_SZ + ROCK_SZ) as i32,
-                    state.scroll.1 - WALL_SZ as i32 + ROCK_SZ as i32,
-                );
-
-                // state
-                //     .terrains
-                //     .push(rock_entity(tile_sheet, state.frame_count, pos1));
-                // state
-                //     .terrains
-                //     .push(rock_entity(tile_sheet, state.frame_count, pos2));
-                state
-                    .terrains
-                    .push(rock_entity(tile_sheet, state.frame_count, pos3));
-                state
-                    .terrains
-                    .push(rock_entity(tile_sheet, state.frame_count, pos4));
-            } else {
-                let pos = Vec2i((i * WALL_SZ) as i32, state.scroll.1 - WALL_SZ as i32);
-                state
-                    .terrains
-                    .push(boulder_entity(tile_sheet, state.frame_count, pos));
-            }
-        }
+fn focus_changed(
+    focused: bool,
+    paused: bool,
+    since: &mut Instant,
+    available_time: &mut f64,
+) -> bool {
+    if focused {
+        *since = Instant::now();
+        *available_time = 0.0;
     }
+    !focused && !paused
 }
 
-fn cleanup_terrain(state: &mut GameState, screen: &Screen) {
-    let frame_count = state.frame_count;
-    state.terrains.retain(|t| {
-        screen.is_visible(t.collider.rect) || frame_count - t.collider.created_at < 300
-    });
+// The game can't draw anything without its atlases, so a broken one, or one
+// missing any of names, ends it
+fn load_atlas(path: &str, texture: &Rc<Texture>, names: &[&str]) -> Atlas {
+    let atlas = Atlas::load(Path::new(path), texture);
+    atlas
+        .and_then(|a| a.check_names(names).map(|_| a))
+        .unwrap_or_else(|e| {
+            eprintln!("Can't load {}: {}", path, e);
+            std::process::exit(1);
+        })
 }
 
-fn update_enemies(state: &mut GameState) {
-    let player_pos = state.mobiles[0].position.clone();
+fn load_start_ground(tileset: &Rc<Tileset>) -> Tilemap {
+    let path = Path::new(START_GROUND_PATH);
+    Tilemap::from_csv(path, Vec2i(0, 0), tileset).unwrap_or_else(|e| {
+        eprintln!("Can't load {}: {}", START_GROUND_PATH, e);
+        std::process::exit(1);
+    })
+}
 
-    for enemy in state.mobiles.iter_mut().skip(1) {
-        // Accelerate away from nearby terrain
-        for terrain in state.terrains.iter() {
-            let dx = (terrain.position.0 - enemy.position.0) as f32;
-            let dy = (terrain.position.1 - enemy.position.1) as f32;
+fn load_font(sheet: &Rc<Texture>) -> Font {
+    Font::proportional(Path::new(FONT_PATH), sheet).unwrap_or_else(|e| {
+        eprintln!("Can't load {}: {}", FONT_PATH, e);
+        std::process::exit(1);
+    })
+}
 
-            if dx.abs() < 50.0 && dy.abs() < 50.0 {
-                if dx.abs() > dy.abs() {
-                    enemy.collider.vx -= 5.0 / dx;
-                } else {
-                    enemy.collider.vy -= 5.0 / dy;
-                }
-            }
-        }
+/*
+Falls back to the defaults if the file is missing or broken. With no settings
+file at all, key bindings still come from an old controls file if there's one.
+*/
+fn load_settings(path: &Path) -> Settings {
+    if !path.exists() {
+        return Settings {
+            controls: load_controls(Path::new(CONTROLS_PATH)),
+            ..Settings::default()
+        };
+    }
+    Settings::load(path).unwrap_or_else(|e| {
+        eprintln!("Ignoring {}: {}", path.display(), e);
+        Settings::default()
+    })
+}
 
-        // Accelerate x towards player
-        let mut dx = ((player_pos.0 - enemy.position.0) as f32) / 50.0;
-        let max_vx = 0.07;
-        if dx < -max_vx {
-            dx = -max_vx;
-        } else if dx > max_vx {
-            dx = max_vx;
-        }
-        enemy.collider.vx += dx;
-
-        // Accelerate y upward if enemy is below player
-        let dy = player_pos.1 - enemy.position.1;
-        let max_vy = 5.0;
-        if dy < 0 {
-            // enemy.collider.vy -= 0.03;
-            enemy.collider.vy = (enemy.collider.vy - 0.03).max(-max_vy);
-        }
+fn save_settings(settings: &Settings) {
+    if let Err(e) = settings.save(Path::new(SETTINGS_PATH)) {
+        eprintln!("Couldn't save {}: {}", SETTINGS_PATH, e);
+    }
+}
 
-        // Accelerate y downward if enemy is above player
-        if dy > 0 {
-            // enemy.collider.vy += 0.03;
-            enemy.collider.vy = (enemy.collider.vy + 0.03).min(max_vy);
-        }
+// Falls back to the default controls if the file is missing or broken
+fn load_controls(path: &Path) -> Controls {
+    if !path.exists() {
+        return Controls::default();
+    }
+    Controls::load(path).unwrap_or_else(|e| {
+        eprintln!("Ignoring {}: {}", path.display(), e);
+        Controls::default()
+    })
+}
 
-        // Accelerate y downward if enemy is less than 50 away from top of screen
-        // let dy = enemy.position.1 - state.scroll.1;
-        // if dy < 75 {
-        //     enemy.collider.vy += 0.03;
-        // }
+// Translate the keyboard into the game's view of the controls
+fn read_input(input: &WinitInputHelper, controls: &Controls) -> InputState {
+    // Debug keys for jumping between stages, left out of release builds
+    #[cfg(debug_assertions)]
+    let skip_to = if input.key_held(VirtualKeyCode::O) {
+        Some(GameStage::Rocks(true, 1))
+    } else if input.key_held(VirtualKeyCode::K) {
+        Some(GameStage::Boulders(true, 1))
+    } else {
+        None
+    };
+    #[cfg(not(debug_assertions))]
+    let skip_to = None;
+
+    InputState {
+        left: controls.held(input, Action::MoveLeft),
+        right: controls.held(input, Action::MoveRight),
+        up: controls.held(input, Action::MoveUp),
+        down: controls.held(input, Action::MoveDown),
+        fire: controls.held(input, Action::Fire),
+        start: controls.held(input, Action::Start),
+        bomb: controls.held(input, Action::Bomb),
+        aim: controls.held(input, Action::Aim),
+        dash: controls.held(input, Action::Dash),
+        skip_to,
+    }
+}
 
-        // Decelerate naturally (due to friction or something)
-        // Note that base speed = (0.0, -1.0) due to camera scrolling upward
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paused_game_takes_no_steps_and_saves_up_no_time() {
+        let dt = 1.0 / 60.0;
+        let mut available_time = 2.5 * dt;
+        assert_eq!(take_steps(&mut available_time, dt, true), 0);
+        assert_eq!(available_time, 0.0);
+
+        // Unpaused, the time built up since is spent as usual
+        available_time = 2.5 * dt;
+        assert_eq!(take_steps(&mut available_time, dt, false), 2);
+        assert!((available_time - 0.5 * dt).abs() < 1e-9);
+    }
 
-        if enemy.collider.vx > 0.0 {
-            enemy.collider.vx = (enemy.collider.vx - 0.01).max(0.0);
-        } else if enemy.collider.vx < 0.0 {
-            enemy.collider.vx = (enemy.collider.vx + 0.01).min(0.0);
-        }
-        if enemy.collider.vy > -1.0 {
-            enemy.collider.vy = (enemy.collider.vy - 0.01).max(-1.0);
-        } else if enemy.collider.vy < -1.0 {
-            enemy.collider.vy = (enemy.collider.vy + 0.01).min(-1.0);
-        }
+    #[test]
+    fn leaving_the_window_pauses_and_coming_back_starts_the_clock_over() {
+        let start = Instant::now();
+        let mut since = start;
+        let mut available_time = 0.25;
+        // Losing focus pauses a running game, but not a paused one again
+        assert!(focus_changed(false, false, &mut since, &mut available_time));
+        assert!(!focus_changed(false, true, &mut since, &mut available_time));
+        assert_eq!((since, available_time), (start, 0.25));
+
+        // Coming back leaves it paused, with no time owed for the time away
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        assert!(!focus_changed(true, true, &mut since, &mut available_time));
+        assert!(since > start);
+        assert_eq!(available_time, 0.0);
+    }
+
+    #[test]
+    fn a_long_hitch_is_dropped_rather_than_caught_up_on() {
+        let dt = 1.0 / 60.0;
+        let mut available_time = 3.0;
+        assert_eq!(
+            take_steps(&mut available_time, dt, false),
+            MAX_CATCH_UP_STEPS
+        );
+        assert!(available_time < dt);
+
+        // A frame that's merely a little slow still catches up in full
+        available_time = 2.0 * dt;
+        assert_eq!(take_steps(&mut available_time, dt, false), 2);
     }
 }
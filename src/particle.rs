@@ -0,0 +1,86 @@
+use crate::screen::Screen;
+use crate::types::{Rect, Rgba, Vec2i};
+
+/*
+Explosion debris. Whenever something is destroyed a ring of particles bursts
+out of it, falls a little, slows down, and fades away over its ttl.
+*/
+pub struct Particle {
+    pub pos: (f32, f32),
+    pub vel: (f32, f32),
+    pub created_at: usize,
+    pub ttl: usize,
+    pub color: Rgba,
+}
+
+// Particles per explosion
+pub const BURST_SIZE: usize = 12;
+// Past this many live particles the oldest are dropped to make room
+pub const MAX_PARTICLES: usize = 400;
+const PARTICLE_TTL: usize = 30;
+const BURST_SPEED: f32 = 2.5;
+const GRAVITY: f32 = 0.1;
+// Fraction of velocity kept each frame
+const DRAG: f32 = 0.92;
+
+impl Particle {
+    pub fn expired(&self, now: usize) -> bool {
+        now - self.created_at >= self.ttl
+    }
+}
+
+/**
+ * Sends BURST_SIZE particles flying out evenly in all directions from center.
+ * No randomness, so this is safe to call from anywhere without disturbing
+ * the game's rng.
+ */
+pub fn spawn_burst(particles: &mut Vec<Particle>, center: Vec2i, color: Rgba, now: usize) {
+    for i in 0..BURST_SIZE {
+        let angle = i as f32 / BURST_SIZE as f32 * std::f32::consts::TAU;
+        // Alternate speeds a bit so the ring doesn't look too perfect
+        let speed = if i % 2 == 0 {
+            BURST_SPEED
+        } else {
+            BURST_SPEED * 0.6
+        };
+        particles.push(Particle {
+            pos: (center.0 as f32, center.1 as f32),
+            vel: (angle.cos() * speed, angle.sin() * speed),
+            created_at: now,
+            ttl: PARTICLE_TTL,
+            color,
+        });
+    }
+    if particles.len() > MAX_PARTICLES {
+        let extra = particles.len() - MAX_PARTICLES;
+        particles.drain(..extra);
+    }
+}
+
+// Moves particles on by this many frames' worth of flight
+pub fn update_particles(particles: &mut Vec<Particle>, now: usize, frames: f32) {
+    let drag = DRAG.powf(frames);
+    for p in particles.iter_mut() {
+        p.vel.0 *= drag;
+        p.vel.1 = p.vel.1 * drag + GRAVITY * frames;
+        p.pos.0 += p.vel.0 * frames;
+        p.pos.1 += p.vel.1 * frames;
+    }
+    particles.retain(|p| !p.expired(now));
+}
+
+pub fn draw_particles(particles: &[Particle], screen: &mut Screen, now: usize) {
+    for p in particles {
+        let left = p.ttl.saturating_sub(now - p.created_at);
+        let alpha = (255 * left / p.ttl.max(1)) as u8;
+        screen.rect(
+            Rect {
+                x: p.pos.0 as i32 - 1,
+                y: p.pos.1 as i32 - 1,
+                w: 3,
+                h: 3,
+            },
+            p.color.with_alpha(alpha),
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use rand::Rng;
+use std::f64::consts::PI;
+
+use crate::axis::ScrollAxis;
+use crate::collision::Projectile;
+use crate::types::{Vec2f, Vec2i};
+
+/*
+A volley of enemy shots, worked out from where it's fired. Angles are measured
+from straight back (down the screen, or left scrolling sideways), positive
+turning clockwise, and turned to the axis at the end like everything else
+laid out vertically. The shots are ordinary enemy-team projectiles, so they
+hit the player and the walls like any other.
+*/
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum BulletPattern {
+    // This many shots spread evenly across FAN_ARC, centered on straight back
+    Fan(u8),
+    // This many shots evenly around a full circle, which turns SPIRAL_TURN a frame
+    Spiral(u8),
+    // One shot straight at the target
+    Aimed,
+    // This many shots at random angles within FAN_ARC of straight back
+    Scatter(u8),
+}
+
+// How fast pattern shots fly, in pixels a frame
+const BULLET_SPEED: f64 = 5.0;
+
+// Radians from one side of a fan to the other
+const FAN_ARC: f64 = PI / 4.0;
+
+// Radians a spiral turns each frame, so volleys a few frames apart don't line up
+const SPIRAL_TURN: f64 = 0.07;
+
+impl BulletPattern {
+    /**
+     * The shots this pattern fires from origin at frame now, with target the
+     * point an aimed shot heads for. Only Scatter draws on rng.
+     */
+    pub fn emit(
+        &self,
+        origin: Vec2i,
+        target: Vec2i,
+        axis: ScrollAxis,
+        now: usize,
+        rng: &mut impl Rng,
+    ) -> Vec<Projectile> {
+        let angles: Vec<f64> = match *self {
+            BulletPattern::Fan(n) => {
+                let mid = (n as f64 - 1.0) / 2.0;
+                let step = if n > 1 {
+                    FAN_ARC / (n as f64 - 1.0)
+                } else {
+                    0.0
+                };
+                (0..n).map(|i| (i as f64 - mid) * step).collect()
+            }
+            BulletPattern::Spiral(n) => {
+                let start = now as f64 * SPIRAL_TURN;
+                (0..n)
+                    .map(|i| start + i as f64 * 2.0 * PI / n as f64)
+                    .collect()
+            }
+            BulletPattern::Aimed => {
+                let dx = (target.0 - origin.0) as f64;
+                let dy = (target.1 - origin.1) as f64;
+                let len = (dx * dx + dy * dy).sqrt().max(1.0);
+                let (vx, vy) = (dx / len * BULLET_SPEED, dy / len * BULLET_SPEED);
+                return vec![Projectile::at(origin.0, origin.1, vx, vy, now)];
+            }
+            BulletPattern::Scatter(n) => (0..n)
+                .map(|_| rng.gen_range(-FAN_ARC / 2.0..=FAN_ARC / 2.0))
+                .collect(),
+        };
+        angles
+            .into_iter()
+            .map(|a| {
+                let v = axis.turn_f(Vec2f(-a.sin() as f32, a.cos() as f32));
+                let (vx, vy) = (v.0 as f64 * BULLET_SPEED, v.1 as f64 * BULLET_SPEED);
+                Projectile::at(origin.0, origin.1, vx, vy, now)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    // Angle of each shot's velocity, from straight down, clockwise
+    fn angles(shots: &[Projectile]) -> Vec<f64> {
+        shots
+            .iter()
+            .map(|p| {
+                let (vx, vy) = p.get_velocity();
+                (-vx).atan2(vy)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_fan_of_five_spreads_evenly_across_its_arc() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let origin = Vec2i(100, 50);
+        let shots = BulletPattern::Fan(5).emit(origin, origin, ScrollAxis::Vertical, 0, &mut rng);
+        assert_eq!(shots.len(), 5);
+        for shot in shots.iter() {
+            let (vx, vy) = shot.get_velocity();
+            assert!((vx.hypot(vy) - BULLET_SPEED).abs() < 1e-4);
+            assert_eq!(shot.rect.center(), origin);
+        }
+        let angles = angles(&shots);
+        for (i, angle) in angles.iter().enumerate() {
+            let expected = (i as f64 - 2.0) * FAN_ARC / 4.0;
+            assert!((angle - expected).abs() < 1e-4, "{:?}", angles);
+        }
+    }
+
+    #[test]
+    fn an_aimed_shot_heads_for_the_target() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let origin = Vec2i(100, 50);
+        let target = Vec2i(130, 90);
+        let shots = BulletPattern::Aimed.emit(origin, target, ScrollAxis::Vertical, 0, &mut rng);
+        assert_eq!(shots.len(), 1);
+        let (vx, vy) = shots[0].get_velocity();
+        assert!((vx - 3.0).abs() < 1e-9 && (vy - 4.0).abs() < 1e-9);
+    }
+}
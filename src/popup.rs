@@ -0,0 +1,47 @@
+use crate::types::Vec2i;
+
+/*
+Little bits of floating text ("-4", "+1") that show up where something was hit
+or destroyed, drift up the screen, fade out, and disappear after ttl frames.
+*/
+pub struct Popup {
+    pub text: String,
+    pub pos: Vec2i,
+    pub created_at: usize,
+    pub ttl: usize,
+}
+
+// How many frames a popup stays up for
+pub const POPUP_TTL: usize = 40;
+
+impl Popup {
+    pub fn new(text: String, pos: Vec2i, now: usize) -> Self {
+        Self {
+            text,
+            pos,
+            created_at: now,
+            ttl: POPUP_TTL,
+        }
+    }
+
+    pub fn expired(&self, now: usize) -> bool {
+        now - self.created_at >= self.ttl
+    }
+
+    // Fully opaque when new, fading linearly to nothing at the end of its ttl
+    pub fn alpha(&self, now: usize) -> u8 {
+        let left = self.ttl.saturating_sub(now - self.created_at);
+        (255 * left / self.ttl.max(1)) as u8
+    }
+}
+
+/**
+ * Moves popups rise pixels up the screen (on top of following the camera by
+ * scroll_step) and drops the ones whose time is up.
+ */
+pub fn update_popups(popups: &mut Vec<Popup>, now: usize, scroll_step: Vec2i, rise: i32) {
+    for popup in popups.iter_mut() {
+        popup.pos = popup.pos + scroll_step - Vec2i(0, rise);
+    }
+    popups.retain(|p| !p.expired(now));
+}
@@ -0,0 +1,289 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::axis::ScrollAxis;
+use crate::difficulty::Difficulty;
+use crate::game::{GameConfig, GameStage, InputState, PlayerConfig};
+use crate::save::{Line, Save, Words};
+
+/*
+Recorded runs. A run is decided by its rng seed, the difficulty it started
+on, the settings it was played with and the input of every frame, so that's
+all a recording keeps:
+
+    spaceshooter-replay 1
+    seed 1234
+    difficulty Normal
+    rate 60
+    axis Vertical
+    tile_terrain false
+    .........
+    .r..f....
+    l...f..a.
+    ..u.....e
+    ....f.... > Boss
+
+Each frame is a line of one letter per held button (l r u d f s b a e, in
+InputState's field order) with . for not held, then the stage skip_to asked
+for, if any.
+*/
+
+const HEADER: &str = "spaceshooter-replay";
+// Bump this whenever what's written changes
+const REPLAY_VERSION: u32 = 1;
+
+// e for evade; d is already down
+const BUTTONS: [char; 9] = ['l', 'r', 'u', 'd', 'f', 's', 'b', 'a', 'e'];
+
+/// Ways loading a recording can fail
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    /// A recording from another version of the game
+    Version(u32),
+    /// The header, seed, difficulty or settings lines are missing or unreadable
+    BadHeader,
+    /// A frame that couldn't be read, at this (1-based) line number
+    BadLine(usize, String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "couldn't read replay: {}", e),
+            ReplayError::Version(v) => write!(
+                f,
+                "replay is from version {}, this game reads version {}",
+                v, REPLAY_VERSION
+            ),
+            ReplayError::BadHeader => {
+                write!(
+                    f,
+                    "expected header, seed, difficulty and settings lines first"
+                )
+            }
+            ReplayError::BadLine(line, text) => {
+                write!(
+                    f,
+                    "line {}: expected a frame of input, got {:?}",
+                    line, text
+                )
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(e: std::io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    pub update_rate: u32,
+    pub scroll_axis: ScrollAxis,
+    pub tile_terrain: bool,
+    pub inputs: Vec<InputState>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    // The config with the recording's settings in place of its own
+    pub fn apply(&self, config: GameConfig) -> GameConfig {
+        // Turning the game on its side moves where the player starts
+        let player = if self.scroll_axis == config.scroll_axis {
+            config.player
+        } else {
+            PlayerConfig::for_axis(self.scroll_axis)
+        };
+        GameConfig {
+            difficulty: self.difficulty,
+            update_rate: self.update_rate,
+            scroll_axis: self.scroll_axis,
+            tile_terrain: self.tile_terrain,
+            player,
+            ..config
+        }
+    }
+}
+
+impl FromStr for Replay {
+    type Err = ReplayError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = text.lines();
+        let version = setting(lines.next(), HEADER)?;
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::Version(version));
+        }
+        let seed = setting(lines.next(), "seed")?;
+        let difficulty = setting(lines.next(), "difficulty")?;
+        let update_rate = setting(lines.next(), "rate")?;
+        let scroll_axis = setting(lines.next(), "axis")?;
+        let tile_terrain = setting(lines.next(), "tile_terrain")?;
+        let inputs = lines
+            .enumerate()
+            .map(|(i, line)| {
+                // After the six header lines, and line numbers start from 1
+                parse_input(line).ok_or_else(|| ReplayError::BadLine(i + 7, line.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            seed,
+            difficulty,
+            update_rate,
+            scroll_axis,
+            tile_terrain,
+            inputs,
+        })
+    }
+}
+
+// The value on a header line of the form "name value"
+fn setting<T: Save>(line: Option<&str>, name: &str) -> Result<T, ReplayError> {
+    let mut words = Words::new(line.ok_or(ReplayError::BadHeader)?);
+    if words.word() != Some(name) {
+        return Err(ReplayError::BadHeader);
+    }
+    words
+        .read()
+        .filter(|_| words.done())
+        .ok_or(ReplayError::BadHeader)
+}
+
+// Writes a recording as it's made, a frame at a time
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, seed: u64, config: &GameConfig) -> std::io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{} {}", HEADER, REPLAY_VERSION)?;
+        writeln!(out, "seed {}", seed)?;
+        writeln!(out, "difficulty {}", config.difficulty.name())?;
+        writeln!(out, "rate {}", config.update_rate)?;
+        writeln!(out, "axis {:?}", config.scroll_axis)?;
+        writeln!(out, "tile_terrain {}", config.tile_terrain)?;
+        Ok(Self { out })
+    }
+
+    pub fn write(&mut self, input: &InputState) -> std::io::Result<()> {
+        writeln!(self.out, "{}", format_input(input))
+    }
+}
+
+fn buttons(input: &InputState) -> [bool; 9] {
+    [
+        input.left,
+        input.right,
+        input.up,
+        input.down,
+        input.fire,
+        input.start,
+        input.bomb,
+        input.aim,
+        input.dash,
+    ]
+}
+
+pub(crate) fn format_input(input: &InputState) -> String {
+    let mut line: String = BUTTONS
+        .iter()
+        .zip(buttons(input).iter())
+        .map(|(&c, &held)| if held { c } else { '.' })
+        .collect();
+    if let Some(stage) = input.skip_to {
+        line.push(' ');
+        line.push_str(&Line::new(">").put(&stage).finish());
+    }
+    line
+}
+
+pub(crate) fn parse_input(line: &str) -> Option<InputState> {
+    let (flags, skip_to) = match line.find(" > ") {
+        Some(at) => {
+            let mut words = Words::new(&line[at + 3..]);
+            let stage = words.read::<GameStage>()?;
+            if !words.done() {
+                return None;
+            }
+            (&line[..at], Some(stage))
+        }
+        None => (line.trim_end(), None),
+    };
+    if flags.chars().count() != BUTTONS.len() {
+        return None;
+    }
+    let mut held = [false; 9];
+    for ((c, &button), held) in flags.chars().zip(BUTTONS.iter()).zip(held.iter_mut()) {
+        match c {
+            '.' => {}
+            _ if c == button => *held = true,
+            _ => return None,
+        }
+    }
+    let [left, right, up, down, fire, start, bomb, aim, dash] = held;
+    Some(InputState {
+        left,
+        right,
+        up,
+        down,
+        fire,
+        start,
+        bomb,
+        aim,
+        dash,
+        skip_to,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recordings_need_the_whole_header() {
+        let text = |version, frame| {
+            format!(
+                "{} {}\nseed 3\ndifficulty Hard\nrate 60\naxis Vertical\ntile_terrain false\n{}\n",
+                HEADER, version, frame
+            )
+        };
+        let replay = Replay::from_str(&text(1, "l..d..bae")).unwrap();
+        assert_eq!(replay.seed, 3);
+        assert_eq!(replay.update_rate, 60);
+        let input = replay.inputs[0];
+        assert!(input.left && input.down && input.bomb && input.aim && input.dash);
+        assert!(!input.fire);
+
+        // Frames are always one letter per button
+        assert!(matches!(
+            Replay::from_str(&text(1, "l..d..b")),
+            Err(ReplayError::BadLine(7, _))
+        ));
+        assert!(matches!(
+            Replay::from_str(&text(2, "l..d..bae")),
+            Err(ReplayError::Version(2))
+        ));
+        // No header line, or a setting missing, isn't a recording
+        assert!(matches!(
+            Replay::from_str("seed 3\ndifficulty Hard\nl..d..bae\n"),
+            Err(ReplayError::BadHeader)
+        ));
+        let no_axis = text(1, "l..d..bae").replace("axis Vertical\n", "");
+        assert!(matches!(
+            Replay::from_str(&no_axis),
+            Err(ReplayError::BadHeader)
+        ));
+    }
+}
@@ -0,0 +1,678 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::axis::ScrollAxis;
+use crate::boss::Boss;
+use crate::collision::{
+    Mobile, PowerUp, PowerUpKind, Projectile, Team, Terrain, Trigger, TriggerKind,
+};
+use crate::difficulty::Difficulty;
+use crate::enemy::EnemyKind;
+use crate::game::{GameStage, InputState, Transition};
+use crate::particle::Particle;
+use crate::popup::Popup;
+use crate::replay;
+use crate::trail::Trail;
+use crate::types::{Rect, Rgba, Vec2f, Vec2i};
+use crate::weapon::Weapon;
+
+/*
+Quick-saves. A save is plain text, a header line and then one line per thing
+saved: a keyword, then its fields separated by spaces.
+
+    spaceshooter-save 2
+    settings Vertical false 1
+    frame 1520
+    stage Rocks false 3
+    mobile 180 500 0 0 184 503 28 19 0 0 100 true false ...
+
+Only what the game plays by is saved. Sprites are rebuilt from assets.rs when
+loading, so a save made with one sprite sheet loads fine with another.
+
+Game decides which lines there are; this module is how the values on them are
+written and read back.
+*/
+
+const HEADER: &str = "spaceshooter-save";
+// Bump this whenever what's written changes; older saves won't load
+pub const SAVE_VERSION: u32 = 15;
+
+/// Ways loading a save can fail
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    /// The first line isn't a save header
+    NotASave,
+    /// A save written by a different version of the game
+    Version(u32),
+    /// A line that couldn't be read, at this (1-based) line number
+    BadLine(usize, String),
+    /// Nothing in the save is the player
+    NoPlayer,
+    /// The save was made with this setting different from the game's
+    Settings(&'static str),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "couldn't read save: {}", e),
+            SaveError::NotASave => write!(f, "not a save file"),
+            SaveError::Version(v) => write!(
+                f,
+                "save is from version {}, this game reads version {}",
+                v, SAVE_VERSION
+            ),
+            SaveError::BadLine(line, text) => write!(f, "line {}: can't read {:?}", line, text),
+            SaveError::NoPlayer => write!(f, "save has no player in it"),
+            SaveError::Settings(setting) => {
+                write!(f, "save was made with a different {}", setting)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+pub fn header() -> String {
+    format!("{} {}", HEADER, SAVE_VERSION)
+}
+
+// Checks the first line of a save
+pub fn check_header(line: Option<&str>) -> Result<(), SaveError> {
+    let mut words = Words::new(line.ok_or(SaveError::NotASave)?);
+    if words.word() != Some(HEADER) {
+        return Err(SaveError::NotASave);
+    }
+    match words.read::<u32>() {
+        Some(SAVE_VERSION) => Ok(()),
+        Some(version) => Err(SaveError::Version(version)),
+        None => Err(SaveError::NotASave),
+    }
+}
+
+// One line of a save being written
+pub struct Line(String);
+
+impl Line {
+    pub fn new(key: &str) -> Self {
+        Line(key.to_string())
+    }
+
+    pub fn put<T: Save>(mut self, value: &T) -> Self {
+        value.save(&mut self);
+        self
+    }
+
+    fn word(&mut self, word: impl fmt::Display) {
+        self.0.push(' ');
+        self.0.push_str(&word.to_string());
+    }
+
+    pub fn finish(self) -> String {
+        self.0
+    }
+}
+
+// One line of a save being read, a word at a time
+pub struct Words<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Words<'a> {
+    pub fn new(line: &'a str) -> Self {
+        Self { rest: line.trim() }
+    }
+
+    pub fn word(&mut self) -> Option<&'a str> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let end = self.rest.find(' ').unwrap_or(self.rest.len());
+        let word = &self.rest[..end];
+        self.rest = self.rest[end..].trim_start();
+        Some(word)
+    }
+
+    pub fn read<T: Save>(&mut self) -> Option<T> {
+        T::load(self)
+    }
+
+    // Everything left on the line
+    fn rest(&mut self) -> &'a str {
+        std::mem::take(&mut self.rest)
+    }
+
+    pub fn done(&self) -> bool {
+        self.rest.is_empty()
+    }
+}
+
+/**
+ * A value that can be written as words on a line and read back. Values that
+ * take the rest of the line (popup text, input) have to come last.
+ */
+pub trait Save: Sized {
+    fn save(&self, line: &mut Line);
+    fn load(words: &mut Words) -> Option<Self>;
+}
+
+// Numbers and bools are written as themselves
+fn parse<T: FromStr>(words: &mut Words) -> Option<T> {
+    words.word()?.parse().ok()
+}
+
+macro_rules! save_as_text {
+    ($($t:ty),*) => {
+        $(impl Save for $t {
+            fn save(&self, line: &mut Line) {
+                line.word(self);
+            }
+
+            fn load(words: &mut Words) -> Option<Self> {
+                parse(words)
+            }
+        })*
+    };
+}
+
+save_as_text!(bool, u8, u16, u32, u64, u128, usize, i32, f32, f64);
+
+impl<T: Save> Save for Option<T> {
+    fn save(&self, line: &mut Line) {
+        match self {
+            Some(value) => {
+                line.word("some");
+                value.save(line);
+            }
+            None => line.word("none"),
+        }
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        match words.word()? {
+            "some" => Some(Some(T::load(words)?)),
+            "none" => Some(None),
+            _ => None,
+        }
+    }
+}
+
+// Written as how many there are, then each of them
+impl<T: Save> Save for Vec<T> {
+    fn save(&self, line: &mut Line) {
+        line.word(self.len());
+        for value in self.iter() {
+            value.save(line);
+        }
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        let len: usize = words.read()?;
+        (0..len).map(|_| words.read()).collect()
+    }
+}
+
+impl Save for Vec2i {
+    fn save(&self, line: &mut Line) {
+        line.word(self.0);
+        line.word(self.1);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Some(Vec2i(words.read()?, words.read()?))
+    }
+}
+
+impl Save for Rect {
+    fn save(&self, line: &mut Line) {
+        line.word(self.x);
+        line.word(self.y);
+        line.word(self.w);
+        line.word(self.h);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Some(Rect {
+            x: words.read()?,
+            y: words.read()?,
+            w: words.read()?,
+            h: words.read()?,
+        })
+    }
+}
+
+impl Save for Rgba {
+    fn save(&self, line: &mut Line) {
+        line.word(self.0);
+        line.word(self.1);
+        line.word(self.2);
+        line.word(self.3);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Some(Rgba(
+            words.read()?,
+            words.read()?,
+            words.read()?,
+            words.read()?,
+        ))
+    }
+}
+
+impl Save for Difficulty {
+    fn save(&self, line: &mut Line) {
+        line.word(self.name());
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Difficulty::from_name(words.word()?)
+    }
+}
+
+impl Save for ScrollAxis {
+    fn save(&self, line: &mut Line) {
+        line.word(format!("{:?}", self));
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        match words.word()? {
+            "Vertical" => Some(ScrollAxis::Vertical),
+            "Horizontal" => Some(ScrollAxis::Horizontal),
+            _ => None,
+        }
+    }
+}
+
+// Stages as their name then their fields, e.g. "Rocks true 1"
+impl Save for GameStage {
+    fn save(&self, line: &mut Line) {
+        match *self {
+            GameStage::Menu => line.word("Menu"),
+            GameStage::Rocks(spawn, wave) => {
+                line.word("Rocks");
+                line.word(spawn);
+                line.word(wave);
+            }
+            GameStage::Boulders(spawn, wave) => {
+                line.word("Boulders");
+                line.word(spawn);
+                line.word(wave);
+            }
+            GameStage::BossWarning(since) => {
+                line.word("BossWarning");
+                line.word(since);
+            }
+            GameStage::Boss => line.word("Boss"),
+            GameStage::Victory(frame) => {
+                line.word("Victory");
+                line.word(frame);
+            }
+            GameStage::GameOver(frame) => {
+                line.word("GameOver");
+                line.word(frame);
+            }
+        }
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        let stage = match words.word()? {
+            "Menu" => GameStage::Menu,
+            "Rocks" => GameStage::Rocks(words.read()?, words.read()?),
+            "Boulders" => GameStage::Boulders(words.read()?, words.read()?),
+            "BossWarning" => GameStage::BossWarning(words.read()?),
+            "Boss" => GameStage::Boss,
+            "Victory" => GameStage::Victory(words.read()?),
+            "GameOver" => GameStage::GameOver(words.read()?),
+            _ => return None,
+        };
+        Some(stage)
+    }
+}
+
+impl Save for Transition {
+    fn save(&self, line: &mut Line) {
+        line.word(self.started_at);
+        self.to.save(line);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Some(Transition {
+            started_at: words.read()?,
+            to: words.read()?,
+        })
+    }
+}
+
+impl Save for Weapon {
+    fn save(&self, line: &mut Line) {
+        match *self {
+            Weapon::Single => line.word("Single"),
+            Weapon::Spread(n) => {
+                line.word("Spread");
+                line.word(n);
+            }
+            Weapon::Rapid => line.word("Rapid"),
+            Weapon::Homing => line.word("Homing"),
+        }
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        let weapon = match words.word()? {
+            "Single" => Weapon::Single,
+            "Spread" => Weapon::Spread(words.read()?),
+            "Rapid" => Weapon::Rapid,
+            "Homing" => Weapon::Homing,
+            _ => return None,
+        };
+        Some(weapon)
+    }
+}
+
+impl Save for EnemyKind {
+    fn save(&self, line: &mut Line) {
+        line.word(format!("{:?}", self));
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        let kind = match words.word()? {
+            "Chaser" => EnemyKind::Chaser,
+            "Strafer" => EnemyKind::Strafer,
+            "Shooter" => EnemyKind::Shooter,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+impl Save for Team {
+    fn save(&self, line: &mut Line) {
+        line.word(format!("{:?}", self));
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        match words.word()? {
+            "Player" => Some(Team::Player),
+            "Enemy" => Some(Team::Enemy),
+            _ => None,
+        }
+    }
+}
+
+impl Save for PowerUpKind {
+    fn save(&self, line: &mut Line) {
+        match self {
+            PowerUpKind::Heal(hp) => {
+                line.word("Heal");
+                line.word(hp);
+            }
+            PowerUpKind::Weapon(weapon) => {
+                line.word("Weapon");
+                weapon.save(line);
+            }
+            PowerUpKind::Shield => line.word("Shield"),
+        }
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        let kind = match words.word()? {
+            "Heal" => PowerUpKind::Heal(words.read()?),
+            "Weapon" => PowerUpKind::Weapon(words.read()?),
+            "Shield" => PowerUpKind::Shield,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+impl Save for Terrain {
+    fn save(&self, line: &mut Line) {
+        self.rect.save(line);
+        line.word(self.created_at);
+        line.word(self.destructible);
+        line.word(self.hp);
+        line.word(self.max_hp);
+        line.word(self.velocity.0);
+        line.word(self.velocity.1);
+        line.word(self.remainder.0);
+        line.word(self.remainder.1);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Some(Terrain {
+            rect: words.read()?,
+            created_at: words.read()?,
+            destructible: words.read()?,
+            hp: words.read()?,
+            max_hp: words.read()?,
+            velocity: Vec2f(words.read()?, words.read()?),
+            remainder: Vec2f(words.read()?, words.read()?),
+        })
+    }
+}
+
+impl Save for Mobile {
+    fn save(&self, line: &mut Line) {
+        self.rect.save(line);
+        line.word(self.vx);
+        line.word(self.vy);
+        line.word(self.hp);
+        line.word(self.is_player);
+        line.word(self.is_boss);
+        line.word(self.last_fired);
+        line.word(self.fire_cooldown);
+        line.word(self.invuln_until);
+        line.word(self.shield_until);
+        line.word(self.armor);
+        self.hitbox_offset.save(line);
+        self.kind.save(line);
+        line.word(self.remainder.0);
+        line.word(self.remainder.1);
+        line.word(self.score_value);
+        line.word(self.max_hp);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Some(Mobile {
+            rect: words.read()?,
+            vx: words.read()?,
+            vy: words.read()?,
+            hp: words.read()?,
+            is_player: words.read()?,
+            is_boss: words.read()?,
+            last_fired: words.read()?,
+            fire_cooldown: words.read()?,
+            invuln_until: words.read()?,
+            shield_until: words.read()?,
+            armor: words.read()?,
+            hitbox_offset: words.read()?,
+            kind: words.read()?,
+            remainder: Vec2f(words.read()?, words.read()?),
+            score_value: words.read()?,
+            max_hp: words.read()?,
+        })
+    }
+}
+
+impl Save for Projectile {
+    fn save(&self, line: &mut Line) {
+        self.rect.save(line);
+        line.word(self.vx);
+        line.word(self.vy);
+        line.word(self.hp);
+        self.team.save(line);
+        line.word(self.homing);
+        // Just whether there's a trail; where it's been is only for show
+        line.word(self.trail.is_some());
+        line.word(self.remainder.0);
+        line.word(self.remainder.1);
+        line.word(self.created_at);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Some(Projectile {
+            rect: words.read()?,
+            vx: words.read()?,
+            vy: words.read()?,
+            hp: words.read()?,
+            team: words.read()?,
+            homing: words.read()?,
+            trail: words.read::<bool>()?.then(Trail::default),
+            remainder: Vec2f(words.read()?, words.read()?),
+            created_at: words.read()?,
+        })
+    }
+}
+
+impl Save for PowerUp {
+    fn save(&self, line: &mut Line) {
+        self.rect.save(line);
+        line.word(self.collected);
+        self.kind.save(line);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        let rect = words.read()?;
+        let collected = words.read()?;
+        Some(PowerUp {
+            collected,
+            ..PowerUp::new(rect, words.read()?)
+        })
+    }
+}
+
+impl Save for TriggerKind {
+    fn save(&self, line: &mut Line) {
+        match self {
+            TriggerKind::Bonus(points) => {
+                line.word("Bonus");
+                line.word(points);
+            }
+        }
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        match words.word()? {
+            "Bonus" => Some(TriggerKind::Bonus(words.read()?)),
+            _ => None,
+        }
+    }
+}
+
+impl Save for Trigger {
+    fn save(&self, line: &mut Line) {
+        self.rect.save(line);
+        line.word(self.occupied);
+        self.kind.save(line);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        let rect = words.read()?;
+        let occupied = words.read()?;
+        Some(Trigger {
+            occupied,
+            ..Trigger::new(rect, words.read()?)
+        })
+    }
+}
+
+impl Save for Boss {
+    fn save(&self, line: &mut Line) {
+        line.word(self.max_hp);
+        line.word(self.phase);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Some(Boss {
+            max_hp: words.read()?,
+            phase: words.read()?,
+        })
+    }
+}
+
+impl Save for Particle {
+    fn save(&self, line: &mut Line) {
+        line.word(self.pos.0);
+        line.word(self.pos.1);
+        line.word(self.vel.0);
+        line.word(self.vel.1);
+        line.word(self.created_at);
+        line.word(self.ttl);
+        self.color.save(line);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Some(Particle {
+            pos: (words.read()?, words.read()?),
+            vel: (words.read()?, words.read()?),
+            created_at: words.read()?,
+            ttl: words.read()?,
+            color: words.read()?,
+        })
+    }
+}
+
+// The text goes last
+impl Save for Popup {
+    fn save(&self, line: &mut Line) {
+        self.pos.save(line);
+        line.word(self.created_at);
+        line.word(self.ttl);
+        self.text.save(line);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Some(Popup {
+            pos: words.read()?,
+            created_at: words.read()?,
+            ttl: words.read()?,
+            text: words.read()?,
+        })
+    }
+}
+
+// Takes the rest of the line, so it may have spaces in it
+impl Save for String {
+    fn save(&self, line: &mut Line) {
+        line.word(self);
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        Some(words.rest().to_string())
+    }
+}
+
+// Written the way replays write a frame, which takes the rest of the line
+impl Save for InputState {
+    fn save(&self, line: &mut Line) {
+        line.word(replay::format_input(self));
+    }
+
+    fn load(words: &mut Words) -> Option<Self> {
+        replay::parse_input(words.rest())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_from_other_versions_are_turned_away() {
+        assert!(check_header(Some(&header())).is_ok());
+        let old = format!("{} {}", HEADER, SAVE_VERSION - 1);
+        assert!(matches!(
+            check_header(Some(&old)),
+            Err(SaveError::Version(v)) if v == SAVE_VERSION - 1
+        ));
+        assert!(matches!(
+            check_header(Some("P3 4 4")),
+            Err(SaveError::NotASave)
+        ));
+        assert!(matches!(check_header(None), Err(SaveError::NotASave)));
+    }
+}
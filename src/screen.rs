@@ -1,6 +1,7 @@
 // We can pull in definitions from elsewhere in the crate!
 use crate::texture::Texture;
 use crate::types::{Rect, Rgba, Vec2i};
+use std::path::Path;
 
 pub struct Screen<'fb> {
     framebuffer: &'fb mut [u8],
@@ -11,6 +12,8 @@ pub struct Screen<'fb> {
 }
 
 impl<'fb> Screen<'fb> {
+    // framebuffer can be any width * height * depth buffer, not just the one
+    // pixels gives us, e.g. a plain Vec<u8> for drawing off screen.
     pub fn wrap(
         framebuffer: &'fb mut [u8],
         width: usize,
@@ -38,6 +41,24 @@ impl<'fb> Screen<'fb> {
         self.position.1 += dy;
     }
 
+    // Writes what's been drawn so far out as a PNG image
+    pub fn save_png(&self, path: &Path) -> image::ImageResult<()> {
+        let color = match self.depth {
+            1 => image::ColorType::L8,
+            2 => image::ColorType::La8,
+            3 => image::ColorType::Rgb8,
+            4 => image::ColorType::Rgba8,
+            d => panic!("Can't save a screen {} bytes deep as an image", d),
+        };
+        image::save_buffer(
+            path,
+            &self.framebuffer[..self.width * self.height * self.depth],
+            self.width as u32,
+            self.height as u32,
+            color,
+        )
+    }
+
     pub fn bounds(&self) -> Rect {
         Rect {
             x: self.position.0,
@@ -47,16 +68,8 @@ impl<'fb> Screen<'fb> {
         }
     }
 
-    pub fn is_visible(&self, object: Rect) -> bool {
-        !(object.x > self.position.0 + self.width as i32
-            || object.y > self.position.1 + self.height as i32
-            || object.x + (object.w as i32) < self.position.0
-            || object.y + (object.h as i32) < self.position.1)
-    }
-
     // Our old, slow friend draw_at, now with super scrolling powers!
     #[inline(always)]
-    #[allow(dead_code)]
     pub fn draw_at(&mut self, col: Rgba, Vec2i(x, y): Vec2i) {
         let x = x - self.position.0;
         let y = y - self.position.1;
@@ -74,6 +87,21 @@ impl<'fb> Screen<'fb> {
         self.framebuffer[idx..(idx + self.depth)].copy_from_slice(&c);
     }
 
+    // Read back a pixel; unlike draw_at, (x, y) is in framebuffer coordinates
+    #[cfg(test)]
+    pub fn get_pixel(&self, x: usize, y: usize) -> Rgba {
+        assert!(
+            x < self.width && y < self.height,
+            "Pixel ({}, {}) out of bounds",
+            x,
+            y
+        );
+        assert_eq!(self.depth, 4);
+        let idx = y * self.width * self.depth + x * self.depth;
+        let px = &self.framebuffer[idx..(idx + self.depth)];
+        Rgba(px[0], px[1], px[2], px[3])
+    }
+
     // Clear's the same...
     pub fn clear(&mut self, col: Rgba) {
         let c = [col.0, col.1, col.2, col.3];
@@ -82,15 +110,14 @@ impl<'fb> Screen<'fb> {
         }
     }
 
-    // Rect needs a translation to start
+    // Rect needs a translation to start.
+    // A translucent col (alpha < 255) is blended over what's already there.
     pub fn rect(&mut self, r: Rect, col: Rgba) {
         let c = [col.0, col.1, col.2, col.3];
+        let ca = col.3 as f32 / 255.0;
+        let opaque = col.with_alpha(255);
         // Here's the translation
-        let r = Rect {
-            x: r.x - self.position.0,
-            y: r.y - self.position.1,
-            ..r
-        };
+        let r = r.translated(-self.position.0, -self.position.1);
         // And the rest is just the same
         let x0 = r.x.max(0).min(self.width as i32) as usize;
         let x1 = (r.x + r.w as i32).max(0).min(self.width as i32) as usize;
@@ -100,14 +127,64 @@ impl<'fb> Screen<'fb> {
         let pitch = self.width * depth;
         for row in self.framebuffer[(y0 * pitch)..(y1 * pitch)].chunks_exact_mut(pitch) {
             for p in row[(x0 * depth)..(x1 * depth)].chunks_exact_mut(depth) {
-                // TODO should handle alpha blending
-                p.copy_from_slice(&c);
+                if col.3 == 255 {
+                    p.copy_from_slice(&c);
+                    continue;
+                }
+                // Blending towards the opaque color also works out the right alpha
+                let Rgba(r, g, b, a) = Rgba(p[0], p[1], p[2], p[3]).blend(opaque, ca);
+                p.copy_from_slice(&[r, g, b, a]);
+            }
+        }
+    }
+
+    // Just the 1px border of r, inside r
+    pub fn rect_outline(&mut self, r: Rect, col: Rgba) {
+        if r.w == 0 || r.h == 0 {
+            return;
+        }
+        let (x0, y0) = (r.x, r.y);
+        let (x1, y1) = (r.x + r.w as i32 - 1, r.y + r.h as i32 - 1);
+        self.line(Vec2i(x0, y0), Vec2i(x1, y0), col);
+        self.line(Vec2i(x1, y0), Vec2i(x1, y1), col);
+        self.line(Vec2i(x1, y1), Vec2i(x0, y1), col);
+        self.line(Vec2i(x0, y1), Vec2i(x0, y0), col);
+    }
+
+    // A 1px ring of the given radius around center (midpoint circle algorithm)
+    pub fn circle_outline(&mut self, center: Vec2i, radius: i32, col: Rgba) {
+        let (mut x, mut y) = (radius, 0);
+        let mut err = 1 - radius;
+        while x >= y {
+            // One point in each octant
+            for &(dx, dy) in &[(x, y), (y, x), (-y, x), (-x, y)] {
+                self.draw_at(col, Vec2i(center.0 + dx, center.1 + dy));
+                self.draw_at(col, Vec2i(center.0 - dx, center.1 - dy));
+            }
+            y += 1;
+            if err < 0 {
+                err += 2 * y + 1;
+            } else {
+                x -= 1;
+                err += 2 * (y - x) + 1;
             }
         }
     }
 
     // Ditto line
-    pub fn line(&mut self, Vec2i(x0, y0): Vec2i, Vec2i(x1, y1): Vec2i, col: Rgba) {
+    pub fn line(&mut self, p0: Vec2i, p1: Vec2i, col: Rgba) {
+        self.line_thick(p0, p1, 1, col);
+    }
+
+    // Same traversal as a 1px line, but each step fills a span of thickness
+    // pixels perpendicular to the line's major axis.
+    pub fn line_thick(
+        &mut self,
+        Vec2i(x0, y0): Vec2i,
+        Vec2i(x1, y1): Vec2i,
+        thickness: u32,
+        col: Rgba,
+    ) {
         let col = [col.0, col.1, col.2, col.3];
         // translate translate
         let x0 = x0 - self.position.0;
@@ -127,17 +204,25 @@ impl<'fb> Screen<'fb> {
         let height = self.height as i32;
         let depth = self.depth;
         let pitch = self.width * depth;
+        // Mostly-horizontal lines get thickened vertically and vice versa
+        let (px, py) = if dx >= -dy { (0, 1) } else { (1, 0) };
+        let lo = -((thickness as i32 - 1) / 2);
+        let hi = thickness as i32 / 2;
         while x != x1 || y != y1 {
-            // We couldn't just clamp x0/y0 and x1/y1 into bounds, because then
-            // we might change the slope of the line.
-            // We could find the intercept of the line with the left/right or top/bottom edges of the rect though, but that's work!
-            if 0 <= x && x < width && 0 <= y && y < height {
-                // TODO this bounds check could in theory be avoided with
-                // the unsafe get_unchecked, but maybe better not...
-                // TODO better handle alpha blending too, but not just yet...
-                self.framebuffer[(y as usize * pitch + x as usize * depth)
-                    ..(y as usize * pitch + (x as usize + 1) * depth)]
-                    .copy_from_slice(&col);
+            for t in lo..=hi {
+                let x = x + px * t;
+                let y = y + py * t;
+                // We couldn't just clamp x0/y0 and x1/y1 into bounds, because then
+                // we might change the slope of the line.
+                // We could find the intercept of the line with the left/right or top/bottom edges of the rect though, but that's work!
+                if 0 <= x && x < width && 0 <= y && y < height {
+                    // TODO this bounds check could in theory be avoided with
+                    // the unsafe get_unchecked, but maybe better not...
+                    // TODO better handle alpha blending too, but not just yet...
+                    self.framebuffer[(y as usize * pitch + x as usize * depth)
+                        ..(y as usize * pitch + (x as usize + 1) * depth)]
+                        .copy_from_slice(&col);
+                }
             }
             let e2 = 2 * err;
             if dy <= e2 {
@@ -152,7 +237,74 @@ impl<'fb> Screen<'fb> {
     }
 
     // Bitblt too begins with a translation
-    pub fn bitblt(&mut self, src: &Texture, from: Rect, Vec2i(to_x, to_y): Vec2i) {
+    pub fn bitblt(&mut self, src: &Texture, from: Rect, to: Vec2i) {
+        self.blit_with(src, from, to, composite_over);
+    }
+
+    /**
+     * Like bitblt, but with the source rect turned clockwise by quarter_turns
+     * quarter turns first. For odd turns the blitted image is from.h wide and
+     * from.w tall. to is where its top-left corner ends up.
+     *
+     * Rotation scrambles the row order, so this goes pixel by pixel instead of
+     * row by row like blit_with, but clips the same way.
+     */
+    pub fn bitblt_rot90(&mut self, src: &Texture, from: Rect, to: Vec2i, quarter_turns: u8) {
+        let turns = quarter_turns % 4;
+        if turns == 0 {
+            self.bitblt(src, from, to);
+            return;
+        }
+        assert!(src.valid_frame(from));
+        let (w, h) = (from.w as i32, from.h as i32);
+        let (dw, dh) = if turns % 2 == 1 { (h, w) } else { (w, h) };
+        let to_x = to.0 - self.position.0;
+        let to_y = to.1 - self.position.1;
+        let depth = self.depth;
+        assert_eq!(depth, src.depth());
+        let src_pitch = src.pitch();
+        let dst_pitch = self.width * depth;
+        let src_buf = src.buffer();
+        let ys = to_y.max(0)..(to_y + dh).min(self.height as i32);
+        let xs = to_x.max(0)..(to_x + dw).min(self.width as i32);
+        for y in ys {
+            for x in xs.clone() {
+                // Position within the rotated image, then back to the source
+                let (dx, dy) = (x - to_x, y - to_y);
+                let (sx, sy) = match turns {
+                    1 => (dy, h - 1 - dx),
+                    2 => (w - 1 - dx, h - 1 - dy),
+                    _ => (w - 1 - dy, dx),
+                };
+                let s = (from.y + sy) as usize * src_pitch + (from.x + sx) as usize * depth;
+                let d = y as usize * dst_pitch + x as usize * depth;
+                composite_over(&mut self.framebuffer[d..d + depth], &src_buf[s..s + depth]);
+            }
+        }
+    }
+
+    // Like bitblt, but only the source alpha is used: it acts as a mask that
+    // gets filled in with col. Handy for recoloring glyphs from a font sheet.
+    pub fn bitblt_colored(&mut self, src: &Texture, from: Rect, to: Vec2i, col: Rgba) {
+        let c = [col.0, col.1, col.2];
+        let ca = col.3 as f32 / 255.0;
+        self.blit_with(src, from, to, |to, from| {
+            let ta = to[3] as f32 / 255.0;
+            let fa = from[3] as f32 / 255.0 * ca;
+            for i in 0..3 {
+                to[i] = ((c[i] as f32 * fa) + (to[i] as f32 * (1.0 - fa))).round() as u8;
+            }
+            to[3] = ((fa + ta * (1.0 - fa)) * 255.0).round() as u8;
+        });
+    }
+
+    fn blit_with<F: Fn(&mut [u8], &[u8])>(
+        &mut self,
+        src: &Texture,
+        from: Rect,
+        Vec2i(to_x, to_y): Vec2i,
+        composite: F,
+    ) {
         let (tw, th) = src.size();
         assert!(0 <= from.x);
         assert!(from.x < tw as i32);
@@ -194,15 +346,116 @@ impl<'fb> Screen<'fb> {
             let from_cols = row_a
                 [(depth * (from.x + x_skip) as usize)..(depth * (from.x + x_count) as usize)]
                 .chunks_exact(depth);
-            // Composite over, assume premultiplied rgba8888
             for (to, from) in to_cols.zip(from_cols) {
-                let ta = to[3] as f32 / 255.0;
-                let fa = from[3] as f32 / 255.0;
-                for i in 0..3 {
-                    to[i] = from[i].saturating_add((to[i] as f32 * (1.0 - fa)).round() as u8);
-                }
-                to[3] = ((fa + ta * (1.0 - fa)) * 255.0).round() as u8;
+                composite(to, from);
             }
         }
     }
 }
+
+// Composite from over to, assuming premultiplied rgba8888
+fn composite_over(to: &mut [u8], from: &[u8]) {
+    let ta = to[3] as f32 / 255.0;
+    let fa = from[3] as f32 / 255.0;
+    for i in 0..3 {
+        to[i] = from[i].saturating_add((to[i] as f32 * (1.0 - fa)).round() as u8);
+    }
+    to[3] = ((fa + ta * (1.0 - fa)) * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba = Rgba(255, 0, 0, 255);
+
+    #[test]
+    fn clear_fills_every_pixel() {
+        let mut fb = vec![0; 7 * 5 * 4];
+        let mut screen = Screen::wrap(&mut fb, 7, 5, 4, Vec2i(30, -40));
+        screen.clear(Rgba(12, 34, 56, 255));
+        for y in 0..5 {
+            for x in 0..7 {
+                assert_eq!(screen.get_pixel(x, y), Rgba(12, 34, 56, 255));
+            }
+        }
+    }
+
+    #[test]
+    fn thick_line_fills_a_row_either_side() {
+        let mut fb = vec![0; 20 * 10 * 4];
+        let mut screen = Screen::wrap(&mut fb, 20, 10, 4, Vec2i(0, 0));
+        screen.line_thick(Vec2i(2, 5), Vec2i(12, 5), 3, RED);
+        for x in 2..12 {
+            for y in 4..=6 {
+                assert_eq!(screen.get_pixel(x, y), RED, "({}, {})", x, y);
+            }
+            assert_eq!(screen.get_pixel(x, 3), Rgba(0, 0, 0, 0));
+            assert_eq!(screen.get_pixel(x, 7), Rgba(0, 0, 0, 0));
+        }
+    }
+
+    #[test]
+    fn quarter_turn_rotates_clockwise() {
+        // A 2 wide, 3 tall picture with every pixel a different color
+        let color = |x: u32, y: u32| Rgba(50 + 100 * x as u8, 40 * y as u8, 7, 255);
+        let image = image::RgbaImage::from_fn(2, 3, |x, y| {
+            let Rgba(r, g, b, a) = color(x, y);
+            image::Rgba([r, g, b, a])
+        });
+        let texture = Texture::new(image);
+        let mut fb = vec![0; 5 * 5 * 4];
+        let mut screen = Screen::wrap(&mut fb, 5, 5, 4, Vec2i(0, 0));
+        let from = Rect {
+            x: 0,
+            y: 0,
+            w: 2,
+            h: 3,
+        };
+        screen.bitblt_rot90(&texture, from, Vec2i(1, 1), 1);
+        // Now 3 wide and 2 tall: the left column is the top row, read bottom up
+        let expected = [
+            [color(0, 2), color(0, 1), color(0, 0)],
+            [color(1, 2), color(1, 1), color(1, 0)],
+        ];
+        for (y, row) in expected.iter().enumerate() {
+            for (x, &col) in row.iter().enumerate() {
+                assert_eq!(screen.get_pixel(1 + x, 1 + y), col, "({}, {})", x, y);
+            }
+        }
+        assert_eq!(screen.get_pixel(1, 3), Rgba(0, 0, 0, 0));
+        assert_eq!(screen.get_pixel(4, 1), Rgba(0, 0, 0, 0));
+    }
+
+    #[test]
+    fn saved_png_loads_back_the_same() {
+        let mut fb = vec![0; 6 * 4 * 4];
+        let mut screen = Screen::wrap(&mut fb, 6, 4, 4, Vec2i(0, 0));
+        screen.clear(Rgba(0, 0, 255, 255));
+        screen.rect(
+            Rect {
+                x: 1,
+                y: 1,
+                w: 2,
+                h: 2,
+            },
+            RED,
+        );
+        let path = std::env::temp_dir().join("screen_saved_png_loads_back.png");
+        screen.save_png(&path).unwrap();
+        let texture = Texture::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(texture.size(), (6, 4));
+        for (x, y) in [(0, 0), (3, 1), (5, 3), (1, 1), (2, 2)] {
+            assert_eq!(
+                texture.get_pixel(x, y),
+                screen.get_pixel(x, y),
+                "({}, {})",
+                x,
+                y
+            );
+        }
+        assert_eq!(texture.get_pixel(2, 2), RED);
+    }
+}
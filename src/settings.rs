@@ -0,0 +1,197 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::controls::{Controls, ControlsError};
+use crate::difficulty::Difficulty;
+use crate::display::WINDOW_SCALE;
+
+/*
+Player preferences that outlast a run, kept as plain text next to the game:
+
+    volume = 0.5
+    muted = false
+    difficulty = Normal
+    window_scale = 2
+    MoveLeft = A
+
+The lowercase names are settings; anything else is a key binding, in the same
+"Action = Key" form as a controls file. Lines starting with # are comments, and
+anything left out keeps its default. A value that reads fine but is out of
+range (a volume of 3, say) falls back to the default instead of failing the
+whole file.
+*/
+
+// The biggest window_scale allowed; much past this the window won't fit on a screen
+pub const MAX_WINDOW_SCALE: u32 = 4;
+
+/// Ways loading a settings file can fail
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(std::io::Error),
+    /// A line that isn't "name = value", at this (1-based) line number
+    BadLine(usize, String),
+    /// A setting whose value isn't the right kind of thing, like a volume of "loud"
+    BadValue(usize, String),
+    /// A key binding that didn't make sense
+    Controls(ControlsError),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Io(e) => write!(f, "couldn't read settings: {}", e),
+            SettingsError::BadLine(line, text) => {
+                write!(f, "line {}: expected name = value, got {:?}", line, text)
+            }
+            SettingsError::BadValue(line, text) => {
+                write!(f, "line {}: can't make sense of {:?}", line, text)
+            }
+            SettingsError::Controls(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for SettingsError {
+    fn from(e: std::io::Error) -> Self {
+        SettingsError::Io(e)
+    }
+}
+
+impl From<ControlsError> for SettingsError {
+    fn from(e: ControlsError) -> Self {
+        SettingsError::Controls(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    // Master volume for sound, 0.0 to 1.0
+    pub volume: f32,
+    pub muted: bool,
+    // What the title screen starts on
+    pub difficulty: Difficulty,
+    // How many window pixels wide each framebuffer pixel is when the window opens
+    pub window_scale: u32,
+    pub controls: Controls,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            volume: 0.5,
+            muted: false,
+            difficulty: Difficulty::default(),
+            window_scale: WINDOW_SCALE,
+            controls: Controls::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> Result<Self, SettingsError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl FromStr for Settings {
+    type Err = SettingsError;
+
+    /// Parses the format written by to_string, on top of the defaults
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut settings = Self::default();
+        let defaults = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = match line.find('=') {
+                Some(eq) => (line[..eq].trim(), line[eq + 1..].trim()),
+                None => return Err(SettingsError::BadLine(i + 1, line.to_string())),
+            };
+            let bad_value = || SettingsError::BadValue(i + 1, line.to_string());
+            match name {
+                "volume" => {
+                    let volume: f32 = value.parse().map_err(|_| bad_value())?;
+                    settings.volume = if (0.0..=1.0).contains(&volume) {
+                        volume
+                    } else {
+                        defaults.volume
+                    };
+                }
+                "muted" => settings.muted = value.parse().map_err(|_| bad_value())?,
+                "difficulty" => {
+                    settings.difficulty = Difficulty::from_name(value).ok_or_else(bad_value)?
+                }
+                "window_scale" => {
+                    let scale: u32 = value.parse().map_err(|_| bad_value())?;
+                    settings.window_scale = if (1..=MAX_WINDOW_SCALE).contains(&scale) {
+                        scale
+                    } else {
+                        defaults.window_scale
+                    };
+                }
+                _ => settings.controls.bind_names(i + 1, name, value)?,
+            }
+        }
+        Ok(settings)
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "volume = {}", self.volume)?;
+        writeln!(f, "muted = {}", self.muted)?;
+        writeln!(f, "difficulty = {}", self.difficulty.name())?;
+        writeln!(f, "window_scale = {}", self.window_scale)?;
+        write!(f, "{}", self.controls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controls::Action;
+    use winit::event::VirtualKeyCode;
+
+    #[test]
+    fn settings_survive_a_round_trip() {
+        let mut settings = Settings {
+            volume: 0.25,
+            muted: true,
+            difficulty: Difficulty::Hard,
+            window_scale: 3,
+            ..Settings::default()
+        };
+        settings.controls.bind(Action::Fire, VirtualKeyCode::J);
+        let path = std::env::temp_dir().join("settings_survive_a_round_trip.cfg");
+        settings.save(&path).unwrap();
+        let loaded = Settings::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, settings);
+    }
+
+    #[test]
+    fn out_of_range_values_fall_back_to_defaults() {
+        let settings = "volume = 3\nwindow_scale = 40\nmuted = true\n"
+            .parse::<Settings>()
+            .unwrap();
+        let defaults = Settings::default();
+        assert_eq!(settings.volume, defaults.volume);
+        assert_eq!(settings.window_scale, defaults.window_scale);
+        // The rest of the file still counts
+        assert!(settings.muted);
+
+        // Values that aren't numbers at all are another matter
+        assert!(matches!(
+            "muted = false\nvolume = loud".parse::<Settings>(),
+            Err(SettingsError::BadValue(2, _))
+        ));
+    }
+}
@@ -1,37 +1,142 @@
-use crate::animation::AnimationSM;
+use crate::animation::{Animation, AnimationSM};
+use crate::atlas::TextureRegion;
 use crate::texture::Texture;
-use crate::types::Vec2i;
+use crate::types::{Rect, Vec2i};
 use std::rc::Rc;
 
+// Where kinds of sprite go in the draw order; higher is drawn later, on top
+pub const Z_SHIPS: i32 = 0;
+pub const Z_TERRAIN: i32 = 10;
+pub const Z_PICKUPS: i32 = 20;
+pub const Z_EFFECTS: i32 = 30;
+
 pub struct Sprite {
     image: Rc<Texture>,
     // pub animation: Rc<Animation>,
     pub animation_sm: AnimationSM,
     pub position: Vec2i,
+    // Clockwise quarter turns to draw the sprite at
+    pub rotation: u8,
+    // Sprites with higher z are drawn over those with lower
+    pub z: i32,
+    // Hidden sprites are skipped by draw_sprite but otherwise carry on as usual
+    pub visible: bool,
 }
 
 impl Sprite {
     pub fn new(image: &Rc<Texture>, animation_sm: AnimationSM, position: Vec2i) -> Self {
         Self {
             image: Rc::clone(image),
-            animation_sm: animation_sm,
+            animation_sm,
             position,
+            rotation: 0,
+            z: Z_SHIPS,
+            visible: true,
         }
     }
+
+    pub fn with_z(mut self, z: i32) -> Self {
+        self.z = z;
+        self
+    }
+
+    // A sprite that only ever shows region, as of frame start_time
+    pub fn from_region(region: &TextureRegion, start_time: usize, position: Vec2i) -> Self {
+        Self::new(
+            &region.texture,
+            AnimationSM::new(
+                vec![Animation::new(
+                    vec![region.rect],
+                    vec![60],
+                    start_time,
+                    true,
+                )],
+                vec![],
+                0,
+            ),
+            position,
+        )
+    }
+
+    // The part of the sheet showing at frame now
+    pub fn frame(&mut self, now: usize) -> Rect {
+        self.animation_sm.current_anim(now).current_frame(now)
+    }
 }
 
 pub trait DrawSpriteExt {
     fn draw_sprite(&mut self, s: &mut Sprite, cur_frame: usize);
+    fn draw_sprites(&mut self, sprites: Vec<&mut Sprite>, cur_frame: usize);
 }
 
 use crate::screen::Screen;
 impl<'fb> DrawSpriteExt for Screen<'fb> {
     fn draw_sprite(&mut self, s: &mut Sprite, cur_frame: usize) {
-        let frame = s
-            .animation_sm
-            .current_anim(cur_frame)
-            .current_frame(cur_frame);
+        if !s.visible {
+            return;
+        }
+        let frame = s.frame(cur_frame);
+        self.bitblt_rot90(&s.image, frame, s.position, s.rotation);
+    }
+
+    // Lowest z first; the sort is stable, so ties keep the order they're given in
+    fn draw_sprites(&mut self, mut sprites: Vec<&mut Sprite>, cur_frame: usize) {
+        sprites.sort_by_key(|s| s.z);
+        for s in sprites {
+            self.draw_sprite(s, cur_frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Rgba;
+
+    // A 4x4 block of col at pos
+    fn block(col: Rgba, pos: Vec2i) -> Sprite {
+        let Rgba(r, g, b, a) = col;
+        let image = image::RgbaImage::from_pixel(4, 4, image::Rgba([r, g, b, a]));
+        let region = TextureRegion {
+            texture: Rc::new(Texture::new(image)),
+            rect: Rect {
+                x: 0,
+                y: 0,
+                w: 4,
+                h: 4,
+            },
+        };
+        Sprite::from_region(&region, 0, pos)
+    }
+
+    // The pixel at (x, y) of an 8x8 screen
+    fn pixel(fb: &[u8], x: usize, y: usize) -> Rgba {
+        let i = (y * 8 + x) * 4;
+        Rgba(fb[i], fb[i + 1], fb[i + 2], fb[i + 3])
+    }
+
+    #[test]
+    fn higher_z_draws_on_top_whatever_the_order() {
+        let red = Rgba(255, 0, 0, 255);
+        let blue = Rgba(0, 0, 255, 255);
+        let mut fb = vec![0; 8 * 8 * 4];
+        let mut screen = Screen::wrap(&mut fb, 8, 8, 4, Vec2i(0, 0));
+        // Given first, but over the top of the other where they overlap
+        let mut high = block(red, Vec2i(2, 2)).with_z(Z_TERRAIN);
+        let mut low = block(blue, Vec2i(0, 0));
+        screen.draw_sprites(vec![&mut high, &mut low], 0);
+        assert_eq!(pixel(&fb, 3, 3), red);
+        assert_eq!(pixel(&fb, 1, 1), blue);
+        assert_eq!(pixel(&fb, 5, 5), red);
+    }
 
-        self.bitblt(&s.image, frame.clone(), s.position);
+    #[test]
+    fn invisible_sprites_leave_the_screen_alone() {
+        let mut fb = vec![0; 8 * 8 * 4];
+        let mut screen = Screen::wrap(&mut fb, 8, 8, 4, Vec2i(0, 0));
+        let mut sprite = block(Rgba(255, 0, 0, 255), Vec2i(2, 2));
+        sprite.visible = false;
+        screen.draw_sprite(&mut sprite, 0);
+        assert!(fb.iter().all(|&b| b == 0));
     }
 }
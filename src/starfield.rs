@@ -0,0 +1,115 @@
+use crate::screen::Screen;
+use crate::types::{Rect, Rgba, Vec2i};
+use rand::Rng;
+
+/*
+A parallax starfield: a few layers of stars, each sliding past at some fraction
+of the camera's speed, so far-away (slow) layers look deeper than near ones.
+Stars live in screen space and wrap around at the edges, so a handful of them
+covers an endless climb (or, scrolling sideways, an endless flight).
+*/
+
+struct Layer {
+    // How fast this layer moves relative to the camera, 0.0 (fixed) to 1.0 (with the world)
+    factor: f32,
+    size: u16,
+    color: Rgba,
+    // (x, y) in screen space when the camera is at 0
+    stars: Vec<(i32, i32)>,
+}
+
+pub struct Starfield {
+    layers: Vec<Layer>,
+    width: i32,
+    height: i32,
+}
+
+impl Starfield {
+    pub fn new<R: Rng>(rng: &mut R, width: usize, height: usize) -> Self {
+        // far to near: (factor, size, color, count)
+        let specs = [
+            (0.25, 1, Rgba(170, 170, 200, 255), 40),
+            (0.5, 2, Rgba(210, 210, 230, 255), 24),
+            (0.75, 2, Rgba::WHITE, 12),
+        ];
+        let layers = specs
+            .iter()
+            .map(|&(factor, size, color, count)| Layer {
+                factor,
+                size,
+                color,
+                stars: (0..count)
+                    .map(|_| {
+                        (
+                            rng.gen_range(0..width as i32),
+                            rng.gen_range(0..height as i32),
+                        )
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self {
+            layers,
+            width: width as i32,
+            height: height as i32,
+        }
+    }
+
+    /**
+     * Where star i of layer is on screen with the camera at scroll. Moving the
+     * camera by d moves the star by layer factor * d, wrapping around the edges.
+     */
+    pub fn star_pos(&self, layer: usize, i: usize, scroll: Vec2i) -> Vec2i {
+        let l = &self.layers[layer];
+        let (x, y) = l.stars[i];
+        let offset_x = (scroll.0 as f32 * l.factor) as i32;
+        let offset_y = (scroll.1 as f32 * l.factor) as i32;
+        Vec2i(
+            (x - offset_x).rem_euclid(self.width),
+            (y - offset_y).rem_euclid(self.height),
+        )
+    }
+
+    pub fn draw(&self, screen: &mut Screen, scroll: Vec2i) {
+        for (li, layer) in self.layers.iter().enumerate() {
+            for i in 0..layer.stars.len() {
+                let Vec2i(x, y) = self.star_pos(li, i, scroll);
+                screen.rect(
+                    Rect {
+                        x: scroll.0 + x,
+                        y: scroll.1 + y,
+                        w: layer.size,
+                        h: layer.size,
+                    },
+                    layer.color,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn stars_move_by_their_share_of_the_scroll() {
+        let field = Starfield::new(&mut StdRng::seed_from_u64(3), 100, 80);
+        let before = field.star_pos(1, 0, Vec2i(0, 0));
+        let after = field.star_pos(1, 0, Vec2i(0, -40));
+        // Half of 40 pixels for the middle layer, wrapping round the bottom
+        assert_eq!(after, Vec2i(before.0, (before.1 + 20) % 80));
+        let sideways = field.star_pos(0, 0, Vec2i(-8, 0));
+        let far = field.star_pos(0, 0, Vec2i(0, 0));
+        assert_eq!(sideways, Vec2i((far.0 + 2) % 100, far.1));
+
+        // And that's where it's drawn, relative to the camera
+        let mut fb = vec![0; 100 * 80 * 4];
+        let mut screen = Screen::wrap(&mut fb, 100, 80, 4, Vec2i(0, -40));
+        field.draw(&mut screen, Vec2i(0, -40));
+        let Vec2i(x, y) = after;
+        assert_ne!(screen.get_pixel(x as usize, y as usize), Rgba(0, 0, 0, 0));
+    }
+}
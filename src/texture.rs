@@ -1,4 +1,6 @@
 use crate::types::Rect;
+#[cfg(test)]
+use crate::types::Rgba;
 use image::{self, RgbaImage};
 use std::path::Path;
 
@@ -15,8 +17,13 @@ enum AlphaChannel {
     Last,
 }
 impl Texture {
+    #[allow(dead_code)]
     pub fn with_file(path: &Path) -> Self {
-        Self::new(image::open(path).expect("Couldn't load image").into_rgba8())
+        Self::load(path).expect("Couldn't load image")
+    }
+    // Like with_file, but hands back the error instead of panicking
+    pub fn load(path: &Path) -> Result<Self, image::ImageError> {
+        Ok(Self::new(image::open(path)?.into_rgba8()))
     }
     pub fn new(image: RgbaImage) -> Self {
         let (width, height) = image.dimensions();
@@ -42,7 +49,62 @@ impl Texture {
         &self.image
     }
 
-    #[allow(dead_code)]
+    // Index of pixel (x, y) in the buffer, which had better be on the texture
+    #[cfg(test)]
+    fn pixel_index(&self, x: usize, y: usize) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "Pixel ({}, {}) out of bounds",
+            x,
+            y
+        );
+        y * self.pitch() + x * self.depth
+    }
+
+    // A pixel as stored, i.e. with its colour premultiplied by its alpha
+    #[cfg(test)]
+    pub fn get_pixel(&self, x: usize, y: usize) -> Rgba {
+        let idx = self.pixel_index(x, y);
+        let px = &self.image[idx..(idx + self.depth)];
+        Rgba(px[0], px[1], px[2], px[3])
+    }
+
+    // Overwrites a pixel; col should be premultiplied like the rest of the texture
+    #[cfg(test)]
+    pub fn set_pixel(&mut self, x: usize, y: usize, col: Rgba) {
+        let idx = self.pixel_index(x, y);
+        self.image[idx..(idx + self.depth)].copy_from_slice(&[col.0, col.1, col.2, col.3]);
+    }
+
+    /**
+     * A copy with each pixel of a "from" colour in map changed to its "to"
+     * colour. Only rgb is compared and replaced: every pixel keeps its own
+     * alpha, so transparent and soft-edged pixels stay that way.
+     */
+    #[cfg(test)]
+    pub fn recolor(&self, map: &[(Rgba, Rgba)]) -> Texture {
+        let mut image = self.image.clone();
+        for px in image.chunks_exact_mut(self.depth) {
+            let a = px[self.depth - 1];
+            if a == 0 {
+                continue;
+            }
+            // Pixels are stored premultiplied, so compare against colours that are too
+            if let Some((_, to)) = map
+                .iter()
+                .find(|(from, _)| premultiplied(*from, a) == px[..3])
+            {
+                px[..3].copy_from_slice(&premultiplied(*to, a));
+            }
+        }
+        Self {
+            image,
+            width: self.width,
+            height: self.height,
+            depth: self.depth,
+        }
+    }
+
     pub fn valid_frame(&self, frame: Rect) -> bool {
         0 <= frame.x
             && (frame.x + frame.w as i32) <= (self.width as i32)
@@ -51,6 +113,17 @@ impl Texture {
     }
 }
 
+// c's rgb scaled by alpha a, rounded the way premultiply rounds
+#[cfg(test)]
+fn premultiplied(c: Rgba, a: u8) -> [u8; 3] {
+    let a = a as f32 / 255.0;
+    [
+        (c.0 as f32 * a) as u8,
+        (c.1 as f32 * a) as u8,
+        (c.2 as f32 * a) as u8,
+    ]
+}
+
 fn premultiply(img: &mut [u8], depth: usize, alpha: AlphaChannel) {
     match alpha {
         AlphaChannel::First => {
@@ -78,3 +151,46 @@ fn premultiply(img: &mut [u8], depth: usize, alpha: AlphaChannel) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recolor_swaps_red_for_blue_and_keeps_transparency() {
+        let red = image::Rgba([255, 0, 0, 255]);
+        let clear_red = image::Rgba([255, 0, 0, 0]);
+        let green = image::Rgba([0, 255, 0, 255]);
+        let image = RgbaImage::from_fn(2, 2, |x, y| match (x, y) {
+            (0, 0) | (1, 1) => red,
+            (1, 0) => green,
+            _ => clear_red,
+        });
+        let texture = Texture::new(image);
+        let blue = texture.recolor(&[(Rgba(255, 0, 0, 255), Rgba(0, 0, 255, 255))]);
+        assert_eq!(blue.size(), (2, 2));
+        assert_eq!(blue.get_pixel(0, 0), Rgba(0, 0, 255, 255));
+        assert_eq!(blue.get_pixel(1, 1), Rgba(0, 0, 255, 255));
+        assert_eq!(blue.get_pixel(1, 0), Rgba(0, 255, 0, 255));
+        assert_eq!(blue.get_pixel(0, 1).3, 0);
+        // The original is left as it was
+        assert_eq!(texture.get_pixel(0, 0), Rgba(255, 0, 0, 255));
+    }
+
+    #[test]
+    fn a_set_pixel_reads_back() {
+        let mut texture = Texture::new(RgbaImage::new(3, 2));
+        texture.set_pixel(2, 1, Rgba(10, 20, 30, 255));
+        assert_eq!(texture.get_pixel(2, 1), Rgba(10, 20, 30, 255));
+        // Its neighbours, before and after it in the buffer, are untouched
+        assert_eq!(texture.get_pixel(1, 1), Rgba(0, 0, 0, 0));
+        assert_eq!(texture.get_pixel(2, 0), Rgba(0, 0, 0, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn pixels_off_the_texture_are_refused() {
+        let texture = Texture::new(RgbaImage::new(3, 2));
+        texture.get_pixel(3, 0);
+    }
+}
@@ -1,4 +1,8 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
 use std::rc::Rc;
 
 use crate::screen::Screen;
@@ -21,10 +25,39 @@ pub struct Tileset {
     pub tiles: Vec<Tile>,
     texture: Rc<Texture>,
     pub tile_ids: HashMap<String, Vec<usize>>,
+    animations: HashMap<TileID, AnimatedTile>,
+}
+
+/// A tile that cycles through other tiles in its tileset over time
+pub struct AnimatedTile {
+    /// Tile indices to show, in order
+    pub frames: Vec<usize>,
+    /// How many frames each entry of frames is shown for
+    pub frame_times: Vec<usize>,
+    pub loops: bool,
+}
+
+impl AnimatedTile {
+    /// Which tile index is showing at frame now
+    fn frame_at(&self, now: usize) -> usize {
+        let total: usize = self.frame_times.iter().sum();
+        let mut rem = if self.loops {
+            now % total
+        } else {
+            now.min(total - 1)
+        };
+        for (frame, ft) in self.frames.iter().zip(self.frame_times.iter()) {
+            if rem < *ft {
+                return *frame;
+            }
+            rem -= ft;
+        }
+        *self.frames.last().unwrap()
+    }
 }
 
 /// Indices into a Tileset
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TileID(usize);
 
 /// Grab a tile with a given ID
@@ -46,12 +79,32 @@ impl Tileset {
             tiles,
             texture: Rc::clone(texture),
             tile_ids,
+            animations: HashMap::new(),
         }
     }
 
-    /// Get the frame rect for a tile ID
-    fn get_rect(&self, id: TileID) -> Rect {
-        let idx = id.0;
+    /// Make tile "id" animate; tiles without an animation are drawn as-is
+    pub fn animate(&mut self, id: usize, anim: AnimatedTile) {
+        assert!(self.contains(TileID(id)), "Animating nonexistent tile");
+        assert!(!anim.frames.is_empty(), "Tile animation has no frames");
+        assert_eq!(anim.frames.len(), anim.frame_times.len());
+        assert!(
+            anim.frame_times.iter().sum::<usize>() > 0,
+            "Tile animation has zero length"
+        );
+        assert!(
+            anim.frames.iter().all(|f| self.contains(TileID(*f))),
+            "Tile animation refers to nonexistent tiles"
+        );
+        self.animations.insert(TileID(id), anim);
+    }
+
+    /// Get the frame rect for a tile ID at frame now
+    fn get_rect(&self, id: TileID, now: usize) -> Rect {
+        let idx = match self.animations.get(&id) {
+            Some(anim) => anim.frame_at(now),
+            None => id.0,
+        };
         let (w, _h) = self.texture.size();
         let tw = w / TILE_SZ;
         let row = idx / tw;
@@ -65,12 +118,122 @@ impl Tileset {
         }
     }
 
+    /// Indices of the tiles covering region of the tileset's image, row by row
+    pub fn tiles_in(&self, region: Rect) -> Vec<usize> {
+        let tw = self.texture.size().0 / TILE_SZ;
+        let (x0, y0) = (region.x as usize / TILE_SZ, region.y as usize / TILE_SZ);
+        let (w, h) = (region.w as usize / TILE_SZ, region.h as usize / TILE_SZ);
+        (y0..y0 + h)
+            .flat_map(|row| (x0..x0 + w).map(move |col| row * tw + col))
+            .collect()
+    }
+
     /// Does this tileset have a title for "id"?
     fn contains(&self, id: TileID) -> bool {
         id.0 < self.tiles.len()
     }
 }
 
+/// Which tile to show for each way a tile can border others of its kind, so
+/// patches of terrain get proper edges and corners. variants is indexed by a
+/// mask of the matching neighbours: NORTH, EAST, SOUTH and WEST added up.
+pub struct AutotileSet {
+    pub variants: [usize; 16],
+}
+
+impl AutotileSet {
+    pub const NORTH: usize = 1;
+    pub const EAST: usize = 2;
+    pub const SOUTH: usize = 4;
+    pub const WEST: usize = 8;
+
+    /// A set made from a 3x3 block of tiles drawing one patch, given row by
+    /// row: its corners, edges and middle. A tile with no neighbours of its
+    /// kind stays alone; one in a line a tile wide takes the middle of the
+    /// patch along that line.
+    pub fn from_patch(alone: usize, patch: &[usize]) -> Self {
+        assert_eq!(patch.len(), 9, "Autotile patch isn't 3x3");
+        // Which third of the patch to take from, given the neighbours on
+        // either side: an edge faces away from the one that's there
+        let third = |before: bool, after: bool| match (before, after) {
+            (false, true) => 0,
+            (true, false) => 2,
+            _ => 1,
+        };
+        let variants = std::array::from_fn(|mask| {
+            if mask == 0 {
+                return alone;
+            }
+            let has = |bit| mask & bit != 0;
+            let col = third(has(Self::WEST), has(Self::EAST));
+            let row = third(has(Self::NORTH), has(Self::SOUTH));
+            patch[row * 3 + col]
+        });
+        Self { variants }
+    }
+
+    /// Is id the base tile, or one of the variants it's been turned into?
+    fn is_kind(&self, base: TileID, id: TileID) -> bool {
+        id == base || self.variants.contains(&id.0)
+    }
+}
+
+/// Ways loading a tilemap from a file can fail
+#[derive(Debug)]
+pub enum TilemapError {
+    Io(std::io::Error),
+    /// A cell that isn't a tile index, at (line, col). Both count from 1, and
+    /// lines count blank ones too, so they match what an editor shows
+    BadCell(usize, usize, String),
+    /// A row whose length doesn't match the first row: (line, expected, found)
+    Ragged(usize, usize, usize),
+    /// A tile index the tileset doesn't have, at (line, col)
+    UnknownTile(usize, usize, usize),
+    Empty,
+}
+
+impl fmt::Display for TilemapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TilemapError::Io(e) => write!(f, "couldn't read tilemap: {}", e),
+            TilemapError::BadCell(line, col, text) => {
+                write!(
+                    f,
+                    "line {} col {}: {:?} is not a tile index",
+                    line, col, text
+                )
+            }
+            TilemapError::Ragged(line, expected, found) => {
+                write!(
+                    f,
+                    "line {} has {} tiles, expected {}",
+                    line, found, expected
+                )
+            }
+            TilemapError::UnknownTile(line, col, id) => {
+                write!(f, "line {} col {}: tileset has no tile {}", line, col, id)
+            }
+            TilemapError::Empty => write!(f, "tilemap has no tiles"),
+        }
+    }
+}
+
+impl From<std::io::Error> for TilemapError {
+    fn from(e: std::io::Error) -> Self {
+        TilemapError::Io(e)
+    }
+}
+
+/// One grid of tiles in a Tilemap, drawn over the layers before it
+#[derive(Clone)]
+pub struct TileLayer {
+    pub name: String,
+    /// A row-major grid of tile IDs in the tileset
+    map: Vec<TileID>,
+    /// Cells holding this tile aren't drawn, so the layers below show through
+    empty: Option<TileID>,
+}
+
 #[derive(Clone)]
 pub struct Tilemap {
     /// Where the tilemap is in space
@@ -79,51 +242,43 @@ pub struct Tilemap {
     dims: (usize, usize),
     /// Which tileset is used for this tilemap
     pub tileset: Rc<Tileset>,
-    /// A row-major grid of tile IDs in tileset
-    map: Vec<TileID>,
+    /// Drawn in order, bottom first; all the same size
+    layers: Vec<TileLayer>,
+    /// The layer whose tiles are looked up, changed and collided with
+    collision_layer: usize,
 }
 
 impl Tilemap {
     /// Draws the portion of self appearing within screen.
     /// This could just as well be an extension trait on Screen defined in =tiles.rs= or
     /// something, like we did for =sprite.rs= and =draw_sprite=.
-    pub fn draw(&self, screen: &mut Screen) {
-        let Rect {
-            x: sx,
-            y: sy,
-            w: sw,
-            h: sh,
-        } = screen.bounds();
+    /// Everything here is in world coordinates: screen.bounds() is where the
+    /// camera is, and bitblt takes the scroll back off, so a tile cut by the
+    /// top or bottom edge is drawn clipped rather than skipped or shifted.
+    pub fn draw(&self, screen: &mut Screen, now: usize) {
+        let view = screen.bounds();
+        // Nothing to do for maps that are entirely off screen, which most stacked maps are
+        if !self.is_visible(Vec2i(view.x, view.y), Vec2i(view.w as i32, view.h as i32)) {
+            return;
+        }
         // We'll draw from the topmost/leftmost visible tile to the bottommost/rightmost visible tile.
-        // The camera combined with out position and size tell us what's visible.
-        // leftmost tile: get camera.x into our frame of reference, then divide down to tile units
-        // Note that it's also forced inside of 0..self.size.0
-        let left = ((sx - self.position.0) / TILE_SZ as i32)
-            .max(0)
-            .min(self.dims.0 as i32) as usize;
-        // rightmost tile: same deal, but with screen.x + screen.w plus a little padding to be sure we draw the rightmost tile even if it's a bit off screen.
-        let right = ((sx + ((sw + TILE_SZ as u16) as i32) - self.position.0) / TILE_SZ as i32)
-            .max(0)
-            .min(self.dims.0 as i32) as usize;
-        // ditto top and bot
-        let top = ((sy - self.position.1) / TILE_SZ as i32)
-            .max(0)
-            .min(self.dims.1 as i32) as usize;
-        let bot = ((sy + ((sh + TILE_SZ as u16) as i32) - self.position.1) / TILE_SZ as i32)
-            .max(0)
-            .min(self.dims.1 as i32) as usize;
-        // Now draw the tiles we need to draw where we need to draw them.
+        let (cols, rows) = self.tile_ranges(view);
+        // Now draw the tiles we need to draw where we need to draw them, a layer at a time.
         // Note that we're zipping up the row index (y) with a slice of the map grid containing the necessary rows so we can avoid making a bounds check for each tile.
-        for (y, row) in (top..bot)
-            .zip(self.map[(top * self.dims.0)..(bot * self.dims.0)].chunks_exact(self.dims.0))
-        {
-            // We are in tile coordinates at this point so we'll need to translate back to pixel units and world coordinates to draw.
-            let ypx = (y * TILE_SZ) as i32 + self.position.1;
-            // Here we can iterate through the column index and the relevant slice of the row in parallel
-            for (x, id) in (left..right).zip(row[left..right].iter()) {
-                let xpx = (x * TILE_SZ) as i32 + self.position.0;
-                let frame = self.tileset.get_rect(*id);
-                screen.bitblt(&self.tileset.texture, frame, Vec2i(xpx, ypx));
+        for layer in self.layers.iter() {
+            let grid = &layer.map[(rows.start * self.dims.0)..(rows.end * self.dims.0)];
+            for (y, row) in rows.clone().zip(grid.chunks_exact(self.dims.0)) {
+                // We are in tile coordinates at this point so we'll need to translate back to pixel units and world coordinates to draw.
+                let ypx = (y * TILE_SZ) as i32 + self.position.1;
+                // Here we can iterate through the column index and the relevant slice of the row in parallel
+                for (x, id) in cols.clone().zip(row[cols.clone()].iter()) {
+                    if Some(*id) == layer.empty {
+                        continue;
+                    }
+                    let xpx = (x * TILE_SZ) as i32 + self.position.0;
+                    let frame = self.tileset.get_rect(*id, now);
+                    screen.bitblt(&self.tileset.texture, frame, Vec2i(xpx, ypx));
+                }
             }
         }
     }
@@ -144,49 +299,566 @@ impl Tilemap {
             position,
             dims,
             tileset: Rc::clone(tileset),
-            map: map.into_iter().map(TileID).collect(),
+            layers: vec![TileLayer {
+                name: String::from("ground"),
+                map: map.into_iter().map(TileID).collect(),
+                empty: None,
+            }],
+            collision_layer: 0,
         }
     }
 
-    #[allow(dead_code)]
-    pub fn tile_id_at(&self, Vec2i(x, y): Vec2i) -> TileID {
-        // Translate into map coordinates
-        let x = (x - self.position.0) / TILE_SZ as i32;
-        let y = (y - self.position.1) / TILE_SZ as i32;
-
-        assert!(
-            x >= 0 && x < self.dims.0 as i32,
-            "Tile X coordinate {} out of bounds {}",
-            x,
-            self.dims.0
+    /// Puts another layer on top of the existing ones; cells holding tile
+    /// empty (if given) let the layers below show through.
+    /// Returns the new layer's index.
+    pub fn add_layer(&mut self, name: &str, map: Vec<usize>, empty: Option<usize>) -> usize {
+        assert_eq!(
+            self.dims.0 * self.dims.1,
+            map.len(),
+            "Layer is the wrong size"
         );
         assert!(
-            y >= 0 && y < self.dims.1 as i32,
-            "Tile Y coordinate {} out of bounds {}",
-            y,
-            self.dims.1
+            map.iter().all(|tid| self.tileset.contains(TileID(*tid))),
+            "Layer refers to nonexistent tiles"
         );
-        self.map[y as usize * self.dims.0 + x as usize]
+        self.layers.push(TileLayer {
+            name: name.to_string(),
+            map: map.into_iter().map(TileID).collect(),
+            empty: empty.map(TileID),
+        });
+        self.layers.len() - 1
+    }
+
+    #[cfg(test)]
+    pub fn layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+
+    /// Makes the layer called name the one that's collided with and edited.
+    /// Fails if there's no such layer.
+    pub fn set_collision_layer(&mut self, name: &str) -> Result<(), ()> {
+        let index = self.layers.iter().position(|l| l.name == name).ok_or(())?;
+        self.collision_layer = index;
+        Ok(())
+    }
+
+    // The grid everything but draw works on
+    fn map(&self) -> &[TileID] {
+        &self.layers[self.collision_layer].map
+    }
+
+    fn map_mut(&mut self) -> &mut [TileID] {
+        &mut self.layers[self.collision_layer].map
+    }
+
+    /// Loads a grid of comma-separated tile indices, one row per line.
+    /// Dimensions come from the grid itself.
+    pub fn from_csv(
+        path: &Path,
+        position: Vec2i,
+        tileset: &Rc<Tileset>,
+    ) -> Result<Self, TilemapError> {
+        Self::from_csv_str(&fs::read_to_string(path)?, position, tileset)
+    }
+
+    pub fn from_csv_str(
+        csv: &str,
+        position: Vec2i,
+        tileset: &Rc<Tileset>,
+    ) -> Result<Self, TilemapError> {
+        let mut map = vec![];
+        let mut width = 0;
+        let mut height = 0;
+        // Number rows before skipping blank lines, so errors point at the line in the file
+        for (row, line) in csv
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty())
+        {
+            let mut count = 0;
+            for (col, cell) in line.split(',').enumerate() {
+                let cell = cell.trim();
+                let id: usize = cell
+                    .parse()
+                    .map_err(|_| TilemapError::BadCell(row + 1, col + 1, cell.to_string()))?;
+                if !tileset.contains(TileID(id)) {
+                    return Err(TilemapError::UnknownTile(row + 1, col + 1, id));
+                }
+                map.push(id);
+                count += 1;
+            }
+            if height == 0 {
+                width = count;
+            } else if count != width {
+                return Err(TilemapError::Ragged(row + 1, width, count));
+            }
+            height += 1;
+        }
+        if map.is_empty() {
+            return Err(TilemapError::Empty);
+        }
+
+        Ok(Self::new(position, (width, height), tileset, map))
+    }
+
+    /// A layer as from_csv_str reads it, one row of tile indices per line
+    pub fn layer_to_csv(&self, layer: usize) -> String {
+        self.layers[layer]
+            .map
+            .chunks_exact(self.dims.0)
+            .map(|row| {
+                row.iter()
+                    .map(|id| id.0.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 
     #[allow(dead_code)]
+    pub fn tile_id_at(&self, posn: Vec2i) -> TileID {
+        self.try_tile_id_at(posn).unwrap_or_else(|| {
+            panic!(
+                "Tile coordinate {:?} out of bounds of map at {:?} with size {:?}",
+                posn, self.position, self.dims
+            )
+        })
+    }
+
+    /// Like tile_id_at, but None if posn isn't on the map
+    pub fn try_tile_id_at(&self, Vec2i(x, y): Vec2i) -> Option<TileID> {
+        // Translate into map coordinates
+        let x = (x - self.position.0).div_euclid(TILE_SZ as i32);
+        let y = (y - self.position.1).div_euclid(TILE_SZ as i32);
+
+        if x < 0 || x >= self.dims.0 as i32 || y < 0 || y >= self.dims.1 as i32 {
+            return None;
+        }
+        Some(self.map()[y as usize * self.dims.0 + x as usize])
+    }
+
+    /// The tile index at world position posn, None if posn isn't on the map
+    pub fn try_tile_idx_at(&self, posn: Vec2i) -> Option<usize> {
+        self.try_tile_id_at(posn).map(|id| id.0)
+    }
+
+    /// World positions and tile indices of the collision layer's cells that
+    /// aren't empty
+    pub fn placed_tiles(&self) -> impl Iterator<Item = (Vec2i, usize)> + '_ {
+        let layer = &self.layers[self.collision_layer];
+        layer
+            .map
+            .iter()
+            .enumerate()
+            .filter(move |(_, id)| Some(**id) != layer.empty)
+            .map(move |(i, id)| {
+                let x = (i % self.dims.0 * TILE_SZ) as i32 + self.position.0;
+                let y = (i / self.dims.0 * TILE_SZ) as i32 + self.position.1;
+                (Vec2i(x, y), id.0)
+            })
+    }
+
     pub fn size(&self) -> (usize, usize) {
         self.dims
     }
 
+    // The part of the world the map covers
+    pub fn bounds(&self) -> Rect {
+        Rect {
+            x: self.position.0,
+            y: self.position.1,
+            w: (self.dims.0 * TILE_SZ) as u16,
+            h: (self.dims.1 * TILE_SZ) as u16,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn tile_at(&self, posn: Vec2i) -> Tile {
         self.tileset[self.tile_id_at(posn)]
     }
 
+    /// Like tile_at, but None if posn isn't on the map
+    #[cfg(test)]
+    pub fn try_tile_at(&self, posn: Vec2i) -> Option<Tile> {
+        self.try_tile_id_at(posn).map(|id| self.tileset[id])
+    }
+
+    /// Change the tile at world position posn to tile index id.
+    /// Fails if posn isn't on the map or id isn't in the tileset.
+    pub fn set_tile(&mut self, Vec2i(x, y): Vec2i, id: usize) -> Result<(), ()> {
+        let x = (x - self.position.0).div_euclid(TILE_SZ as i32);
+        let y = (y - self.position.1).div_euclid(TILE_SZ as i32);
+        if x < 0 || y < 0 {
+            return Err(());
+        }
+        self.set_tile_idx(x as usize, y as usize, id)
+    }
+
+    /// Change the tile in column x, row y of the collision layer to tile index id.
+    pub fn set_tile_idx(&mut self, x: usize, y: usize, id: usize) -> Result<(), ()> {
+        if x >= self.dims.0 || y >= self.dims.1 || !self.tileset.contains(TileID(id)) {
+            return Err(());
+        }
+        let width = self.dims.0;
+        self.map_mut()[y * width + x] = TileID(id);
+        Ok(())
+    }
+
+    /// Replace every tile of base's kind on the collision layer (base itself or
+    /// any of set's variants) with the variant for which of its four
+    /// neighbours are the same kind. Neighbours off the map don't count.
+    pub fn autotile(&mut self, base: usize, set: &AutotileSet) {
+        assert!(
+            set.variants
+                .iter()
+                .all(|tid| self.tileset.contains(TileID(*tid))),
+            "Autotile set refers to nonexistent tiles"
+        );
+        let (w, h) = (self.dims.0 as i32, self.dims.1 as i32);
+        let base = TileID(base);
+        // Masks come from the map as it was, not as it's being rewritten
+        let before = self.map().to_vec();
+        let is_kind = |x: i32, y: i32| {
+            x >= 0 && y >= 0 && x < w && y < h && set.is_kind(base, before[(y * w + x) as usize])
+        };
+        for y in 0..h {
+            for x in 0..w {
+                if !is_kind(x, y) {
+                    continue;
+                }
+                let mut mask = 0;
+                for (dx, dy, bit) in [
+                    (0, -1, AutotileSet::NORTH),
+                    (1, 0, AutotileSet::EAST),
+                    (0, 1, AutotileSet::SOUTH),
+                    (-1, 0, AutotileSet::WEST),
+                ] {
+                    if is_kind(x + dx, y + dy) {
+                        mask |= bit;
+                    }
+                }
+                self.map_mut()[(y * w + x) as usize] = TileID(set.variants[mask]);
+            }
+        }
+    }
+
+    /// World-space rects of all solid tiles on the collision layer overlapping region.
+    pub fn solid_rects_in(&self, region: Rect) -> Vec<Rect> {
+        // Same tiles as draw would draw for a screen covering region
+        let (cols, rows) = self.tile_ranges(region);
+
+        let mut rects = vec![];
+        for y in rows {
+            for x in cols.clone() {
+                if self.tileset[self.map()[y * self.dims.0 + x]].solid {
+                    rects.push(Rect {
+                        x: (x * TILE_SZ) as i32 + self.position.0,
+                        y: (y * TILE_SZ) as i32 + self.position.1,
+                        w: TILE_SZ as u16,
+                        h: TILE_SZ as u16,
+                    });
+                }
+            }
+        }
+        rects
+    }
+
+    /// The columns and rows of tiles that overlap region, even partly. The
+    /// camera combined with our position and size tell us what's visible:
+    /// region's edges are brought into our frame of reference and divided
+    /// down to tile units, the far ones rounding up so a tile only partly in
+    /// region counts and one just past its edge doesn't. Both ranges are
+    /// forced inside the map, so they're empty if region misses it.
+    fn tile_ranges(&self, region: Rect) -> (Range<usize>, Range<usize>) {
+        let tile = TILE_SZ as i32;
+        let clamp = |t: i32, max: usize| t.max(0).min(max as i32) as usize;
+        let left = clamp((region.x - self.position.0).div_euclid(tile), self.dims.0);
+        let right = clamp(
+            (region.x + region.w as i32 - self.position.0 + tile - 1).div_euclid(tile),
+            self.dims.0,
+        );
+        let top = clamp((region.y - self.position.1).div_euclid(tile), self.dims.1);
+        let bot = clamp(
+            (region.y + region.h as i32 - self.position.1 + tile - 1).div_euclid(tile),
+            self.dims.1,
+        );
+        (left..right, top..bot)
+    }
+
+    /// Does any of the map overlap the screen at screen_pos, screen_dim big?
+    /// Exactly when draw has some tile to draw.
     pub fn is_visible(&self, screen_pos: Vec2i, screen_dim: Vec2i) -> bool {
         let dims_px = Vec2i(
             (self.dims.0 * TILE_SZ) as i32,
             (self.dims.1 * TILE_SZ) as i32,
         );
-        !((self.position.0 + dims_px.0 as i32) < screen_pos.0
-            || self.position.0 > screen_pos.0 + screen_dim.0
-            || (self.position.1 + dims_px.1 as i32) < screen_pos.1
-            || self.position.1 > screen_pos.1 + screen_dim.1)
+        self.position.0 + dims_px.0 > screen_pos.0
+            && self.position.0 < screen_pos.0 + screen_dim.0
+            && self.position.1 + dims_px.1 > screen_pos.1
+            && self.position.1 < screen_pos.1 + screen_dim.1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Rgba;
+
+    // Eight tiles off a blank 64x32 sheet, four to a row; tile 1 is solid
+    fn tileset() -> Tileset {
+        let texture = Rc::new(Texture::new(image::RgbaImage::new(64, 32)));
+        let mut tiles = vec![Tile { solid: false }; 8];
+        tiles[1].solid = true;
+        Tileset::new(tiles, &texture, HashMap::new())
+    }
+
+    // The colour every pixel of tile idx is on painted_tileset's sheet
+    fn tile_color(idx: usize) -> Rgba {
+        Rgba(30 * (idx as u8 + 1), 0, 0, 255)
+    }
+
+    // Like tileset, but each tile is a solid block of its tile_color
+    fn painted_tileset() -> Tileset {
+        let sheet = image::RgbaImage::from_fn(64, 32, |x, y| {
+            let Rgba(r, g, b, a) = tile_color((y / 16 * 4 + x / 16) as usize);
+            image::Rgba([r, g, b, a])
+        });
+        let mut tiles = vec![Tile { solid: false }; 8];
+        tiles[1].solid = true;
+        Tileset::new(tiles, &Rc::new(Texture::new(sheet)), HashMap::new())
+    }
+
+    // What drawing map leaves on a blank w x h screen scrolled to scroll,
+    // and a way to read its pixels back by screen coordinates
+    fn render(map: &Tilemap, w: usize, h: usize, scroll: Vec2i) -> impl Fn(usize, usize) -> Rgba {
+        let mut fb = vec![0; w * h * 4];
+        map.draw(&mut Screen::wrap(&mut fb, w, h, 4, scroll), 0);
+        move |x, y| {
+            let i = (y * w + x) * 4;
+            Rgba(fb[i], fb[i + 1], fb[i + 2], fb[i + 3])
+        }
+    }
+
+    // Where tile idx is on the sheet
+    fn sheet_rect(idx: i32) -> Rect {
+        Rect {
+            x: idx % 4 * 16,
+            y: idx / 4 * 16,
+            w: 16,
+            h: 16,
+        }
+    }
+
+    #[test]
+    fn animated_tile_steps_through_its_frames() {
+        let mut tileset = tileset();
+        tileset.animate(
+            0,
+            AnimatedTile {
+                frames: vec![1, 2, 5],
+                frame_times: vec![5, 5, 5],
+                loops: true,
+            },
+        );
+        let at = |now| tileset.get_rect(TileID(0), now);
+        assert_eq!(at(0), sheet_rect(1));
+        assert_eq!(at(4), sheet_rect(1));
+        assert_eq!(at(5), sheet_rect(2));
+        assert_eq!(at(10), sheet_rect(5));
+        assert_eq!(at(15), sheet_rect(1));
+        // Tiles without an animation stay put
+        assert_eq!(tileset.get_rect(TileID(6), 10), sheet_rect(6));
+    }
+
+    #[test]
+    fn csv_grid_loads_with_its_own_dimensions() {
+        let path = std::env::temp_dir().join("tiles_csv_grid_loads.csv");
+        fs::write(&path, "0,1,2\n3, 4 ,5\n").unwrap();
+        let tilemap = Tilemap::from_csv(&path, Vec2i(0, 0), &Rc::new(tileset())).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(tilemap.size(), (3, 2));
+        assert_eq!(tilemap.layer_to_csv(0), "0,1,2\n3,4,5");
+    }
+
+    #[test]
+    fn ragged_csv_is_rejected() {
+        let result = Tilemap::from_csv_str("0,1,2\n3,4\n", Vec2i(0, 0), &Rc::new(tileset()));
+        assert!(matches!(result, Err(TilemapError::Ragged(2, 3, 2))));
+    }
+
+    #[test]
+    fn csv_errors_count_the_blank_lines() {
+        let tileset = Rc::new(tileset());
+        let result = Tilemap::from_csv_str("\n0,1,2\n\n3,4\n", Vec2i(0, 0), &tileset);
+        assert!(matches!(result, Err(TilemapError::Ragged(4, 3, 2))));
+        let message = result.err().unwrap().to_string();
+        assert_eq!(message, "line 4 has 2 tiles, expected 3");
+        let result = Tilemap::from_csv_str("0,1\n\n2,x\n", Vec2i(0, 0), &tileset);
+        assert!(matches!(result, Err(TilemapError::BadCell(3, 2, _))));
+        let tilemap = Tilemap::from_csv_str("\n0,1\n\n2,3\n", Vec2i(0, 0), &tileset).unwrap();
+        assert_eq!(tilemap.size(), (2, 2));
+    }
+
+    #[test]
+    fn tile_lookups_are_none_off_the_map() {
+        let tilemap = Tilemap::new(Vec2i(-16, 32), (2, 1), &Rc::new(tileset()), vec![0, 1]);
+        assert!(!tilemap.try_tile_at(Vec2i(-16, 32)).unwrap().solid);
+        assert!(tilemap.try_tile_at(Vec2i(15, 47)).unwrap().solid);
+        assert_eq!(tilemap.try_tile_idx_at(Vec2i(0, 40)), Some(1));
+        for outside in [Vec2i(-17, 32), Vec2i(16, 32), Vec2i(0, 31), Vec2i(0, 48)] {
+            assert!(tilemap.try_tile_at(outside).is_none(), "{:?}", outside);
+        }
+    }
+
+    #[test]
+    fn set_tile_is_read_back() {
+        let mut tilemap = Tilemap::new(Vec2i(0, 0), (2, 2), &Rc::new(tileset()), vec![0; 4]);
+        assert_eq!(tilemap.set_tile(Vec2i(20, 3), 1), Ok(()));
+        assert!(tilemap.tile_at(Vec2i(16, 0)).solid);
+        assert!(tilemap.tile_id_at(Vec2i(31, 15)) == TileID(1));
+        assert_eq!(tilemap.set_tile_idx(0, 1, 7), Ok(()));
+        assert_eq!(tilemap.try_tile_idx_at(Vec2i(0, 16)), Some(7));
+        // Off the map, or not in the tileset
+        assert_eq!(tilemap.set_tile(Vec2i(-1, 0), 1), Err(()));
+        assert_eq!(tilemap.set_tile_idx(2, 0, 1), Err(()));
+        assert_eq!(tilemap.set_tile_idx(0, 0, 8), Err(()));
+        assert_eq!(tilemap.layer_to_csv(0), "0,1\n7,0");
+    }
+
+    #[test]
+    fn layers_draw_in_order_and_only_one_collides() {
+        let tileset = Rc::new(painted_tileset());
+        let mut map = Tilemap::new(Vec2i(0, 0), (2, 2), &tileset, vec![2; 4]);
+        // Clouds over the top left, with 0 as the gaps
+        map.add_layer("clouds", vec![3, 0, 0, 0], Some(0));
+        // Solid rock bottom right, which nothing draws on top of
+        let walls = map.add_layer("walls", vec![0, 0, 0, 1], Some(0));
+        assert_eq!(walls, 2);
+        assert_eq!(map.layers().len(), 3);
+
+        let pixel = render(&map, 32, 32, Vec2i(0, 0));
+        assert_eq!(pixel(4, 4), tile_color(3));
+        assert_eq!(pixel(20, 4), tile_color(2));
+        assert_eq!(pixel(4, 20), tile_color(2));
+        assert_eq!(pixel(20, 20), tile_color(1));
+
+        // The ground has nothing solid, so until walls collides there's nothing to hit
+        let everywhere = map.bounds();
+        assert!(map.solid_rects_in(everywhere).is_empty());
+        assert_eq!(map.try_tile_idx_at(Vec2i(20, 20)), Some(2));
+        assert_eq!(map.set_collision_layer("walls"), Ok(()));
+        assert_eq!(
+            map.solid_rects_in(everywhere),
+            vec![Rect {
+                x: 16,
+                y: 16,
+                w: 16,
+                h: 16
+            }]
+        );
+        assert_eq!(map.try_tile_idx_at(Vec2i(20, 20)), Some(1));
+        assert_eq!(map.set_collision_layer("sky"), Err(()));
+    }
+
+    #[test]
+    fn autotile_picks_variants_by_neighbours() {
+        // A 3x3 patch of tile 1 in a 4x4 map, the rest tile 0
+        let tileset = Rc::new(Tileset::new(
+            vec![Tile { solid: false }; 32],
+            &Rc::new(Texture::new(image::RgbaImage::new(64, 128))),
+            HashMap::new(),
+        ));
+        #[rustfmt::skip]
+        let map = vec![
+            1, 1, 1, 0,
+            1, 1, 1, 0,
+            1, 1, 1, 0,
+            0, 0, 0, 0,
+        ];
+        let mut map = Tilemap::new(Vec2i(0, 0), (4, 4), &tileset, map);
+        // Each mask gets its own variant, 16 + mask
+        let set = AutotileSet {
+            variants: std::array::from_fn(|mask| 16 + mask),
+        };
+        map.autotile(1, &set);
+        let at = |x: usize, y: usize| map.tile_id_at(Vec2i(x as i32 * 16, y as i32 * 16)).0;
+        let n = AutotileSet::NORTH;
+        let e = AutotileSet::EAST;
+        let s = AutotileSet::SOUTH;
+        let w = AutotileSet::WEST;
+        assert_eq!(at(1, 1), 16 + (n | e | s | w));
+        assert_eq!(at(1, 0), 16 + (e | s | w));
+        assert_eq!(at(0, 0), 16 + (e | s));
+        assert_eq!(at(2, 2), 16 + (n | w));
+        assert_eq!(at(3, 3), 0);
+
+        // Running it again sees the variants as the same kind, so nothing changes
+        let before = map.layer_to_csv(0);
+        map.autotile(1, &set);
+        assert_eq!(map.layer_to_csv(0), before);
+    }
+
+    #[test]
+    fn patch_sets_take_edges_and_corners_from_a_3x3_block() {
+        let set = AutotileSet::from_patch(1, &[10, 11, 12, 13, 14, 15, 16, 17, 18]);
+        let n = AutotileSet::NORTH;
+        let e = AutotileSet::EAST;
+        let s = AutotileSet::SOUTH;
+        let w = AutotileSet::WEST;
+        assert_eq!(set.variants[0], 1);
+        assert_eq!(set.variants[e | s], 10);
+        assert_eq!(set.variants[e | s | w], 11);
+        assert_eq!(set.variants[n | e | s | w], 14);
+        assert_eq!(set.variants[n | w], 18);
+        // A row a tile high runs along the patch's middle row
+        assert_eq!(set.variants[e], 13);
+        assert_eq!(set.variants[e | w], 14);
+
+        // Blocks are read off the sheet row by row
+        let block = Rect {
+            x: 16,
+            y: 0,
+            w: 32,
+            h: 32,
+        };
+        assert_eq!(tileset().tiles_in(block), vec![1, 2, 5, 6]);
+    }
+
+    #[test]
+    fn draw_only_covers_tiles_in_view() {
+        let map = Tilemap::new(Vec2i(0, -160), (20, 20), &Rc::new(tileset()), vec![0; 400]);
+        let view = |x, y| Rect { x, y, w: 32, h: 16 };
+        // Lined up with the tiles, exactly two across and one down
+        assert_eq!(map.tile_ranges(view(16, -128)), (1..3, 2..3));
+        // Off by a pixel, the tiles it cuts into at either end count too
+        assert_eq!(map.tile_ranges(view(17, -127)), (1..4, 2..4));
+        // Views hanging off the map only get what's on it, and nothing at all past it
+        assert_eq!(map.tile_ranges(view(-8, -170)), (0..2, 0..1));
+        assert_eq!(map.tile_ranges(view(0, 160)), (0..2, 20..20));
+        assert!(!map.is_visible(Vec2i(0, 160), Vec2i(32, 16)));
+        assert!(map.is_visible(Vec2i(0, 159), Vec2i(32, 16)));
+    }
+
+    #[test]
+    fn scrolled_tiles_land_on_the_right_pixels() {
+        // A column of tiles 0 to 7 going down, with the camera scrolled up the
+        // world to cut into the top and bottom ones
+        let map = Tilemap::new(
+            Vec2i(0, -128),
+            (1, 8),
+            &Rc::new(painted_tileset()),
+            (0..8).collect(),
+        );
+        let pixel = render(&map, 16, 40, Vec2i(0, -100));
+        // World y -100 is 12px into tile 1, so it's showing for the first 4 rows
+        assert_eq!(pixel(0, 0), tile_color(1));
+        assert_eq!(pixel(15, 3), tile_color(1));
+        assert_eq!(pixel(0, 4), tile_color(2));
+        assert_eq!(pixel(8, 20), tile_color(3));
+        // The last 4 rows are the top of tile 4, at world y -64
+        assert_eq!(pixel(0, 35), tile_color(3));
+        assert_eq!(pixel(0, 36), tile_color(4));
+        assert_eq!(pixel(15, 39), tile_color(4));
     }
 }
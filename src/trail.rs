@@ -0,0 +1,45 @@
+use crate::types::Vec2i;
+
+/*
+Where something has been over the last few frames, for drawing a fading trail
+behind it. Points go in a fixed ring buffer, like FrameTimer's samples, so a
+trail never grows past TRAIL_LEN however long it's kept.
+*/
+
+pub const TRAIL_LEN: usize = 6;
+
+#[derive(Clone)]
+pub struct Trail {
+    // Only the first filled are real
+    points: [Vec2i; TRAIL_LEN],
+    // Where the next point goes, overwriting the oldest once full
+    next: usize,
+    filled: usize,
+}
+
+impl Default for Trail {
+    fn default() -> Self {
+        Self {
+            points: [Vec2i(0, 0); TRAIL_LEN],
+            next: 0,
+            filled: 0,
+        }
+    }
+}
+
+impl Trail {
+    pub fn push(&mut self, point: Vec2i) {
+        self.points[self.next] = point;
+        self.next = (self.next + 1) % TRAIL_LEN;
+        self.filled = (self.filled + 1).min(TRAIL_LEN);
+    }
+
+    pub fn len(&self) -> usize {
+        self.filled
+    }
+
+    // The points recorded, newest first
+    pub fn points(&self) -> impl Iterator<Item = Vec2i> + '_ {
+        (1..=self.filled).map(move |back| self.points[(self.next + TRAIL_LEN - back) % TRAIL_LEN])
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::{Add, Mul, Sub};
+
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub struct Rect {
     pub x: i32,
@@ -9,7 +11,225 @@ pub struct Rect {
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub struct Vec2i(pub i32, pub i32);
 
+// For velocities and anything else that needs sub-pixel precision
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Vec2f(pub f32, pub f32);
+
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub struct Rgba(pub u8, pub u8, pub u8, pub u8);
 
 // Feel free to add impl blocks with convenience functions
+
+impl Add for Vec2i {
+    type Output = Vec2i;
+    fn add(self, other: Vec2i) -> Vec2i {
+        Vec2i(self.0 + other.0, self.1 + other.1)
+    }
+}
+
+impl Sub for Vec2i {
+    type Output = Vec2i;
+    fn sub(self, other: Vec2i) -> Vec2i {
+        Vec2i(self.0 - other.0, self.1 - other.1)
+    }
+}
+
+impl Add for Vec2f {
+    type Output = Vec2f;
+    fn add(self, other: Vec2f) -> Vec2f {
+        Vec2f(self.0 + other.0, self.1 + other.1)
+    }
+}
+
+impl Sub for Vec2f {
+    type Output = Vec2f;
+    fn sub(self, other: Vec2f) -> Vec2f {
+        Vec2f(self.0 - other.0, self.1 - other.1)
+    }
+}
+
+impl Mul<f32> for Vec2f {
+    type Output = Vec2f;
+    fn mul(self, k: f32) -> Vec2f {
+        Vec2f(self.0 * k, self.1 * k)
+    }
+}
+
+impl From<Vec2i> for Vec2f {
+    fn from(v: Vec2i) -> Self {
+        Vec2f(v.0 as f32, v.1 as f32)
+    }
+}
+
+impl Rgba {
+    pub const BLACK: Rgba = Rgba(0, 0, 0, 255);
+    pub const WHITE: Rgba = Rgba(255, 255, 255, 255);
+
+    // An opaque color from 0xRRGGBB
+    pub const fn from_hex(hex: u32) -> Rgba {
+        Rgba((hex >> 16) as u8, (hex >> 8) as u8, hex as u8, 255)
+    }
+
+    pub const fn with_alpha(self, a: u8) -> Rgba {
+        Rgba(self.0, self.1, self.2, a)
+    }
+
+    // Linear interpolation, channel by channel: t = 0.0 is self, t = 1.0 is other
+    pub fn blend(self, other: Rgba, t: f32) -> Rgba {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Rgba(
+            mix(self.0, other.0),
+            mix(self.1, other.1),
+            mix(self.2, other.2),
+            mix(self.3, other.3),
+        )
+    }
+}
+
+impl Vec2f {
+    pub fn length(self) -> f32 {
+        (self.0 * self.0 + self.1 * self.1).sqrt()
+    }
+
+    // Same direction with length 1, except the zero vector stays zero
+    pub fn normalized(self) -> Vec2f {
+        let len = self.length();
+        if len == 0.0 {
+            self
+        } else {
+            self * (1.0 / len)
+        }
+    }
+
+    // Drops the fractional parts, like `as i32` (so towards zero)
+    pub fn truncate(self) -> Vec2i {
+        Vec2i(self.0 as i32, self.1 as i32)
+    }
+
+    // Nearest whole pixel
+    pub fn round(self) -> Vec2i {
+        Vec2i(self.0.round() as i32, self.1.round() as i32)
+    }
+}
+
+impl Rect {
+    // A w x h rect with center at (or just below/right of, for even sizes) center
+    pub fn from_center(center: Vec2i, w: u16, h: u16) -> Rect {
+        Rect {
+            x: center.0 - w as i32 / 2,
+            y: center.1 - h as i32 / 2,
+            w,
+            h,
+        }
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.w as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.h as i32
+    }
+
+    pub fn center(&self) -> Vec2i {
+        Vec2i(self.x + self.w as i32 / 2, self.y + self.h as i32 / 2)
+    }
+
+    // The right and bottom edges are just outside the rect
+    pub fn contains(&self, p: Vec2i) -> bool {
+        self.x <= p.0 && p.0 < self.right() && self.y <= p.1 && p.1 < self.bottom()
+    }
+
+    // Rects that only share an edge don't count as intersecting
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+
+    // Like intersects, but rects that only share an edge or corner count too
+    pub fn touches_or_overlaps(&self, other: &Rect) -> bool {
+        self.x <= other.right()
+            && other.x <= self.right()
+            && self.y <= other.bottom()
+            && other.y <= self.bottom()
+    }
+
+    pub fn translated(&self, dx: i32, dy: i32) -> Rect {
+        Rect {
+            x: self.x + dx,
+            y: self.y + dy,
+            ..*self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vector_arithmetic_and_conversions() {
+        assert_eq!(Vec2i(3, -4) + Vec2i(1, 1), Vec2i(4, -3));
+        assert_eq!(Vec2i(3, -4) - Vec2i(1, 1), Vec2i(2, -5));
+        assert_eq!(Vec2f(1.5, -2.0) + Vec2f(0.5, 0.5), Vec2f(2.0, -1.5));
+        assert_eq!(Vec2f(1.5, -2.0) - Vec2f(0.5, 0.5), Vec2f(1.0, -2.5));
+        assert_eq!(Vec2f(1.5, -2.0) * 2.0, Vec2f(3.0, -4.0));
+        assert_eq!(Vec2f(3.0, 4.0).length(), 5.0);
+        assert_eq!(Vec2f(3.0, 4.0).normalized(), Vec2f(0.6, 0.8));
+        assert_eq!(Vec2f(0.0, 0.0).normalized(), Vec2f(0.0, 0.0));
+
+        // Whole vectors survive the trip through Vec2f either way
+        let v = Vec2i(-7, 12);
+        assert_eq!(Vec2f::from(v).truncate(), v);
+        assert_eq!(Vec2f::from(v).round(), v);
+        // Fractions truncate towards zero, or round to nearest
+        assert_eq!(Vec2f(1.7, -1.7).truncate(), Vec2i(1, -1));
+        assert_eq!(Vec2f(1.7, -1.7).round(), Vec2i(2, -2));
+    }
+
+    #[test]
+    fn rect_geometry() {
+        let r = Rect {
+            x: 10,
+            y: 20,
+            w: 6,
+            h: 4,
+        };
+        assert_eq!(r.center(), Vec2i(13, 22));
+        assert_eq!(Rect::from_center(Vec2i(13, 22), 6, 4), r);
+        assert_eq!(r.translated(-10, 5).center(), Vec2i(3, 27));
+        assert!(r.contains(Vec2i(10, 20)));
+        assert!(r.contains(Vec2i(15, 23)));
+        assert!(!r.contains(Vec2i(16, 23)));
+        assert!(!r.contains(Vec2i(15, 24)));
+
+        assert!(r.intersects(&r.translated(5, 3)));
+        // Touching along an edge, or at a corner, isn't intersecting, but does count as touching
+        for touching in [
+            r.translated(6, 0),
+            r.translated(-6, 0),
+            r.translated(0, 4),
+            r.translated(0, -4),
+            r.translated(6, 4),
+        ] {
+            assert!(!r.intersects(&touching), "{:?}", touching);
+            assert!(!touching.intersects(&r), "{:?}", touching);
+            assert!(r.touches_or_overlaps(&touching), "{:?}", touching);
+        }
+        assert!(!r.touches_or_overlaps(&r.translated(7, 0)));
+    }
+
+    #[test]
+    fn hex_colors_and_blending() {
+        assert_eq!(Rgba::from_hex(0xffc5ff), Rgba(255, 197, 255, 255));
+        assert_eq!(Rgba::from_hex(0x000000), Rgba::BLACK);
+        assert_eq!(Rgba::WHITE.with_alpha(10), Rgba(255, 255, 255, 10));
+        let (from, to) = (Rgba(0, 100, 200, 255), Rgba(100, 200, 0, 55));
+        assert_eq!(from.blend(to, 0.5), Rgba(50, 150, 100, 155));
+        assert_eq!(from.blend(to, 0.0), from);
+        assert_eq!(from.blend(to, 2.0), to);
+    }
+}
@@ -0,0 +1,80 @@
+/*
+The pacing of the rocks and boulders stages, as data. Each stage works through
+its list of waves in order; what a wave spawns, how often, and whether the
+stage moves on afterwards all come from here rather than from update_game.
+
+A wave spawns once, on the first frame that's a multiple of spawn_interval (or
+straight away if that's 0). It is over once there are no enemies left, so
+terrain-only waves end as soon as they've spawned.
+*/
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TerrainType {
+    // Rocks scattered across a few rows
+    RockField,
+    // A row of boulders with gaps of rocks to shoot through
+    BoulderWall,
+}
+
+// What happens once a wave is over
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum StageTransition {
+    // On to the next wave of the stage
+    NextWave,
+    // On to the next stage
+    NextStage,
+    // On to the next stage with chance (n in d), otherwise the next wave
+    Chance(usize, usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wave {
+    pub spawn_interval: usize,
+    // Enemies on Normal; the difficulty scales this
+    pub enemy_count: usize,
+    pub terrain_type: Option<TerrainType>,
+    pub next: StageTransition,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaveTable {
+    pub rocks: Vec<Wave>,
+    pub boulders: Vec<Wave>,
+}
+
+impl Default for WaveTable {
+    /*
+    Rocks: waves of enemies, with a 1 in 4 better chance of moving on after
+    each one from wave 2, and always after wave 5.
+    Boulders: boulder walls closer and closer together, moving on with the same
+    odds from wave 4, and always after wave 7.
+    */
+    fn default() -> Self {
+        let chances = |from_wave: usize, n: usize| {
+            (1..=n).map(move |wave| match wave {
+                _ if wave == n => StageTransition::NextStage,
+                _ if wave < from_wave => StageTransition::NextWave,
+                _ => StageTransition::Chance(wave + 1 - from_wave, 4),
+            })
+        };
+        Self {
+            rocks: chances(2, 5)
+                .map(|next| Wave {
+                    spawn_interval: 30,
+                    enemy_count: 4,
+                    terrain_type: None,
+                    next,
+                })
+                .collect(),
+            boulders: chances(4, 7)
+                .enumerate()
+                .map(|(i, next)| Wave {
+                    spawn_interval: 300 - (i + 1) * 8,
+                    enemy_count: 0,
+                    terrain_type: Some(TerrainType::BoulderWall),
+                    next,
+                })
+                .collect(),
+        }
+    }
+}
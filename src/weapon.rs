@@ -0,0 +1,96 @@
+use crate::axis::ScrollAxis;
+use crate::collision::{Mobile, Projectile};
+use crate::types::Vec2f;
+
+/*
+What the player is currently shooting with. Picked up from weapon power-ups.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Weapon {
+    // One straight shot
+    Single,
+    // This many shots fanned out around straight ahead
+    Spread(u8),
+    // One straight shot, twice as often
+    Rapid,
+    // One shot that steers itself into the nearest enemy
+    Homing,
+}
+
+// sideways speed difference between neighbouring shots of a spread
+const SPREAD_VX: f64 = 2.0;
+
+impl Weapon {
+    // Frames between shots, given the base fire interval
+    pub fn interval(&self, base: usize) -> usize {
+        match self {
+            Weapon::Rapid => (base / 2).max(1),
+            _ => base,
+        }
+    }
+
+    // The projectiles fired in one shot from from, ahead along axis, at frame now
+    pub fn fire(&self, from: &Mobile, axis: ScrollAxis, now: usize) -> Vec<Projectile> {
+        match *self {
+            Weapon::Single | Weapon::Rapid => vec![Projectile::new(from, axis, now)],
+            Weapon::Homing => vec![Projectile::new(from, axis, now).homing()],
+            Weapon::Spread(n) => {
+                let mid = (n as f64 - 1.0) / 2.0;
+                let side = axis.turn_f(Vec2f(1.0, 0.0));
+                (0..n)
+                    .map(|i| {
+                        let proj = Projectile::new(from, axis, now);
+                        let (vx, vy) = proj.get_velocity();
+                        let fan = (i as f64 - mid) * SPREAD_VX;
+                        proj.with_velocity(vx + fan * side.0 as f64, vy + fan * side.1 as f64)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::PLAYER_FIRE_COOLDOWN;
+
+    #[test]
+    fn spread_of_three_fires_three_different_ways() {
+        let player = Mobile::player(100, 100, 100);
+        let shots = Weapon::Spread(3).fire(&player, ScrollAxis::Vertical, 0);
+        let velocities: Vec<(f64, f64)> = shots.iter().map(|s| s.get_velocity()).collect();
+        assert_eq!(velocities.len(), 3);
+        for (i, a) in velocities.iter().enumerate() {
+            for b in &velocities[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        // One straight ahead, the others either side of it
+        let straight = Weapon::Single.fire(&player, ScrollAxis::Vertical, 0)[0].get_velocity();
+        assert_eq!(velocities[1], straight);
+        assert_eq!(velocities[0].0, -velocities[2].0);
+    }
+
+    // How many shots a player holding fire gets off in the first frames frames with weapon
+    fn shots_in(weapon: Weapon, frames: usize) -> usize {
+        let mut player = Mobile::player(100, 100, 100);
+        player.set_fire_cooldown(weapon.interval(PLAYER_FIRE_COOLDOWN));
+        player.last_fired = 0;
+        let mut shots = 0;
+        for now in 1..=frames {
+            if player.ready_to_fire(now) {
+                player.last_fired = now;
+                shots += weapon.fire(&player, ScrollAxis::Vertical, now).len();
+            }
+        }
+        shots
+    }
+
+    #[test]
+    fn rapid_fire_shoots_twice_as_often() {
+        let window = PLAYER_FIRE_COOLDOWN * 10;
+        assert_eq!(shots_in(Weapon::Single, window), 10);
+        assert_eq!(shots_in(Weapon::Rapid, window), 20);
+    }
+}